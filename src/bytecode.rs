@@ -0,0 +1,136 @@
+use crate::{optimizer::Op, position::Position};
+
+/// Instruction of the flat bytecode executed by the interpreter
+/// Same as the optimized operations, but loops are replaced by jumps to precomputed targets
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Code {
+    Add {
+        offset: isize,
+        delta: i32,
+        position: Position,
+    },
+    Move {
+        offset: isize,
+        position: Position,
+    },
+    SetZero,
+    MulAdd {
+        offset: isize,
+        factor: i32,
+    },
+    ScanZero {
+        step: isize,
+        position: Position,
+    },
+    Output {
+        offset: isize,
+    },
+    Input {
+        offset: isize,
+    },
+    Debug {
+        position: Position,
+    },
+    /// Start of a loop, jumps to the instruction after its end if the current cell is zero
+    JumpIfZero(usize),
+    /// End of a loop, jumps to the first instruction of its body if the current cell isn't zero
+    JumpUnlessZero(usize),
+}
+
+/// Compiles the optimized operations into bytecode, with the jump targets of the loops resolved
+/// The operations are consumed as they are compiled, so each loop is dropped as soon as it is compiled
+pub(crate) fn compile(ops: Vec<Op>) -> Vec<Code> {
+    let mut code = Vec::with_capacity(ops.len());
+    let mut blocks = vec![(ops.into_iter(), None)];
+
+    while let Some((ops, _)) = blocks.last_mut() {
+        let instruction = match ops.next() {
+            Some(Op::Add {
+                offset,
+                delta,
+                position,
+            }) => Code::Add {
+                offset,
+                delta,
+                position,
+            },
+            Some(Op::Move { offset, position }) => Code::Move { offset, position },
+            Some(Op::SetZero) => Code::SetZero,
+            Some(Op::MulAdd { offset, factor }) => Code::MulAdd { offset, factor },
+            Some(Op::ScanZero { step, position }) => Code::ScanZero { step, position },
+            Some(Op::Output { offset }) => Code::Output { offset },
+            Some(Op::Input { offset }) => Code::Input { offset },
+            Some(Op::Debug { position }) => Code::Debug { position },
+            Some(Op::Loop(loop_ops)) => {
+                blocks.push((loop_ops.into_iter(), Some(code.len())));
+                Code::JumpIfZero(0)
+            }
+            None => {
+                let (_, loop_start) = blocks.pop().expect("the block is on the stack");
+                let Some(start) = loop_start else {
+                    break;
+                };
+                code.push(Code::JumpUnlessZero(start + 1));
+                code[start] = Code::JumpIfZero(code.len());
+                continue;
+            }
+        };
+        code.push(instruction);
+    }
+
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiler_keeps_operations_without_loops() {
+        assert_eq!(
+            vec![
+                Code::SetZero,
+                Code::Output { offset: 1 },
+                Code::Input { offset: -1 }
+            ],
+            compile(vec![
+                Op::SetZero,
+                Op::Output { offset: 1 },
+                Op::Input { offset: -1 }
+            ])
+        );
+    }
+
+    #[test]
+    fn compiler_resolves_jumps_of_nested_loops() {
+        let ops = vec![
+            Op::Output { offset: 0 },
+            Op::Loop(vec![
+                Op::Loop(vec![Op::Output { offset: 0 }]),
+                Op::Input { offset: 0 },
+            ]),
+            Op::SetZero,
+        ];
+        assert_eq!(
+            vec![
+                Code::Output { offset: 0 },
+                Code::JumpIfZero(7),
+                Code::JumpIfZero(5),
+                Code::Output { offset: 0 },
+                Code::JumpUnlessZero(3),
+                Code::Input { offset: 0 },
+                Code::JumpUnlessZero(2),
+                Code::SetZero,
+            ],
+            compile(ops)
+        );
+    }
+
+    #[test]
+    fn compiler_jumps_over_empty_loops() {
+        assert_eq!(
+            vec![Code::JumpIfZero(2), Code::JumpUnlessZero(1)],
+            compile(vec![Op::Loop(vec![])])
+        );
+    }
+}
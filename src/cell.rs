@@ -0,0 +1,133 @@
+use std::fmt::Debug;
+
+/// Type of the values held by the memory cells, implemented for `u8`, `u16`, `u32` and `u64`
+pub trait Cell: Copy + Default + PartialEq + Debug + Send + Sync + 'static {
+    /// The value of 1
+    const ONE: Self;
+    /// The maximum value a cell can hold
+    const MAX: Self;
+
+    /// Adds two values, wrapping around at the maximum value
+    fn wrapping_add(self, other: Self) -> Self;
+
+    /// Subtracts two values, wrapping around at zero
+    fn wrapping_sub(self, other: Self) -> Self;
+
+    /// Multiplies two values, wrapping around at the maximum value
+    fn wrapping_mul(self, other: Self) -> Self;
+
+    /// Adds a signed value, returns `None` if the result is below zero or above the maximum value
+    fn checked_add_signed(self, value: i32) -> Option<Self>;
+
+    /// Adds a signed value, stopping at zero or at the maximum value
+    fn saturating_add_signed(self, value: i32) -> Self;
+
+    /// Converts a signed value, wrapping around like two's complement (e.g. -1 becomes the maximum value)
+    fn from_i32(value: i32) -> Self;
+
+    /// Converts a byte read from the input
+    fn from_u8(value: u8) -> Self;
+
+    /// The lowest byte of the value
+    fn low_byte(self) -> u8;
+
+    /// Widens the value to 64 bits
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! impl_cell {
+    ($($type:ty),*) => {
+        $(
+            impl Cell for $type {
+                const ONE: Self = 1;
+                const MAX: Self = <$type>::MAX;
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$type>::wrapping_add(self, other)
+                }
+
+                fn wrapping_sub(self, other: Self) -> Self {
+                    <$type>::wrapping_sub(self, other)
+                }
+
+                fn wrapping_mul(self, other: Self) -> Self {
+                    <$type>::wrapping_mul(self, other)
+                }
+
+                fn checked_add_signed(self, value: i32) -> Option<Self> {
+                    <$type>::try_from(i128::from(self) + i128::from(value)).ok()
+                }
+
+                fn saturating_add_signed(self, value: i32) -> Self {
+                    let result = i128::from(self) + i128::from(value);
+                    <$type>::try_from(result.max(0)).unwrap_or(<$type>::MAX)
+                }
+
+                fn from_i32(value: i32) -> Self {
+                    value as $type
+                }
+
+                fn from_u8(value: u8) -> Self {
+                    value.into()
+                }
+
+                fn low_byte(self) -> u8 {
+                    self as u8
+                }
+
+                fn to_u64(self) -> u64 {
+                    self.into()
+                }
+            }
+        )*
+    };
+}
+
+impl_cell!(u8, u16, u32, u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_converts_negative_values_wrapping_around() {
+        assert_eq!(u8::MAX, <u8 as Cell>::from_i32(-1));
+        assert_eq!(u16::MAX - 1, <u16 as Cell>::from_i32(-2));
+        assert_eq!(u64::MAX, <u64 as Cell>::from_i32(-1));
+    }
+
+    #[test]
+    fn cell_converts_values_beyond_its_range_wrapping_around() {
+        assert_eq!(4, <u8 as Cell>::from_i32(260));
+        assert_eq!(260, <u16 as Cell>::from_i32(260));
+    }
+
+    #[test]
+    fn cell_checks_signed_additions_for_overflow() {
+        assert_eq!(Some(255), 250_u8.checked_add_signed(5));
+        assert_eq!(None, 250_u8.checked_add_signed(6));
+        assert_eq!(None, 0_u16.checked_add_signed(-1));
+        assert_eq!(Some(256), 250_u16.checked_add_signed(6));
+    }
+
+    #[test]
+    fn cell_saturates_signed_additions() {
+        assert_eq!(255, 250_u8.saturating_add_signed(10));
+        assert_eq!(0, 5_u8.saturating_add_signed(-10));
+        assert_eq!(u64::MAX, u64::MAX.saturating_add_signed(1));
+        assert_eq!(7, 5_u32.saturating_add_signed(2));
+    }
+
+    #[test]
+    fn cell_outputs_its_low_byte() {
+        assert_eq!(0x34, 0x1234_u16.low_byte());
+        assert_eq!(0xff, u64::MAX.low_byte());
+    }
+
+    #[test]
+    fn cell_wraps_around_its_own_range() {
+        assert_eq!(0, Cell::wrapping_add(u8::MAX, 1));
+        assert_eq!(256, Cell::wrapping_add(255_u16, 1));
+        assert_eq!(u32::MAX, Cell::wrapping_sub(0_u32, 1));
+    }
+}
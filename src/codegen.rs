@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+
+use crate::{bytecode::Code, error::BfError, program::Node};
+
+mod c;
+mod javascript;
+mod llvm;
+mod python;
+mod readable;
+mod rust;
+mod wasm;
+mod x86_64;
+
+/// Language a program can be compiled to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[non_exhaustive]
+pub enum Target {
+    /// Standalone C program, reading from the standard input and writing to the standard output
+    C,
+    /// Rust source file with a `run` function taking the input and the output, and a `main` function calling it
+    /// with the standard ones, it can be included in a module with `include!`
+    Rust,
+    /// WebAssembly module in the text format, with the tape in its linear memory and the input and the output
+    /// imported as the host functions `env.input` and `env.output`
+    Wat,
+    /// The same WebAssembly module as `Wat` in the binary format
+    Wasm,
+    /// Static x86-64 Linux executable, which reads and writes with system calls and needs no libraries
+    #[value(name = "x86_64-linux")]
+    X86_64Linux,
+    /// GNU assembler source of the same executable as `X86_64Linux`, to be built with `as` and `ld`
+    #[value(name = "x86_64-linux-asm")]
+    X86_64LinuxAsm,
+    /// LLVM IR module in the text format, with a `main` function reading with `getchar`
+    /// and writing with `putchar`
+    Llvm,
+    /// JavaScript program for Node.js, written to be read, with each statement commented
+    /// with the instructions it comes from and their position
+    #[value(name = "javascript")]
+    JavaScript,
+    /// Python 3 program, written to be read like the `JavaScript` one
+    Python,
+}
+
+/// Translates the program into a standalone program in the target language with a tape of the given size,
+/// it behaves like the interpreter with the default policies
+/// The programs written to be read are translated from the parsed instructions and the others from the bytecode
+/// Debug instructions are left out, they are only executed by the interpreter
+/// Fails if the tape has no cells or more than the target supports
+pub(crate) fn generate(
+    nodes: &[Node],
+    code: &[Code],
+    target: Target,
+    memory_size: usize,
+) -> Result<Vec<u8>, BfError> {
+    if memory_size == 0 {
+        return Err(BfError::EmptyTape);
+    }
+    let max = target.max_memory_size();
+    if memory_size > max {
+        return Err(BfError::TapeTooBig { memory_size, max });
+    }
+    Ok(match target {
+        Target::C => c::generate(code, memory_size).into_bytes(),
+        Target::Rust => rust::generate(code, memory_size).into_bytes(),
+        Target::Wat => wasm::generate_text(code, memory_size).into_bytes(),
+        Target::Wasm => wasm::generate_binary(code, memory_size),
+        Target::X86_64Linux => x86_64::generate_executable(code, memory_size),
+        Target::X86_64LinuxAsm => x86_64::generate_assembly(code, memory_size).into_bytes(),
+        Target::Llvm => llvm::generate(code, memory_size).into_bytes(),
+        Target::JavaScript => javascript::generate(nodes, memory_size).into_bytes(),
+        Target::Python => python::generate(nodes, memory_size).into_bytes(),
+    })
+}
+
+impl Target {
+    /// Number of cells of the biggest tape the compiled programs can address
+    fn max_memory_size(self) -> usize {
+        match self {
+            Target::Wat | Target::Wasm => wasm::MAX_MEMORY_SIZE,
+            Target::X86_64Linux | Target::X86_64LinuxAsm => x86_64::MAX_MEMORY_SIZE,
+            _ => usize::MAX,
+        }
+    }
+}
+
+/// Source code being generated line by line, with the lines indented by the depth of their block
+pub(crate) struct SourceWriter {
+    source: String,
+    indent: usize,
+    indent_width: usize,
+}
+
+impl SourceWriter {
+    pub(crate) fn new(indent_width: usize) -> SourceWriter {
+        SourceWriter {
+            source: String::new(),
+            indent: 0,
+            indent_width,
+        }
+    }
+
+    pub(crate) fn line(&mut self, line: impl Display) {
+        let line = line.to_string();
+        if !line.is_empty() {
+            let width = self.indent * self.indent_width;
+            self.source.extend(std::iter::repeat_n(' ', width));
+            self.source.push_str(&line);
+        }
+        self.source.push('\n');
+    }
+
+    /// Writes the line opening a block, the following lines are indented one level deeper
+    pub(crate) fn open(&mut self, line: impl Display) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    /// Writes the line closing a block, it and the following lines are indented one level less
+    pub(crate) fn close(&mut self, line: impl Display) {
+        self.indent -= 1;
+        self.line(line);
+    }
+
+    /// Writes a line one level less indented than the current block, such as a label
+    pub(crate) fn label(&mut self, line: impl Display) {
+        self.indent -= 1;
+        self.line(line);
+        self.indent += 1;
+    }
+
+    /// Ends a block without a closing line, the following lines are indented one level less
+    pub(crate) fn dedent(&mut self) {
+        self.indent -= 1;
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.source
+    }
+}
+
+/// The offset as the number of cells to move forward, so that moves wrap around the tape
+pub(crate) fn forward(offset: isize, memory_size: usize) -> usize {
+    offset.rem_euclid(memory_size as isize) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_writer_indents_blocks() {
+        let mut writer = SourceWriter::new(2);
+        writer.open("a {");
+        writer.line("b");
+        writer.label("c:");
+        writer.line("");
+        writer.close("}");
+        writer.open("d:");
+        writer.line("e");
+        writer.dedent();
+        writer.line("f");
+        assert_eq!("a {\n  b\nc:\n\n}\nd:\n  e\nf\n", writer.finish());
+    }
+
+    #[test]
+    fn offsets_are_moves_forward_around_the_tape() {
+        assert_eq!(0, forward(0, 5));
+        assert_eq!(2, forward(7, 5));
+        assert_eq!(4, forward(-1, 5));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::bytecode::Code;
+
+use super::{forward, SourceWriter};
+
+/// Generates a C program with a tape of `memory_size` bytes, the pointer wraps around it
+/// and `,` leaves the cell unchanged at the end of the input
+pub(crate) fn generate(code: &[Code], memory_size: usize) -> String {
+    let mut c = SourceWriter::new(4);
+    c.line("#include <stdio.h>");
+    c.line("");
+    c.line(format!("#define MEMORY_SIZE {memory_size}"));
+    c.line("");
+    c.line("static unsigned char tape[MEMORY_SIZE];");
+    c.line("");
+    c.open("int main(void) {");
+    c.line("size_t p = 0;");
+    c.line("int c;");
+    c.line("");
+
+    let cell = |offset: isize| match forward(offset, memory_size) {
+        0 => String::from("tape[p]"),
+        forward => format!("tape[(p + {forward}) % MEMORY_SIZE]"),
+    };
+    for instruction in code {
+        match *instruction {
+            Code::Add { offset, delta, .. } => {
+                let operator = if delta < 0 { "-" } else { "+" };
+                let value = delta.unsigned_abs() % 256;
+                c.line(format!("{} {operator}= {value};", cell(offset)));
+            }
+            Code::Move { offset, .. } => match forward(offset, memory_size) {
+                0 => {}
+                forward => c.line(format!("p = (p + {forward}) % MEMORY_SIZE;")),
+            },
+            Code::SetZero => c.line(format!("{} = 0;", cell(0))),
+            Code::MulAdd { offset, factor } => {
+                c.line(format!(
+                    "{} += {} * {};",
+                    cell(offset),
+                    cell(0),
+                    factor as u8
+                ));
+            }
+            Code::ScanZero { step, .. } => c.line(format!(
+                "while ({}) p = (p + {}) % MEMORY_SIZE;",
+                cell(0),
+                forward(step, memory_size)
+            )),
+            Code::Output { offset } => c.line(format!("putchar({});", cell(offset))),
+            Code::Input { offset } => {
+                c.line(format!("if ((c = getchar()) != EOF) {} = c;", cell(offset)));
+            }
+            Code::Debug { .. } => {}
+            Code::JumpIfZero(_) => c.open(format!("while ({}) {{", cell(0))),
+            Code::JumpUnlessZero(_) => c.close("}"),
+        }
+    }
+
+    c.line("");
+    c.line("return 0;");
+    c.close("}");
+    c.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        codegen::Target,
+        program::{Parser, Program},
+    };
+
+    fn generate_source(source_code: &str, memory_size: usize) -> String {
+        let program = Program::parse(source_code);
+        String::from_utf8(program.compile(Target::C, memory_size).unwrap()).unwrap()
+    }
+
+    fn body(c: &str) -> Vec<&str> {
+        let start = c.find("int c;\n\n").unwrap() + "int c;\n\n".len();
+        let end = c.rfind("\n\n    return 0;").unwrap();
+        c[start..end].lines().map(str::trim).collect()
+    }
+
+    #[test]
+    fn generator_writes_a_standalone_program_with_the_tape_size() {
+        let c = generate_source("", 300);
+        assert!(c.starts_with("#include <stdio.h>\n\n#define MEMORY_SIZE 300\n"));
+        assert!(c.contains("static unsigned char tape[MEMORY_SIZE];"));
+        assert!(c.ends_with("    return 0;\n}\n"));
+    }
+
+    #[test]
+    fn generator_addresses_cells_around_the_tape() {
+        assert_eq!(
+            vec![
+                "tape[(p + 2) % MEMORY_SIZE] += 3;",
+                "tape[(p + 9) % MEMORY_SIZE] -= 1;",
+                "p = (p + 9) % MEMORY_SIZE;"
+            ],
+            body(&generate_source(">>+++<<<-", 10))
+        );
+    }
+
+    #[test]
+    fn generator_leaves_cells_unchanged_at_the_end_of_input() {
+        assert_eq!(
+            vec![
+                "if ((c = getchar()) != EOF) tape[p] = c;",
+                "putchar(tape[p]);"
+            ],
+            body(&generate_source(",.", 10))
+        );
+    }
+
+    #[test]
+    fn generator_writes_loops_and_idioms() {
+        assert_eq!(
+            vec![
+                "while (tape[p]) {",
+                "tape[p] -= 1;",
+                "tape[(p + 1) % MEMORY_SIZE] += tape[p] * 254;",
+                "tape[p] = 0;",
+                "p = (p + 9) % MEMORY_SIZE;",
+                "while (tape[p]) p = (p + 9) % MEMORY_SIZE;",
+                "}"
+            ],
+            body(&generate_source("[-[->--<]<[<]]", 10))
+        );
+    }
+
+    #[test]
+    fn generator_leaves_out_debug_instructions() {
+        let program = Parser::new().with_debug_char('#').parse("+#.").unwrap();
+        assert_eq!(
+            generate_source("+.", 10),
+            String::from_utf8(program.compile(Target::C, 10).unwrap()).unwrap()
+        );
+    }
+}
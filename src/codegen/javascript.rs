@@ -0,0 +1,108 @@
+use crate::program::Node;
+
+use super::{
+    readable::{statements, Action},
+    SourceWriter,
+};
+
+/// Generates a JavaScript program for Node.js with a tape of `memory_size` bytes, each statement commented
+/// with the instructions it comes from and their position
+/// The pointer and the cells wrap around and `,` leaves the cell unchanged at the end of the input
+pub(crate) fn generate(nodes: &[Node], memory_size: usize) -> String {
+    let mut js = SourceWriter::new(2);
+    js.line("// Translated from Brainfuck, each statement is commented with the instructions it comes from");
+    js.line("const fs = require(\"fs\");");
+    js.line("");
+    js.line(format!("const MEMORY_SIZE = {memory_size};"));
+    js.line("const tape = new Uint8Array(MEMORY_SIZE);");
+    js.line("let p = 0;");
+    js.line("");
+    js.line("const input = fs.readFileSync(0);");
+    js.line("let inputPosition = 0;");
+    js.line("");
+    js.open("function output() {");
+    js.line("fs.writeSync(1, Uint8Array.of(tape[p]));");
+    js.close("}");
+    js.line("");
+    js.open("function readInput() {");
+    js.open("if (inputPosition < input.length) {");
+    js.line("tape[p] = input[inputPosition++];");
+    js.close("}");
+    js.close("}");
+    js.line("");
+
+    for statement in statements(nodes) {
+        let comment = format!("// {} at {}", statement.source, statement.position);
+        match statement.action {
+            Action::Add(count) => js.line(format!("tape[p] += {count}; {comment}")),
+            Action::Subtract(count) => js.line(format!("tape[p] -= {count}; {comment}")),
+            Action::MoveRight(count) => js.line(format!(
+                "p = (p + {}) % MEMORY_SIZE; {comment}",
+                count % memory_size
+            )),
+            Action::MoveLeft(count) => js.line(format!(
+                "p = (p - {} + MEMORY_SIZE) % MEMORY_SIZE; {comment}",
+                count % memory_size
+            )),
+            Action::Output => js.line(format!("output(); {comment}")),
+            Action::Input => js.line(format!("readInput(); {comment}")),
+            Action::LoopStart => js.open(format!("while (tape[p] !== 0) {{ {comment}")),
+            Action::LoopEnd => js.close("}"),
+        }
+    }
+    js.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{codegen::Target, program::Program};
+
+    fn generate_source(source_code: &str, memory_size: usize) -> String {
+        let program = Program::parse(source_code);
+        String::from_utf8(program.compile(Target::JavaScript, memory_size).unwrap()).unwrap()
+    }
+
+    fn body(js: &str) -> Vec<&str> {
+        let start = js.rfind("}\n\n").unwrap() + "}\n\n".len();
+        js[start..].lines().collect()
+    }
+
+    #[test]
+    fn generator_declares_the_tape_and_the_io_functions() {
+        let js = generate_source("", 300);
+        assert!(
+            js.contains("const MEMORY_SIZE = 300;\nconst tape = new Uint8Array(MEMORY_SIZE);\n")
+        );
+        assert!(js.contains("function output() {\n  fs.writeSync(1, Uint8Array.of(tape[p]));\n}\n"));
+        assert!(js.contains("function readInput() {\n"));
+    }
+
+    #[test]
+    fn generator_comments_statements_with_their_positions() {
+        assert_eq!(
+            vec![
+                "tape[p] += 3; // +++ at line 1, column 1",
+                "p = (p + 2) % MEMORY_SIZE; // >> at line 1, column 4",
+                "tape[p] -= 1; // - at line 2, column 1",
+                "p = (p - 1 + MEMORY_SIZE) % MEMORY_SIZE; // <<<<<<<<<<< at line 2, column 2",
+                "readInput(); // , at line 2, column 13",
+                "output(); // . at line 2, column 14",
+            ],
+            body(&generate_source("+++>>\n-<<<<<<<<<<<,.", 10))
+        );
+    }
+
+    #[test]
+    fn generator_writes_loops_as_while_statements() {
+        assert_eq!(
+            vec![
+                "while (tape[p] !== 0) { // [ at line 1, column 1",
+                "  tape[p] -= 1; // - at line 1, column 2",
+                "  while (tape[p] !== 0) { // [ at line 1, column 3",
+                "  }",
+                "}",
+            ],
+            body(&generate_source("[-[]]", 10))
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt::Display;
+
+use crate::bytecode::Code;
+
+use super::{forward, SourceWriter};
+
+/// Generates an LLVM IR module in the text format with a `main` function executing the program
+/// on a tape of `memory_size` bytes, the pointer and the cells wrap around and `,` leaves the cell unchanged
+/// at the end of the input
+/// The pointer is kept in memory and reloaded by every instruction, LLVM's `mem2reg` promotes it to a register
+pub(crate) fn generate(code: &[Code], memory_size: usize) -> String {
+    let mut ir = Builder {
+        writer: SourceWriter::new(2),
+        next_value: 0,
+        memory_size,
+    };
+    ir.writer.line(format!(
+        "@tape = internal global [{memory_size} x i8] zeroinitializer"
+    ));
+    ir.writer.line("");
+    ir.writer.line("declare i32 @putchar(i32)");
+    ir.writer.line("declare i32 @getchar()");
+    ir.writer.line("");
+    ir.writer.open("define i32 @main() {");
+    ir.writer.label("entry:");
+    ir.writer.line("%p = alloca i64");
+    ir.writer.line("store i64 0, ptr %p");
+
+    for (idx, instruction) in code.iter().enumerate() {
+        match *instruction {
+            Code::Add { offset, delta, .. } => {
+                let cell = ir.cell(offset);
+                let value = ir.value(format!("load i8, ptr {cell}"));
+                let sum = ir.value(format!("add i8 {value}, {}", delta as i8));
+                ir.writer.line(format!("store i8 {sum}, ptr {cell}"));
+            }
+            Code::Move { offset, .. } => ir.move_by(offset),
+            Code::SetZero => {
+                let cell = ir.cell(0);
+                ir.writer.line(format!("store i8 0, ptr {cell}"));
+            }
+            Code::MulAdd { offset, factor } => {
+                let cell = ir.cell(0);
+                let value = ir.value(format!("load i8, ptr {cell}"));
+                let product = ir.value(format!("mul i8 {value}, {}", factor as i8));
+                let target = ir.cell(offset);
+                let target_value = ir.value(format!("load i8, ptr {target}"));
+                let sum = ir.value(format!("add i8 {target_value}, {product}"));
+                ir.writer.line(format!("store i8 {sum}, ptr {target}"));
+            }
+            Code::ScanZero { step, .. } => {
+                let scan = format!("scan{idx}");
+                ir.branch_unless_zero(&scan, &format!("{scan}.step"), &format!("{scan}.end"));
+                ir.writer.label(format!("{scan}.step:"));
+                ir.move_by(step);
+                ir.writer.line(format!("br label %{scan}"));
+                ir.writer.label(format!("{scan}.end:"));
+            }
+            Code::Output { offset } => {
+                let cell = ir.cell(offset);
+                let value = ir.value(format!("load i8, ptr {cell}"));
+                let character = ir.value(format!("zext i8 {value} to i32"));
+                ir.value(format!("call i32 @putchar(i32 {character})"));
+            }
+            Code::Input { offset } => {
+                let character = ir.value("call i32 @getchar()");
+                let end_of_input = ir.value(format!("icmp slt i32 {character}, 0"));
+                let byte = ir.value(format!("trunc i32 {character} to i8"));
+                let cell = ir.cell(offset);
+                let value = ir.value(format!("load i8, ptr {cell}"));
+                let new_value =
+                    ir.value(format!("select i1 {end_of_input}, i8 {value}, i8 {byte}"));
+                ir.writer.line(format!("store i8 {new_value}, ptr {cell}"));
+            }
+            Code::Debug { .. } => {}
+            Code::JumpIfZero(_) => {
+                let start = format!("loop{idx}");
+                ir.branch_unless_zero(&start, &format!("{start}.body"), &format!("{start}.end"));
+                ir.writer.label(format!("{start}.body:"));
+            }
+            Code::JumpUnlessZero(body) => {
+                // the loop is named after its start, which is just before its body
+                let start = format!("loop{}", body - 1);
+                ir.writer.line(format!("br label %{start}"));
+                ir.writer.label(format!("{start}.end:"));
+            }
+        }
+    }
+
+    ir.writer.line("ret i32 0");
+    ir.writer.close("}");
+    ir.writer.finish()
+}
+
+/// LLVM IR being generated, with the values numbered in the order they are defined
+struct Builder {
+    writer: SourceWriter,
+    next_value: usize,
+    memory_size: usize,
+}
+
+impl Builder {
+    /// Writes an instruction defining a new value and returns the value
+    fn value(&mut self, instruction: impl Display) -> String {
+        let value = format!("%v{}", self.next_value);
+        self.next_value += 1;
+        self.writer.line(format!("{value} = {instruction}"));
+        value
+    }
+
+    /// Writes the instructions computing the index of the cell at the offset from the current one,
+    /// wrapping around the tape
+    fn cell_idx(&mut self, offset: isize) -> String {
+        let idx = self.value("load i64, ptr %p");
+        match forward(offset, self.memory_size) {
+            0 => idx,
+            forward => {
+                let sum = self.value(format!("add i64 {idx}, {forward}"));
+                self.value(format!("urem i64 {sum}, {}", self.memory_size))
+            }
+        }
+    }
+
+    /// Writes the instructions computing the address of the cell at the offset from the current one
+    /// and returns it
+    fn cell(&mut self, offset: isize) -> String {
+        let idx = self.cell_idx(offset);
+        self.value(format!(
+            "getelementptr inbounds [{} x i8], ptr @tape, i64 0, i64 {idx}",
+            self.memory_size
+        ))
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        if forward(offset, self.memory_size) != 0 {
+            let idx = self.cell_idx(offset);
+            self.writer.line(format!("store i64 {idx}, ptr %p"));
+        }
+    }
+
+    /// Writes a block, named `block`, which branches to `then` unless the current cell is zero
+    /// and to `otherwise` if it is
+    fn branch_unless_zero(&mut self, block: &str, then: &str, otherwise: &str) {
+        self.writer.line(format!("br label %{block}"));
+        self.writer.label(format!("{block}:"));
+        let cell = self.cell(0);
+        let value = self.value(format!("load i8, ptr {cell}"));
+        let is_zero = self.value(format!("icmp eq i8 {value}, 0"));
+        self.writer.line(format!(
+            "br i1 {is_zero}, label %{otherwise}, label %{then}"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{codegen::Target, program::Program};
+
+    fn generate_source(source_code: &str, memory_size: usize) -> String {
+        let program = Program::parse(source_code);
+        String::from_utf8(program.compile(Target::Llvm, memory_size).unwrap()).unwrap()
+    }
+
+    fn body(ir: &str) -> Vec<&str> {
+        let start = ir.find("store i64 0, ptr %p\n").unwrap() + "store i64 0, ptr %p\n".len();
+        let end = ir.rfind("  ret i32 0").unwrap();
+        ir[start..end].lines().map(str::trim).collect()
+    }
+
+    #[test]
+    fn generator_writes_the_tape_the_io_declarations_and_main() {
+        let ir = generate_source("", 300);
+        assert!(ir.starts_with("@tape = internal global [300 x i8] zeroinitializer\n"));
+        assert!(ir.contains("declare i32 @putchar(i32)\ndeclare i32 @getchar()\n"));
+        assert!(ir.contains("define i32 @main() {\nentry:\n  %p = alloca i64\n"));
+        assert!(ir.ends_with("  ret i32 0\n}\n"));
+    }
+
+    #[test]
+    fn generator_wraps_cells_and_offsets_around() {
+        assert_eq!(
+            vec![
+                "%v0 = load i64, ptr %p",
+                "%v1 = add i64 %v0, 2",
+                "%v2 = urem i64 %v1, 10",
+                "%v3 = getelementptr inbounds [10 x i8], ptr @tape, i64 0, i64 %v2",
+                "%v4 = load i8, ptr %v3",
+                "%v5 = add i8 %v4, -1",
+                "store i8 %v5, ptr %v3",
+                "%v6 = load i64, ptr %p",
+                "%v7 = add i64 %v6, 9",
+                "%v8 = urem i64 %v7, 10",
+                "store i64 %v8, ptr %p",
+            ],
+            body(&generate_source(">>-<<<", 10))
+        );
+    }
+
+    #[test]
+    fn generator_leaves_cells_unchanged_at_the_end_of_input() {
+        assert_eq!(
+            vec![
+                "%v0 = call i32 @getchar()",
+                "%v1 = icmp slt i32 %v0, 0",
+                "%v2 = trunc i32 %v0 to i8",
+                "%v3 = load i64, ptr %p",
+                "%v4 = getelementptr inbounds [10 x i8], ptr @tape, i64 0, i64 %v3",
+                "%v5 = load i8, ptr %v4",
+                "%v6 = select i1 %v1, i8 %v5, i8 %v2",
+                "store i8 %v6, ptr %v4",
+            ],
+            body(&generate_source(",", 10))
+        );
+    }
+
+    #[test]
+    fn generator_writes_loops_as_blocks() {
+        let ir = generate_source("[.]", 10);
+        assert_eq!(
+            vec![
+                "br label %loop0",
+                "loop0:",
+                "%v0 = load i64, ptr %p",
+                "%v1 = getelementptr inbounds [10 x i8], ptr @tape, i64 0, i64 %v0",
+                "%v2 = load i8, ptr %v1",
+                "%v3 = icmp eq i8 %v2, 0",
+                "br i1 %v3, label %loop0.end, label %loop0.body",
+                "loop0.body:",
+                "%v4 = load i64, ptr %p",
+                "%v5 = getelementptr inbounds [10 x i8], ptr @tape, i64 0, i64 %v4",
+                "%v6 = load i8, ptr %v5",
+                "%v7 = zext i8 %v6 to i32",
+                "%v8 = call i32 @putchar(i32 %v7)",
+                "br label %loop0",
+                "loop0.end:",
+            ],
+            body(&ir)
+        );
+        assert!(ir.contains("\nloop0.body:\n  %v4"));
+    }
+}
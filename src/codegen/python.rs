@@ -0,0 +1,116 @@
+use crate::program::Node;
+
+use super::{
+    readable::{statements, Action},
+    SourceWriter,
+};
+
+/// Generates a Python 3 program with a tape of `memory_size` bytes, each statement commented
+/// with the instructions it comes from and their position
+/// The pointer and the cells wrap around and `,` leaves the cell unchanged at the end of the input
+pub(crate) fn generate(nodes: &[Node], memory_size: usize) -> String {
+    let mut py = SourceWriter::new(4);
+    py.line("# Translated from Brainfuck, each statement is commented with the instructions it comes from");
+    py.line("import sys");
+    py.line("");
+    py.line(format!("MEMORY_SIZE = {memory_size}"));
+    py.line("tape = bytearray(MEMORY_SIZE)");
+    py.line("p = 0");
+    py.line("");
+    py.line("");
+    py.open("def output():");
+    py.line("sys.stdout.buffer.write(bytes([tape[p]]))");
+    py.dedent();
+    py.line("");
+    py.line("");
+    py.open("def read_input():");
+    py.line("byte = sys.stdin.buffer.read(1)");
+    py.open("if byte:");
+    py.line("tape[p] = byte[0]");
+    py.dedent();
+    py.dedent();
+    py.line("");
+    py.line("");
+
+    let mut previous = None;
+    for statement in statements(nodes) {
+        let comment = format!("# {} at {}", statement.source, statement.position);
+        match statement.action {
+            Action::Add(count) => {
+                py.line(format!("tape[p] = (tape[p] + {count}) % 256  {comment}"))
+            }
+            Action::Subtract(count) => {
+                py.line(format!("tape[p] = (tape[p] - {count}) % 256  {comment}"))
+            }
+            Action::MoveRight(count) => {
+                py.line(format!("p = (p + {count}) % MEMORY_SIZE  {comment}"))
+            }
+            Action::MoveLeft(count) => {
+                py.line(format!("p = (p - {count}) % MEMORY_SIZE  {comment}"))
+            }
+            Action::Output => py.line(format!("output()  {comment}")),
+            Action::Input => py.line(format!("read_input()  {comment}")),
+            Action::LoopStart => py.open(format!("while tape[p] != 0:  {comment}")),
+            Action::LoopEnd => {
+                if previous == Some(Action::LoopStart) {
+                    py.line("pass");
+                }
+                py.dedent();
+            }
+        }
+        previous = Some(statement.action);
+    }
+    py.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{codegen::Target, program::Program};
+
+    fn generate_source(source_code: &str, memory_size: usize) -> String {
+        let program = Program::parse(source_code);
+        String::from_utf8(program.compile(Target::Python, memory_size).unwrap()).unwrap()
+    }
+
+    fn body(py: &str) -> Vec<&str> {
+        let start = py.find("tape[p] = byte[0]\n\n\n").unwrap() + "tape[p] = byte[0]\n\n\n".len();
+        py[start..].lines().collect()
+    }
+
+    #[test]
+    fn generator_declares_the_tape_and_the_io_functions() {
+        let py = generate_source("", 300);
+        assert!(py.contains("MEMORY_SIZE = 300\ntape = bytearray(MEMORY_SIZE)\n"));
+        assert!(py.contains("def output():\n    sys.stdout.buffer.write(bytes([tape[p]]))\n"));
+        assert!(py.contains("def read_input():\n"));
+    }
+
+    #[test]
+    fn generator_comments_statements_with_their_positions() {
+        assert_eq!(
+            vec![
+                "tape[p] = (tape[p] + 3) % 256  # +++ at line 1, column 1",
+                "p = (p + 2) % MEMORY_SIZE  # >> at line 1, column 4",
+                "tape[p] = (tape[p] - 1) % 256  # - at line 2, column 1",
+                "p = (p - 1) % MEMORY_SIZE  # < at line 2, column 2",
+                "read_input()  # , at line 2, column 3",
+                "output()  # . at line 2, column 4",
+            ],
+            body(&generate_source("+++>>\n-<,.", 10))
+        );
+    }
+
+    #[test]
+    fn generator_writes_loops_as_while_statements() {
+        assert_eq!(
+            vec![
+                "while tape[p] != 0:  # [ at line 1, column 1",
+                "    tape[p] = (tape[p] - 1) % 256  # - at line 1, column 2",
+                "    while tape[p] != 0:  # [ at line 1, column 3",
+                "        pass",
+                "output()  # . at line 1, column 6",
+            ],
+            body(&generate_source("[-[]].", 10))
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    position::Position,
+    program::{Instruction, Node},
+};
+
+/// Statement of a program translated to be read, made of one instruction or a run of the same one
+pub(crate) struct Statement {
+    pub(crate) action: Action,
+    /// Source code of the instructions, e.g. `+++`
+    pub(crate) source: String,
+    /// Position of the first of the instructions
+    pub(crate) position: Position,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Action {
+    Add(usize),
+    Subtract(usize),
+    MoveRight(usize),
+    MoveLeft(usize),
+    Output,
+    Input,
+    LoopStart,
+    /// The end of the loop which started at the position of the statement
+    LoopEnd,
+}
+
+/// Translates the parsed instructions into statements, each run of the same addition or move becomes one
+pub(crate) fn statements(nodes: &[Node]) -> Vec<Statement> {
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut blocks = vec![(nodes.iter(), None)];
+
+    while let Some((nodes, _)) = blocks.last_mut() {
+        let Some(node) = nodes.next() else {
+            if let (_, Some(position)) = blocks.pop().expect("the block is on the stack") {
+                statements.push(Statement {
+                    action: Action::LoopEnd,
+                    source: String::from("]"),
+                    position,
+                });
+            }
+            continue;
+        };
+        let (action, source) = match &node.instruction {
+            Instruction::IncrementData => (Action::Add(1), '+'),
+            Instruction::DecrementData => (Action::Subtract(1), '-'),
+            Instruction::NextCell => (Action::MoveRight(1), '>'),
+            Instruction::PreviousCell => (Action::MoveLeft(1), '<'),
+            Instruction::OutputData => (Action::Output, '.'),
+            Instruction::InputData => (Action::Input, ','),
+            Instruction::Loop(loop_nodes) => {
+                blocks.push((loop_nodes.iter(), Some(node.position)));
+                (Action::LoopStart, '[')
+            }
+            Instruction::Debug => continue,
+        };
+        if let Some(last) = statements.last_mut() {
+            if let Some(action) = continued(last.action, action) {
+                last.action = action;
+                last.source.push(source);
+                continue;
+            }
+        }
+        statements.push(Statement {
+            action,
+            source: String::from(source),
+            position: node.position,
+        });
+    }
+
+    statements
+}
+
+/// The action of a run of instructions continued by one more, if it can be
+fn continued(run: Action, next: Action) -> Option<Action> {
+    match (run, next) {
+        (Action::Add(count), Action::Add(_)) => Some(Action::Add(count + 1)),
+        (Action::Subtract(count), Action::Subtract(_)) => Some(Action::Subtract(count + 1)),
+        (Action::MoveRight(count), Action::MoveRight(_)) => Some(Action::MoveRight(count + 1)),
+        (Action::MoveLeft(count), Action::MoveLeft(_)) => Some(Action::MoveLeft(count + 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+
+    fn translate(source_code: &str) -> Vec<(Action, String, usize)> {
+        statements(Program::parse(source_code).nodes())
+            .into_iter()
+            .map(|statement| {
+                (
+                    statement.action,
+                    statement.source,
+                    statement.position.offset,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn runs_of_the_same_instruction_become_one_statement() {
+        assert_eq!(
+            vec![
+                (Action::Add(3), String::from("+++"), 0),
+                (Action::Subtract(1), String::from("-"), 3),
+                (Action::MoveRight(2), String::from(">>"), 4),
+                (Action::MoveLeft(1), String::from("<"), 6),
+                (Action::Output, String::from("."), 8),
+                (Action::Output, String::from("."), 9),
+                (Action::Input, String::from(","), 10),
+            ],
+            translate("+++->><\n..,")
+        );
+    }
+
+    #[test]
+    fn loops_start_and_end_at_the_position_of_their_start() {
+        assert_eq!(
+            vec![
+                (Action::Add(1), String::from("+"), 0),
+                (Action::LoopStart, String::from("["), 1),
+                (Action::LoopStart, String::from("["), 2),
+                (Action::LoopEnd, String::from("]"), 2),
+                (Action::Add(1), String::from("+"), 4),
+                (Action::LoopEnd, String::from("]"), 1),
+                (Action::Add(1), String::from("+"), 6),
+            ],
+            translate("+[[]+]+")
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use crate::bytecode::Code;
+
+use super::{forward, SourceWriter};
+
+/// Generates a Rust source file with a `run` function executing the program on a tape of `memory_size` bytes,
+/// and a `main` function running it on the standard input and output
+/// The pointer and the cells wrap around like in `Memory` and `,` leaves the cell unchanged at the end of the input
+/// The file can also be included in a module with `include!`, e.g. after being generated by a build script
+pub(crate) fn generate(code: &[Code], memory_size: usize) -> String {
+    let reads_input = code
+        .iter()
+        .any(|instruction| matches!(instruction, Code::Input { .. }));
+
+    let mut rust = SourceWriter::new(4);
+    rust.line("use std::io::{self, Read, Write};");
+    rust.line("");
+    rust.line(format!("const MEMORY_SIZE: usize = {memory_size};"));
+    rust.line("");
+    rust.line("/// Runs the program, reading from the input and writing to the output");
+    rust.line("#[allow(unused_assignments, unused_mut, unused_variables)]");
+    rust.open("pub fn run(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {");
+    rust.line("let mut tape = vec![0_u8; MEMORY_SIZE];");
+    rust.line("let mut p = 0;");
+    rust.line("");
+
+    let cell = |offset: isize| match forward(offset, memory_size) {
+        0 => String::from("tape[p]"),
+        forward => format!("tape[(p + {forward}) % MEMORY_SIZE]"),
+    };
+    for instruction in code {
+        match *instruction {
+            Code::Add { offset, delta, .. } => {
+                let method = if delta < 0 { "sub" } else { "add" };
+                let value = delta.unsigned_abs() % 256;
+                let cell = cell(offset);
+                rust.line(format!("{cell} = {cell}.wrapping_{method}({value});"));
+            }
+            Code::Move { offset, .. } => match forward(offset, memory_size) {
+                0 => {}
+                forward => rust.line(format!("p = (p + {forward}) % MEMORY_SIZE;")),
+            },
+            Code::SetZero => rust.line(format!("{} = 0;", cell(0))),
+            Code::MulAdd { offset, factor } => {
+                let target = cell(offset);
+                rust.line(format!(
+                    "{target} = {target}.wrapping_add({}.wrapping_mul({}));",
+                    cell(0),
+                    factor as u8
+                ));
+            }
+            Code::ScanZero { step, .. } => {
+                rust.open(format!("while {} != 0 {{", cell(0)));
+                rust.line(format!(
+                    "p = (p + {}) % MEMORY_SIZE;",
+                    forward(step, memory_size)
+                ));
+                rust.close("}");
+            }
+            Code::Output { offset } => {
+                rust.line(format!("output.write_all(&[{}])?;", cell(offset)))
+            }
+            Code::Input { offset } => {
+                rust.open("if let Some(byte) = read_byte(input)? {");
+                rust.line(format!("{} = byte;", cell(offset)));
+                rust.close("}");
+            }
+            Code::Debug { .. } => {}
+            Code::JumpIfZero(_) => rust.open(format!("while {} != 0 {{", cell(0))),
+            Code::JumpUnlessZero(_) => rust.close("}"),
+        }
+    }
+
+    rust.line("");
+    rust.line("output.flush()");
+    rust.close("}");
+
+    if reads_input {
+        rust.line("");
+        rust.line("/// Reads the next byte of the input, `None` at the end of it");
+        rust.open("fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {");
+        rust.line("let mut byte = [0_u8];");
+        rust.open("match input.read_exact(&mut byte) {");
+        rust.line("Ok(()) => Ok(Some(byte[0])),");
+        rust.line("Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(None),");
+        rust.line("Err(error) => Err(error),");
+        rust.close("}");
+        rust.close("}");
+    }
+
+    rust.line("");
+    rust.line("#[allow(dead_code)]");
+    rust.open("fn main() -> io::Result<()> {");
+    rust.line("let mut output = io::BufWriter::new(io::stdout().lock());");
+    rust.line("run(&mut io::stdin().lock(), &mut output)");
+    rust.close("}");
+    rust.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{codegen::Target, program::Program};
+
+    fn generate_source(source_code: &str, memory_size: usize) -> String {
+        let program = Program::parse(source_code);
+        String::from_utf8(program.compile(Target::Rust, memory_size).unwrap()).unwrap()
+    }
+
+    fn body(rust: &str) -> Vec<&str> {
+        let start = rust.find("let mut p = 0;\n\n").unwrap() + "let mut p = 0;\n\n".len();
+        let end = rust.find("\n\n    output.flush()").unwrap();
+        rust[start..end].lines().map(str::trim).collect()
+    }
+
+    #[test]
+    fn generator_writes_a_run_function_and_a_main_function() {
+        let rust = generate_source("", 300);
+        assert!(rust
+            .starts_with("use std::io::{self, Read, Write};\n\nconst MEMORY_SIZE: usize = 300;\n"));
+        assert!(rust.contains(
+            "pub fn run(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {"
+        ));
+        assert!(rust.contains("let mut tape = vec![0_u8; MEMORY_SIZE];"));
+        assert!(rust.ends_with("    run(&mut io::stdin().lock(), &mut output)\n}\n"));
+        assert!(!rust.contains("fn read_byte"));
+    }
+
+    #[test]
+    fn generator_wraps_cells_and_offsets_around() {
+        assert_eq!(
+            vec![
+                "tape[(p + 2) % MEMORY_SIZE] = tape[(p + 2) % MEMORY_SIZE].wrapping_add(3);",
+                "tape[(p + 9) % MEMORY_SIZE] = tape[(p + 9) % MEMORY_SIZE].wrapping_sub(1);",
+                "p = (p + 9) % MEMORY_SIZE;"
+            ],
+            body(&generate_source(">>+++<<<-", 10))
+        );
+    }
+
+    #[test]
+    fn generator_leaves_cells_unchanged_at_the_end_of_input() {
+        let rust = generate_source(",.", 10);
+        assert_eq!(
+            vec![
+                "if let Some(byte) = read_byte(input)? {",
+                "tape[p] = byte;",
+                "}",
+                "output.write_all(&[tape[p]])?;"
+            ],
+            body(&rust)
+        );
+        assert!(rust.contains("fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {"));
+    }
+
+    #[test]
+    fn generator_writes_loops_and_idioms() {
+        assert_eq!(
+            vec![
+                "while tape[p] != 0 {",
+                "tape[p] = tape[p].wrapping_sub(1);",
+                "tape[(p + 1) % MEMORY_SIZE] = tape[(p + 1) % MEMORY_SIZE].wrapping_add(tape[p].wrapping_mul(254));",
+                "tape[p] = 0;",
+                "p = (p + 9) % MEMORY_SIZE;",
+                "while tape[p] != 0 {",
+                "p = (p + 9) % MEMORY_SIZE;",
+                "}",
+                "}"
+            ],
+            body(&generate_source("[-[->--<]<[<]]", 10))
+        );
+    }
+}
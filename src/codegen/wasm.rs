@@ -0,0 +1,474 @@
+use crate::bytecode::Code;
+
+use super::{forward, SourceWriter};
+
+/// Size of a page of WebAssembly linear memory
+const PAGE_SIZE: usize = 65536;
+
+/// Number of cells of the biggest tape, the address of a cell at an offset is the sum of two addresses
+/// on the tape, which has to fit in 32 bits, so the tape takes at most half of the 65536 pages
+pub(crate) const MAX_MEMORY_SIZE: usize = 1 << 31;
+
+/// Indexes of the imported functions
+const OUTPUT: u32 = 0;
+const INPUT: u32 = 1;
+
+/// Indexes of the locals of the `run` function: the pointer, the address of the cell being changed
+/// and the byte read from the input
+const P: u32 = 0;
+const ADDRESS: u32 = 1;
+const BYTE: u32 = 2;
+
+/// WebAssembly instruction, as used in the body of the `run` function
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Instr {
+    Block,
+    Loop,
+    If,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Call(u32),
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    I32Load8U,
+    I32Store8,
+    I32Const(i32),
+    I32Eqz,
+    I32GeS,
+    I32Add,
+    I32Mul,
+    I32RemU,
+}
+
+impl Instr {
+    fn text(self) -> String {
+        match self {
+            Instr::Block => String::from("block"),
+            Instr::Loop => String::from("loop"),
+            Instr::If => String::from("if"),
+            Instr::End => String::from("end"),
+            Instr::Br(depth) => format!("br {depth}"),
+            Instr::BrIf(depth) => format!("br_if {depth}"),
+            Instr::Call(function) => format!("call {}", function_name(function)),
+            Instr::LocalGet(local) => format!("local.get {}", local_name(local)),
+            Instr::LocalSet(local) => format!("local.set {}", local_name(local)),
+            Instr::LocalTee(local) => format!("local.tee {}", local_name(local)),
+            Instr::I32Load8U => String::from("i32.load8_u"),
+            Instr::I32Store8 => String::from("i32.store8"),
+            Instr::I32Const(value) => format!("i32.const {value}"),
+            Instr::I32Eqz => String::from("i32.eqz"),
+            Instr::I32GeS => String::from("i32.ge_s"),
+            Instr::I32Add => String::from("i32.add"),
+            Instr::I32Mul => String::from("i32.mul"),
+            Instr::I32RemU => String::from("i32.rem_u"),
+        }
+    }
+
+    fn encode(self, bytes: &mut Vec<u8>) {
+        // blocks have no result and memory accesses are byte aligned with no static offset
+        const EMPTY: u8 = 0x40;
+        match self {
+            Instr::Block => bytes.extend([0x02, EMPTY]),
+            Instr::Loop => bytes.extend([0x03, EMPTY]),
+            Instr::If => bytes.extend([0x04, EMPTY]),
+            Instr::End => bytes.push(0x0b),
+            Instr::Br(depth) => encode_with_index(bytes, 0x0c, depth),
+            Instr::BrIf(depth) => encode_with_index(bytes, 0x0d, depth),
+            Instr::Call(function) => encode_with_index(bytes, 0x10, function),
+            Instr::LocalGet(local) => encode_with_index(bytes, 0x20, local),
+            Instr::LocalSet(local) => encode_with_index(bytes, 0x21, local),
+            Instr::LocalTee(local) => encode_with_index(bytes, 0x22, local),
+            Instr::I32Load8U => bytes.extend([0x2d, 0, 0]),
+            Instr::I32Store8 => bytes.extend([0x3a, 0, 0]),
+            Instr::I32Const(value) => {
+                bytes.push(0x41);
+                signed_leb128(bytes, value);
+            }
+            Instr::I32Eqz => bytes.push(0x45),
+            Instr::I32GeS => bytes.push(0x4e),
+            Instr::I32Add => bytes.push(0x6a),
+            Instr::I32Mul => bytes.push(0x6c),
+            Instr::I32RemU => bytes.push(0x70),
+        }
+    }
+}
+
+fn function_name(function: u32) -> &'static str {
+    match function {
+        OUTPUT => "$output",
+        _ => "$input",
+    }
+}
+
+fn local_name(local: u32) -> &'static str {
+    match local {
+        P => "$p",
+        ADDRESS => "$address",
+        _ => "$byte",
+    }
+}
+
+fn encode_with_index(bytes: &mut Vec<u8>, opcode: u8, index: u32) {
+    bytes.push(opcode);
+    unsigned_leb128(bytes, index);
+}
+
+/// Instructions of the `run` function, the tape is at the start of the linear memory
+fn instructions(code: &[Code], memory_size: usize) -> Vec<Instr> {
+    let address = |offset: isize| match forward(offset, memory_size) {
+        0 => vec![Instr::LocalGet(P)],
+        forward => vec![
+            Instr::LocalGet(P),
+            Instr::I32Const(forward as i32),
+            Instr::I32Add,
+            // the tape has at most 2^31 cells, whose number is read as unsigned by the remainder
+            Instr::I32Const(memory_size as u32 as i32),
+            Instr::I32RemU,
+        ],
+    };
+    let mut instructions = vec![];
+    for instruction in code {
+        match *instruction {
+            Code::Add { offset, delta, .. } => {
+                instructions.extend(address(offset));
+                instructions.extend([
+                    Instr::LocalTee(ADDRESS),
+                    Instr::LocalGet(ADDRESS),
+                    Instr::I32Load8U,
+                    Instr::I32Const(delta.rem_euclid(256)),
+                    Instr::I32Add,
+                    Instr::I32Store8,
+                ]);
+            }
+            Code::Move { offset, .. } => {
+                if forward(offset, memory_size) != 0 {
+                    instructions.extend(address(offset));
+                    instructions.push(Instr::LocalSet(P));
+                }
+            }
+            Code::SetZero => {
+                instructions.extend([Instr::LocalGet(P), Instr::I32Const(0), Instr::I32Store8])
+            }
+            Code::MulAdd { offset, factor } => {
+                instructions.extend(address(offset));
+                instructions.extend([
+                    Instr::LocalTee(ADDRESS),
+                    Instr::LocalGet(ADDRESS),
+                    Instr::I32Load8U,
+                    Instr::LocalGet(P),
+                    Instr::I32Load8U,
+                    Instr::I32Const(factor.rem_euclid(256)),
+                    Instr::I32Mul,
+                    Instr::I32Add,
+                    Instr::I32Store8,
+                ]);
+            }
+            Code::ScanZero { step, .. } => {
+                instructions.extend([
+                    Instr::Block,
+                    Instr::Loop,
+                    Instr::LocalGet(P),
+                    Instr::I32Load8U,
+                    Instr::I32Eqz,
+                    Instr::BrIf(1),
+                ]);
+                instructions.extend(address(step));
+                instructions.extend([Instr::LocalSet(P), Instr::Br(0), Instr::End, Instr::End]);
+            }
+            Code::Output { offset } => {
+                instructions.extend(address(offset));
+                instructions.extend([Instr::I32Load8U, Instr::Call(OUTPUT)]);
+            }
+            Code::Input { offset } => {
+                instructions.extend([
+                    Instr::Call(INPUT),
+                    Instr::LocalTee(BYTE),
+                    Instr::I32Const(0),
+                    Instr::I32GeS,
+                    Instr::If,
+                ]);
+                instructions.extend(address(offset));
+                instructions.extend([Instr::LocalGet(BYTE), Instr::I32Store8, Instr::End]);
+            }
+            Code::Debug { .. } => {}
+            Code::JumpIfZero(_) => instructions.extend([
+                Instr::Block,
+                Instr::Loop,
+                Instr::LocalGet(P),
+                Instr::I32Load8U,
+                Instr::I32Eqz,
+                Instr::BrIf(1),
+            ]),
+            Code::JumpUnlessZero(_) => {
+                instructions.extend([Instr::Br(0), Instr::End, Instr::End]);
+            }
+        }
+    }
+    instructions
+}
+
+/// Number of pages of linear memory needed for the tape
+fn pages(memory_size: usize) -> usize {
+    memory_size.div_ceil(PAGE_SIZE).max(1)
+}
+
+/// Generates a WebAssembly module in the text format, exporting its linear memory as `memory`
+/// and a `run` function executing the program on a tape of `memory_size` bytes at the start of the memory
+/// It imports `env.output`, which takes the byte to write, and `env.input`, which returns the byte read
+/// or -1 at the end of the input, when `,` leaves the cell unchanged
+pub(crate) fn generate_text(code: &[Code], memory_size: usize) -> String {
+    let mut wat = SourceWriter::new(2);
+    wat.open("(module");
+    wat.line(r#"(import "env" "output" (func $output (param i32)))"#);
+    wat.line(r#"(import "env" "input" (func $input (result i32)))"#);
+    wat.line(format!(
+        r#"(memory (export "memory") {})"#,
+        pages(memory_size)
+    ));
+    wat.open(r#"(func (export "run")"#);
+    wat.line("(local $p i32)");
+    wat.line("(local $address i32)");
+    wat.line("(local $byte i32)");
+    for instruction in instructions(code, memory_size) {
+        match instruction {
+            Instr::Block | Instr::Loop | Instr::If => wat.open(instruction.text()),
+            Instr::End => wat.close(instruction.text()),
+            _ => wat.line(instruction.text()),
+        }
+    }
+    wat.close(")");
+    wat.close(")");
+    wat.finish()
+}
+
+/// Generates the same WebAssembly module as `generate_text` in the binary format
+pub(crate) fn generate_binary(code: &[Code], memory_size: usize) -> Vec<u8> {
+    const I32: u8 = 0x7f;
+    const FUNCTION: u8 = 0x00;
+    const MEMORY: u8 = 0x02;
+
+    let mut module = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+    // the types of the output, input and run functions
+    let mut types = vec![3];
+    types.extend([0x60, 1, I32, 0]);
+    types.extend([0x60, 0, 1, I32]);
+    types.extend([0x60, 0, 0]);
+    section(&mut module, 1, &types);
+
+    let mut imports = vec![2];
+    for (name, type_idx) in [("output", 0), ("input", 1)] {
+        name_bytes(&mut imports, "env");
+        name_bytes(&mut imports, name);
+        imports.extend([FUNCTION, type_idx]);
+    }
+    section(&mut module, 2, &imports);
+
+    section(&mut module, 3, &[1, 2]);
+
+    let mut memories = vec![1, 0x00];
+    unsigned_leb128(&mut memories, pages(memory_size) as u32);
+    section(&mut module, 5, &memories);
+
+    let mut exports = vec![2];
+    name_bytes(&mut exports, "memory");
+    exports.extend([MEMORY, 0]);
+    name_bytes(&mut exports, "run");
+    exports.extend([FUNCTION, 2]);
+    section(&mut module, 7, &exports);
+
+    let mut body = vec![1, 3, I32];
+    for instruction in instructions(code, memory_size) {
+        instruction.encode(&mut body);
+    }
+    Instr::End.encode(&mut body);
+    let mut bodies = vec![1];
+    unsigned_leb128(&mut bodies, body.len() as u32);
+    bodies.extend(body);
+    section(&mut module, 10, &bodies);
+
+    module
+}
+
+fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    module.push(id);
+    unsigned_leb128(module, contents.len() as u32);
+    module.extend(contents);
+}
+
+fn name_bytes(bytes: &mut Vec<u8>, name: &str) {
+    unsigned_leb128(bytes, name.len() as u32);
+    bytes.extend(name.as_bytes());
+}
+
+fn unsigned_leb128(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn signed_leb128(bytes: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codegen::Target, error::BfError, program::Program};
+
+    fn generate_text_source(source_code: &str, memory_size: usize) -> String {
+        let program = Program::parse(source_code);
+        String::from_utf8(program.compile(Target::Wat, memory_size).unwrap()).unwrap()
+    }
+
+    fn body(wat: &str) -> Vec<&str> {
+        let start = wat.find("(local $byte i32)\n").unwrap() + "(local $byte i32)\n".len();
+        let end = wat.rfind("  )\n)").unwrap();
+        wat[start..end].lines().map(str::trim).collect()
+    }
+
+    #[test]
+    fn text_module_imports_the_io_and_exports_the_memory_and_run() {
+        let wat = generate_text_source("", 70000);
+        assert!(wat.starts_with("(module\n"));
+        assert!(wat.contains(r#"(import "env" "output" (func $output (param i32)))"#));
+        assert!(wat.contains(r#"(import "env" "input" (func $input (result i32)))"#));
+        assert!(wat.contains(r#"(memory (export "memory") 2)"#));
+        assert!(wat.contains(r#"(func (export "run")"#));
+        assert!(wat.ends_with("  )\n)\n"));
+    }
+
+    #[test]
+    fn text_module_wraps_cells_and_offsets_around() {
+        assert_eq!(
+            vec![
+                "local.get $p",
+                "i32.const 2",
+                "i32.add",
+                "i32.const 10",
+                "i32.rem_u",
+                "local.tee $address",
+                "local.get $address",
+                "i32.load8_u",
+                "i32.const 255",
+                "i32.add",
+                "i32.store8",
+                "local.get $p",
+                "i32.const 9",
+                "i32.add",
+                "i32.const 10",
+                "i32.rem_u",
+                "local.set $p",
+            ],
+            body(&generate_text_source(">>-<<<", 10))
+        );
+    }
+
+    #[test]
+    fn text_module_leaves_cells_unchanged_at_the_end_of_input() {
+        assert_eq!(
+            vec![
+                "call $input",
+                "local.tee $byte",
+                "i32.const 0",
+                "i32.ge_s",
+                "if",
+                "local.get $p",
+                "local.get $byte",
+                "i32.store8",
+                "end",
+                "local.get $p",
+                "i32.load8_u",
+                "call $output",
+            ],
+            body(&generate_text_source(",.", 10))
+        );
+    }
+
+    #[test]
+    fn text_module_writes_loops_as_blocks() {
+        let wat = generate_text_source("[[-]]", 10);
+        assert_eq!(
+            vec![
+                "block",
+                "loop",
+                "local.get $p",
+                "i32.load8_u",
+                "i32.eqz",
+                "br_if 1",
+                "local.get $p",
+                "i32.const 0",
+                "i32.store8",
+                "br 0",
+                "end",
+                "end",
+            ],
+            body(&wat)
+        );
+        assert!(wat.contains("\n    block\n      loop\n        local.get $p\n"));
+    }
+
+    #[test]
+    fn binary_module_has_the_header_and_the_sections_in_order() {
+        let wasm = Program::parse("+.").compile(Target::Wasm, 10).unwrap();
+        assert_eq!(b"\0asm\x01\0\0\0", &wasm[..8]);
+        let mut ids = vec![];
+        let mut rest = &wasm[8..];
+        while let [id, size, contents @ ..] = rest {
+            ids.push(*id);
+            rest = &contents[*size as usize..];
+        }
+        assert_eq!(vec![1, 2, 3, 5, 7, 10], ids);
+    }
+
+    #[test]
+    fn modules_are_not_generated_for_tapes_bigger_than_the_linear_memory_can_address() {
+        let wat = generate_text_source(".", MAX_MEMORY_SIZE);
+        assert!(wat.contains(r#"(memory (export "memory") 32768)"#));
+        for target in [Target::Wat, Target::Wasm] {
+            assert!(matches!(
+                Program::parse(".").compile(target, MAX_MEMORY_SIZE + 1),
+                Err(BfError::TapeTooBig {
+                    max: MAX_MEMORY_SIZE,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn leb128_encodes_seven_bits_per_byte() {
+        let encode_unsigned = |value| {
+            let mut bytes = vec![];
+            unsigned_leb128(&mut bytes, value);
+            bytes
+        };
+        let encode_signed = |value| {
+            let mut bytes = vec![];
+            signed_leb128(&mut bytes, value);
+            bytes
+        };
+        assert_eq!(vec![0x7f], encode_unsigned(127));
+        assert_eq!(vec![0xe5, 0x8e, 0x26], encode_unsigned(624485));
+        assert_eq!(vec![0x3f], encode_signed(63));
+        assert_eq!(vec![0xc0, 0x00], encode_signed(64));
+        assert_eq!(vec![0x7f], encode_signed(-1));
+        assert_eq!(vec![0xc0, 0xbb, 0x78], encode_signed(-123456));
+    }
+}
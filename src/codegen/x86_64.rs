@@ -0,0 +1,483 @@
+use std::fmt::Write;
+
+use crate::bytecode::Code;
+
+use super::forward;
+
+/// Address the executable is loaded at
+const BASE_ADDRESS: u64 = 0x400000;
+const PAGE_SIZE: u64 = 0x1000;
+const ELF_HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+/// The code follows the ELF header and the program headers of the two segments, one for the code
+/// and one for the tape
+const CODE_OFFSET: u64 = ELF_HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+
+/// Number of cells of the biggest tape, whose cells are addressed with 32-bit offsets
+pub(crate) const MAX_MEMORY_SIZE: usize = i32::MAX as usize;
+
+/// Linux system calls used by the executable
+const SYS_READ: u8 = 0;
+const SYS_WRITE: u8 = 1;
+const SYS_EXIT: u8 = 60;
+
+/// Instruction of the executable, one or more x86-64 instructions, while it runs:
+/// - `rbx` points to the tape
+/// - `r12` is the index of the current cell
+/// - `r13` is the number of cells
+/// - `rax` is the index of the cell being accessed
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Instr {
+    /// Sets up the registers, the pointer starts at the first cell
+    Start,
+    /// The start of the bytecode instruction at the index, which a jump targets
+    Label(usize),
+    /// Sets `rax` to the index of the cell at the number of cells forward from the current one
+    CellIdx(u32),
+    /// Moves the pointer forward by the number of cells, wrapping around the tape
+    MoveBy(u32),
+    AddCell(u8),
+    ClearCell,
+    /// Sets `ecx` to the value of the cell multiplied by the factor
+    MultiplyCell(i32),
+    /// Adds the lowest byte of `ecx` to the cell
+    AddProduct,
+    /// Moves the pointer forward by the number of cells until the current cell is zero,
+    /// the index makes its labels unique
+    ScanZero(usize, u32),
+    Output,
+    /// Reads into the cell, which is left unchanged at the end of the input
+    Input,
+    JumpIfZero(usize),
+    JumpUnlessZero(usize),
+    Exit,
+}
+
+impl Instr {
+    fn lines(self) -> Vec<String> {
+        match self {
+            Instr::Start => vec![
+                String::from("lea rbx, [rip + tape]"),
+                String::from("xor r12d, r12d"),
+                String::from("mov r13, MEMORY_SIZE"),
+            ],
+            Instr::Label(idx) => vec![format!(".L{idx}:")],
+            Instr::CellIdx(0) => vec![String::from("mov rax, r12")],
+            Instr::CellIdx(forward) => vec![
+                format!("lea rax, [r12 + {forward}]"),
+                String::from("mov rdx, rax"),
+                String::from("sub rdx, r13"),
+                String::from("cmovae rax, rdx"),
+            ],
+            Instr::MoveBy(forward) => vec![
+                format!("add r12, {forward}"),
+                String::from("mov rdx, r12"),
+                String::from("sub rdx, r13"),
+                String::from("cmovae r12, rdx"),
+            ],
+            Instr::AddCell(value) => vec![format!("add byte ptr [rbx + rax], {value}")],
+            Instr::ClearCell => vec![String::from("mov byte ptr [rbx + rax], 0")],
+            Instr::MultiplyCell(factor) => vec![
+                String::from("movzx ecx, byte ptr [rbx + rax]"),
+                format!("imul ecx, ecx, {factor}"),
+            ],
+            Instr::AddProduct => vec![String::from("add byte ptr [rbx + rax], cl")],
+            Instr::ScanZero(idx, forward) => {
+                let mut lines = vec![format!(".Lscan{idx}:")];
+                lines.extend(Instr::CellIdx(0).lines());
+                lines.push(String::from("cmp byte ptr [rbx + rax], 0"));
+                lines.push(format!("je .Lfound{idx}"));
+                lines.extend(Instr::MoveBy(forward).lines());
+                lines.push(format!("jmp .Lscan{idx}"));
+                lines.push(format!(".Lfound{idx}:"));
+                lines
+            }
+            Instr::Output => vec![
+                String::from("lea rsi, [rbx + rax]"),
+                String::from("mov edi, 1"),
+                String::from("mov edx, 1"),
+                format!("mov eax, {SYS_WRITE}"),
+                String::from("syscall"),
+            ],
+            Instr::Input => vec![
+                String::from("lea rsi, [rbx + rax]"),
+                String::from("mov edi, 0"),
+                String::from("mov edx, 1"),
+                format!("mov eax, {SYS_READ}"),
+                String::from("syscall"),
+            ],
+            Instr::JumpIfZero(target) => vec![
+                String::from("mov rax, r12"),
+                String::from("cmp byte ptr [rbx + rax], 0"),
+                format!("je .L{target}"),
+            ],
+            Instr::JumpUnlessZero(target) => vec![
+                String::from("mov rax, r12"),
+                String::from("cmp byte ptr [rbx + rax], 0"),
+                format!("jne .L{target}"),
+            ],
+            Instr::Exit => vec![
+                format!("mov eax, {SYS_EXIT}"),
+                String::from("xor edi, edi"),
+                String::from("syscall"),
+            ],
+        }
+    }
+
+    fn encode(self, assembler: &mut Assembler) {
+        match self {
+            Instr::Start => {
+                assembler.emit(&[0x48, 0x8d, 0x1d]); // lea rbx, [rip + tape]
+                assembler.emit(&[0, 0, 0, 0]);
+                assembler.tape_displacement = assembler.bytes.len();
+                assembler.emit(&[0x45, 0x31, 0xe4]); // xor r12d, r12d
+                assembler.emit(&[0x49, 0xc7, 0xc5]); // mov r13, MEMORY_SIZE
+                assembler.emit(&(assembler.memory_size as u32).to_le_bytes());
+            }
+            Instr::Label(idx) => assembler.labels[idx] = assembler.bytes.len(),
+            Instr::CellIdx(0) => assembler.emit(&[0x4c, 0x89, 0xe0]), // mov rax, r12
+            Instr::CellIdx(forward) => {
+                assembler.emit(&[0x49, 0x8d, 0x84, 0x24]); // lea rax, [r12 + forward]
+                assembler.emit(&forward.to_le_bytes());
+                assembler.emit(&[0x48, 0x89, 0xc2]); // mov rdx, rax
+                assembler.emit(&[0x4c, 0x29, 0xea]); // sub rdx, r13
+                assembler.emit(&[0x48, 0x0f, 0x43, 0xc2]); // cmovae rax, rdx
+            }
+            Instr::MoveBy(forward) => {
+                assembler.emit(&[0x49, 0x81, 0xc4]); // add r12, forward
+                assembler.emit(&forward.to_le_bytes());
+                assembler.emit(&[0x4c, 0x89, 0xe2]); // mov rdx, r12
+                assembler.emit(&[0x4c, 0x29, 0xea]); // sub rdx, r13
+                assembler.emit(&[0x4c, 0x0f, 0x43, 0xe2]); // cmovae r12, rdx
+            }
+            Instr::AddCell(value) => assembler.emit(&[0x80, 0x04, 0x03, value]),
+            Instr::ClearCell => assembler.emit(&[0xc6, 0x04, 0x03, 0x00]),
+            Instr::MultiplyCell(factor) => {
+                assembler.emit(&[0x0f, 0xb6, 0x0c, 0x03]); // movzx ecx, byte ptr [rbx + rax]
+                assembler.emit(&[0x69, 0xc9]); // imul ecx, ecx, factor
+                assembler.emit(&factor.to_le_bytes());
+            }
+            Instr::AddProduct => assembler.emit(&[0x00, 0x0c, 0x03]),
+            Instr::ScanZero(_, forward) => {
+                let scan = assembler.bytes.len();
+                Instr::CellIdx(0).encode(assembler);
+                assembler.emit(&[0x80, 0x3c, 0x03, 0x00]); // cmp byte ptr [rbx + rax], 0
+                assembler.emit(&[0x0f, 0x84, 0, 0, 0, 0]); // je found
+                let found = assembler.bytes.len();
+                Instr::MoveBy(forward).encode(assembler);
+                assembler.emit(&[0xe9]); // jmp scan
+                assembler.emit(&[0, 0, 0, 0]);
+                let end = assembler.bytes.len();
+                assembler.patch_rel32(end, scan);
+                assembler.patch_rel32(found, end);
+            }
+            Instr::Output => assembler.syscall(SYS_WRITE, 1),
+            Instr::Input => assembler.syscall(SYS_READ, 0),
+            Instr::JumpIfZero(target) => {
+                Instr::CellIdx(0).encode(assembler);
+                assembler.emit(&[0x80, 0x3c, 0x03, 0x00]); // cmp byte ptr [rbx + rax], 0
+                assembler.emit(&[0x0f, 0x84]); // je target
+                assembler.jump_to(target);
+            }
+            Instr::JumpUnlessZero(target) => {
+                Instr::CellIdx(0).encode(assembler);
+                assembler.emit(&[0x80, 0x3c, 0x03, 0x00]); // cmp byte ptr [rbx + rax], 0
+                assembler.emit(&[0x0f, 0x85]); // jne target
+                assembler.jump_to(target);
+            }
+            Instr::Exit => {
+                assembler.emit(&[0xb8, SYS_EXIT, 0, 0, 0]); // mov eax, SYS_EXIT
+                assembler.emit(&[0x31, 0xff]); // xor edi, edi
+                assembler.emit(&[0x0f, 0x05]); // syscall
+            }
+        }
+    }
+}
+
+/// Instructions of the executable, with a label at each target of a jump
+fn instructions(code: &[Code], memory_size: usize) -> Vec<Instr> {
+    debug_assert!(memory_size <= MAX_MEMORY_SIZE, "checked before generating");
+    let cells_forward = |offset: isize| forward(offset, memory_size) as u32;
+    let mut targets = vec![false; code.len() + 1];
+    for instruction in code {
+        if let Code::JumpIfZero(target) | Code::JumpUnlessZero(target) = *instruction {
+            targets[target] = true;
+        }
+    }
+
+    let mut instructions = vec![Instr::Start];
+    for (idx, instruction) in code.iter().enumerate() {
+        if targets[idx] {
+            instructions.push(Instr::Label(idx));
+        }
+        match *instruction {
+            Code::Add { offset, delta, .. } => instructions.extend([
+                Instr::CellIdx(cells_forward(offset)),
+                Instr::AddCell(delta.rem_euclid(256) as u8),
+            ]),
+            Code::Move { offset, .. } => {
+                if cells_forward(offset) != 0 {
+                    instructions.push(Instr::MoveBy(cells_forward(offset)));
+                }
+            }
+            Code::SetZero => instructions.extend([Instr::CellIdx(0), Instr::ClearCell]),
+            Code::MulAdd { offset, factor } => instructions.extend([
+                Instr::CellIdx(0),
+                Instr::MultiplyCell(factor),
+                Instr::CellIdx(cells_forward(offset)),
+                Instr::AddProduct,
+            ]),
+            Code::ScanZero { step, .. } => {
+                instructions.push(Instr::ScanZero(idx, cells_forward(step)))
+            }
+            Code::Output { offset } => {
+                instructions.extend([Instr::CellIdx(cells_forward(offset)), Instr::Output]);
+            }
+            Code::Input { offset } => {
+                instructions.extend([Instr::CellIdx(cells_forward(offset)), Instr::Input]);
+            }
+            Code::Debug { .. } => {}
+            Code::JumpIfZero(target) => instructions.push(Instr::JumpIfZero(target)),
+            Code::JumpUnlessZero(target) => instructions.push(Instr::JumpUnlessZero(target)),
+        }
+    }
+    if targets[code.len()] {
+        instructions.push(Instr::Label(code.len()));
+    }
+    instructions.push(Instr::Exit);
+    instructions
+}
+
+/// Generates GNU assembler source in the Intel syntax for a static x86-64 Linux executable
+/// with a tape of `memory_size` bytes, which can be built with `as` and `ld`
+/// The pointer and the cells wrap around and `,` leaves the cell unchanged at the end of the input
+pub(crate) fn generate_assembly(code: &[Code], memory_size: usize) -> String {
+    let mut assembly = String::new();
+    assembly.push_str(".intel_syntax noprefix\n\n");
+    let _ = writeln!(assembly, ".set MEMORY_SIZE, {memory_size}");
+    assembly.push_str(".lcomm tape, MEMORY_SIZE\n\n");
+    assembly.push_str(".text\n.globl _start\n_start:\n");
+    for instruction in instructions(code, memory_size) {
+        for line in instruction.lines() {
+            if line.ends_with(':') {
+                let _ = writeln!(assembly, "{line}");
+            } else {
+                let _ = writeln!(assembly, "    {line}");
+            }
+        }
+    }
+    assembly
+}
+
+/// Generates the same program as `generate_assembly` as a static x86-64 Linux ELF executable,
+/// it is loaded with its code readable and executable, and the tape readable and writable
+pub(crate) fn generate_executable(code: &[Code], memory_size: usize) -> Vec<u8> {
+    let mut assembler = Assembler {
+        bytes: Vec::new(),
+        memory_size,
+        labels: vec![0; code.len() + 1],
+        jumps: Vec::new(),
+        tape_displacement: 0,
+    };
+    for instruction in instructions(code, memory_size) {
+        instruction.encode(&mut assembler);
+    }
+    for (end, target) in std::mem::take(&mut assembler.jumps) {
+        assembler.patch_rel32(end, assembler.labels[target]);
+    }
+
+    let file_size = CODE_OFFSET + assembler.bytes.len() as u64;
+    let tape_address = BASE_ADDRESS + file_size.next_multiple_of(PAGE_SIZE);
+    let end = assembler.tape_displacement;
+    let displacement = (tape_address - (BASE_ADDRESS + CODE_OFFSET + end as u64)) as u32;
+    assembler.bytes[end - 4..end].copy_from_slice(&displacement.to_le_bytes());
+
+    let mut executable = Vec::with_capacity(file_size as usize);
+    executable.extend(b"\x7fELF");
+    executable.extend([2, 1, 1, 0]); // 64-bit, little endian, version 1, System V
+    executable.extend([0; 8]);
+    executable.extend(2_u16.to_le_bytes()); // executable
+    executable.extend(0x3e_u16.to_le_bytes()); // x86-64
+    executable.extend(1_u32.to_le_bytes());
+    executable.extend((BASE_ADDRESS + CODE_OFFSET).to_le_bytes()); // entry point
+    executable.extend(ELF_HEADER_SIZE.to_le_bytes()); // program headers
+    executable.extend(0_u64.to_le_bytes()); // no section headers
+    executable.extend(0_u32.to_le_bytes());
+    executable.extend((ELF_HEADER_SIZE as u16).to_le_bytes());
+    executable.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    executable.extend(2_u16.to_le_bytes());
+    executable.extend([0; 6]);
+    program_header(&mut executable, 0b101, BASE_ADDRESS, file_size, file_size);
+    program_header(&mut executable, 0b110, tape_address, 0, memory_size as u64);
+    executable.extend(assembler.bytes);
+    executable
+}
+
+/// Appends the header of a loadable segment, with the flags for reading, writing and executing it,
+/// which starts at the beginning of the file
+fn program_header(executable: &mut Vec<u8>, flags: u32, address: u64, file_size: u64, size: u64) {
+    executable.extend(1_u32.to_le_bytes());
+    executable.extend(flags.to_le_bytes());
+    executable.extend(0_u64.to_le_bytes());
+    executable.extend(address.to_le_bytes());
+    executable.extend(address.to_le_bytes());
+    executable.extend(file_size.to_le_bytes());
+    executable.extend(size.to_le_bytes());
+    executable.extend(PAGE_SIZE.to_le_bytes());
+}
+
+struct Assembler {
+    bytes: Vec<u8>,
+    memory_size: usize,
+    /// Start of the machine code of each bytecode instruction which a jump targets, followed by the end
+    labels: Vec<usize>,
+    /// Jumps to labels, by the end of their 32-bit displacement
+    jumps: Vec<(usize, usize)>,
+    /// End of the displacement of the tape from the code
+    tape_displacement: usize,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Sets the 32-bit displacement ending at `end` to the target
+    fn patch_rel32(&mut self, end: usize, target: usize) {
+        let displacement = (target as i32).wrapping_sub(end as i32).to_le_bytes();
+        self.bytes[end - 4..end].copy_from_slice(&displacement);
+    }
+
+    /// Emits the 32-bit displacement of a jump to a label, patched once all of the code is assembled
+    fn jump_to(&mut self, label: usize) {
+        self.emit(&[0, 0, 0, 0]);
+        self.jumps.push((self.bytes.len(), label));
+    }
+
+    /// Reads or writes the cell at `rax` from or to the file descriptor
+    fn syscall(&mut self, number: u8, file_descriptor: u8) {
+        self.emit(&[0x48, 0x8d, 0x34, 0x03]); // lea rsi, [rbx + rax]
+        self.emit(&[0xbf, file_descriptor, 0, 0, 0]); // mov edi, file_descriptor
+        self.emit(&[0xba, 1, 0, 0, 0]); // mov edx, 1
+        self.emit(&[0xb8, number, 0, 0, 0]); // mov eax, number
+        self.emit(&[0x0f, 0x05]); // syscall
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codegen::Target, program::Program};
+
+    fn generate_assembly_source(source_code: &str, memory_size: usize) -> String {
+        let program = Program::parse(source_code);
+        String::from_utf8(
+            program
+                .compile(Target::X86_64LinuxAsm, memory_size)
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn body(assembly: &str) -> Vec<&str> {
+        let start = assembly.find("    mov r13, MEMORY_SIZE\n").unwrap()
+            + "    mov r13, MEMORY_SIZE\n".len();
+        let end = assembly.rfind("    mov eax, 60\n").unwrap();
+        assembly[start..end].lines().map(str::trim).collect()
+    }
+
+    #[test]
+    fn assembly_declares_the_tape_and_the_entry_point() {
+        let assembly = generate_assembly_source("", 300);
+        assert!(assembly.starts_with(".intel_syntax noprefix\n\n.set MEMORY_SIZE, 300\n"));
+        assert!(assembly.contains(".lcomm tape, MEMORY_SIZE\n"));
+        assert!(assembly.contains(".globl _start\n_start:\n    lea rbx, [rip + tape]\n"));
+        assert!(assembly.ends_with("    mov eax, 60\n    xor edi, edi\n    syscall\n"));
+    }
+
+    #[test]
+    fn assembly_wraps_cells_and_offsets_around() {
+        assert_eq!(
+            vec![
+                "lea rax, [r12 + 2]",
+                "mov rdx, rax",
+                "sub rdx, r13",
+                "cmovae rax, rdx",
+                "add byte ptr [rbx + rax], 255",
+                "add r12, 9",
+                "mov rdx, r12",
+                "sub rdx, r13",
+                "cmovae r12, rdx",
+            ],
+            body(&generate_assembly_source(">>-<<<", 10))
+        );
+    }
+
+    #[test]
+    fn assembly_reads_and_writes_with_syscalls() {
+        assert_eq!(
+            vec![
+                "mov rax, r12",
+                "lea rsi, [rbx + rax]",
+                "mov edi, 0",
+                "mov edx, 1",
+                "mov eax, 0",
+                "syscall",
+                "mov rax, r12",
+                "lea rsi, [rbx + rax]",
+                "mov edi, 1",
+                "mov edx, 1",
+                "mov eax, 1",
+                "syscall",
+            ],
+            body(&generate_assembly_source(",.", 10))
+        );
+    }
+
+    #[test]
+    fn assembly_jumps_to_labels_of_loops_and_scans() {
+        assert_eq!(
+            vec![
+                "mov rax, r12",
+                "cmp byte ptr [rbx + rax], 0",
+                "je .L4",
+                ".L1:",
+                ".Lscan1:",
+                "mov rax, r12",
+                "cmp byte ptr [rbx + rax], 0",
+                "je .Lfound1",
+                "add r12, 9",
+                "mov rdx, r12",
+                "sub rdx, r13",
+                "cmovae r12, rdx",
+                "jmp .Lscan1",
+                ".Lfound1:",
+                "mov rax, r12",
+                "add byte ptr [rbx + rax], 1",
+                "mov rax, r12",
+                "cmp byte ptr [rbx + rax], 0",
+                "jne .L1",
+                ".L4:",
+            ],
+            body(&generate_assembly_source("[[<]+]", 10))
+        );
+    }
+
+    #[test]
+    fn executable_is_a_static_elf_with_code_and_tape_segments() {
+        let executable = Program::parse("+.")
+            .compile(Target::X86_64Linux, 30000)
+            .unwrap();
+        let u64_at = |at: usize| u64::from_le_bytes(executable[at..at + 8].try_into().unwrap());
+        assert_eq!(b"\x7fELF\x02\x01\x01", &executable[..7]);
+        assert_eq!(BASE_ADDRESS + CODE_OFFSET, u64_at(24));
+        // the code segment covers the whole file
+        assert_eq!(5, executable[68]);
+        assert_eq!(executable.len() as u64, u64_at(64 + 32));
+        // the tape segment isn't in the file and starts on the page after it
+        assert_eq!(6, executable[124]);
+        assert_eq!(BASE_ADDRESS + PAGE_SIZE, u64_at(120 + 16));
+        assert_eq!(0, u64_at(120 + 32));
+        assert_eq!(30000, u64_at(120 + 40));
+    }
+}
@@ -0,0 +1,553 @@
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Read, Write},
+    str::FromStr,
+};
+
+use crate::{
+    cell::Cell,
+    debugger::{Debugger, StopReason, Watchpoint},
+    position::Position,
+};
+
+/// Number of cells shown on each side of the current one by `tape`, unless given
+const TAPE_RADIUS: usize = 5;
+
+const HELP: &str = "\
+step, s [COUNT]           execute the next instruction, or the next COUNT of them
+next, n                   execute the next instruction, or the whole loop if it starts one
+out, o                    execute the rest of the current loop
+continue, c               execute until a breakpoint or the end of the program
+back, k [COUNT]           go back before the last executed instruction, or the last COUNT of them
+lastwrite, lw CELL        go back to right after the previous write to the cell
+goto, g COUNT             go back or forward to right after COUNT executed instructions
+count                     show the number of executed instructions
+break, b LINE:COLUMN      stop at the first instruction at or after the position
+delete, d LINE:COLUMN     remove the breakpoint added at the position
+breakpoints, bl           list the breakpoints
+watch CELL                stop after the cell at the index is written
+watch CELL=VALUE          stop after the value is written to the cell
+watch FIRST-LAST          stop after the data pointer moves into the cells
+unwatch WATCHPOINT        remove the watchpoint, given like to watch
+watchpoints, wl           list the watchpoints
+tape, t [RADIUS]          show the cells around the data pointer, 5 on each side by default
+where, w                  show the position of the next instruction
+help, h                   show this help
+quit, q                   stop debugging
+";
+
+/// Command of the debugger console
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Step(usize),
+    Next,
+    Out,
+    Continue,
+    Back(usize),
+    LastWrite(usize),
+    Goto(usize),
+    Count,
+    Break(usize, usize),
+    Delete(usize, usize),
+    Breakpoints,
+    Watch(Watchpoint),
+    Unwatch(Watchpoint),
+    Watchpoints,
+    Tape(usize),
+    Where,
+    Help,
+    Quit,
+}
+
+/// Writer shared between the program being debugged and the console, so that the output of both is
+/// written in the order it happens
+pub(crate) struct SharedWriter<'o, W>(pub(crate) &'o RefCell<W>);
+
+impl<W> Write for SharedWriter<'_, W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Runs the interactive console of the `debug` subcommand, reading the commands line by line until
+/// `quit` or the end of the commands
+/// Failures of the program are reported and the console continues, so that its state can be inspected
+pub(crate) fn run<R, W, T, O>(
+    debugger: &mut Debugger<'_, '_, R, W, T>,
+    source_code: &str,
+    commands: &mut impl BufRead,
+    output: &RefCell<O>,
+) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    T: Cell,
+    O: Write,
+{
+    match debugger.position() {
+        Some(position) => write_location(&mut *output.borrow_mut(), source_code, position)?,
+        None => writeln!(output.borrow_mut(), "the program has no instructions")?,
+    }
+    let mut line = String::new();
+    loop {
+        write!(output.borrow_mut(), "(bf) ")?;
+        output.borrow_mut().flush()?;
+        line.clear();
+        if commands.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let command = match parse_command(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(message) => {
+                writeln!(output.borrow_mut(), "{message}")?;
+                continue;
+            }
+        };
+        let result = match command {
+            Command::Step(count) => {
+                let mut result = debugger.step();
+                for _ in 1..count {
+                    match result {
+                        Ok(StopReason::Step(_)) => result = debugger.step(),
+                        _ => break,
+                    }
+                }
+                result
+            }
+            Command::Next => debugger.step_over(),
+            Command::Out => debugger.step_out(),
+            Command::Continue => debugger.resume(),
+            Command::Back(count) => {
+                let mut reason = debugger.step_back();
+                for _ in 1..count {
+                    match reason {
+                        StopReason::Step(_) => reason = debugger.step_back(),
+                        _ => break,
+                    }
+                }
+                Ok(reason)
+            }
+            Command::LastWrite(cell) => match debugger.back_to_write(cell) {
+                StopReason::Watchpoint(_, executed) => {
+                    let mut output = output.borrow_mut();
+                    writeln!(
+                        output,
+                        "last write to cell {cell} by the instruction at {executed}"
+                    )?;
+                    match debugger.position() {
+                        Some(position) => write_location(&mut *output, source_code, position)?,
+                        None => writeln!(output, "the program has finished")?,
+                    }
+                    continue;
+                }
+                reason => Ok(reason),
+            },
+            Command::Goto(count) => debugger.go_to(count),
+            Command::Count => {
+                writeln!(
+                    output.borrow_mut(),
+                    "{} instructions executed",
+                    debugger.executed()
+                )?;
+                continue;
+            }
+            Command::Break(line, column) => {
+                match debugger.add_breakpoint(line, column) {
+                    Some(position) => writeln!(output.borrow_mut(), "breakpoint at {position}")?,
+                    None => writeln!(
+                        output.borrow_mut(),
+                        "no instruction at or after line {line}, column {column}"
+                    )?,
+                }
+                continue;
+            }
+            Command::Delete(line, column) => {
+                match debugger.remove_breakpoint(line, column) {
+                    Some(position) => {
+                        writeln!(output.borrow_mut(), "removed breakpoint at {position}")?
+                    }
+                    None => writeln!(
+                        output.borrow_mut(),
+                        "no breakpoint at line {line}, column {column}"
+                    )?,
+                }
+                continue;
+            }
+            Command::Breakpoints => {
+                let breakpoints = debugger.breakpoints();
+                if breakpoints.is_empty() {
+                    writeln!(output.borrow_mut(), "no breakpoints")?;
+                }
+                for position in breakpoints {
+                    writeln!(output.borrow_mut(), "breakpoint at {position}")?;
+                }
+                continue;
+            }
+            Command::Watch(watchpoint) => {
+                if debugger.add_watchpoint(watchpoint) {
+                    writeln!(output.borrow_mut(), "watching {watchpoint}")?;
+                } else {
+                    writeln!(output.borrow_mut(), "already watching {watchpoint}")?;
+                }
+                continue;
+            }
+            Command::Unwatch(watchpoint) => {
+                if debugger.remove_watchpoint(watchpoint) {
+                    writeln!(output.borrow_mut(), "stopped watching {watchpoint}")?;
+                } else {
+                    writeln!(output.borrow_mut(), "not watching {watchpoint}")?;
+                }
+                continue;
+            }
+            Command::Watchpoints => {
+                let watchpoints = debugger.watchpoints();
+                if watchpoints.is_empty() {
+                    writeln!(output.borrow_mut(), "no watchpoints")?;
+                }
+                for watchpoint in watchpoints {
+                    writeln!(output.borrow_mut(), "watching {watchpoint}")?;
+                }
+                continue;
+            }
+            Command::Tape(radius) => {
+                write!(output.borrow_mut(), "{}", debugger.memory().window(radius))?;
+                continue;
+            }
+            Command::Where => {
+                match debugger.position() {
+                    Some(position) => {
+                        write_location(&mut *output.borrow_mut(), source_code, position)?
+                    }
+                    None => writeln!(output.borrow_mut(), "the program has finished")?,
+                }
+                continue;
+            }
+            Command::Help => {
+                write!(output.borrow_mut(), "{HELP}")?;
+                continue;
+            }
+            Command::Quit => return Ok(()),
+        };
+        let mut output = output.borrow_mut();
+        match result {
+            Ok(StopReason::Step(position)) => write_location(&mut *output, source_code, position)?,
+            Ok(StopReason::Breakpoint(position)) => {
+                writeln!(output, "breakpoint hit")?;
+                write_location(&mut *output, source_code, position)?;
+            }
+            Ok(StopReason::Debug(position)) => {
+                writeln!(output, "debug instruction")?;
+                write_location(&mut *output, source_code, position)?;
+                write!(output, "{}", debugger.memory().window(TAPE_RADIUS))?;
+            }
+            Ok(StopReason::Watchpoint(watchpoint, executed)) => {
+                writeln!(
+                    output,
+                    "watchpoint hit: {watchpoint} by the instruction at {executed}"
+                )?;
+                match debugger.position() {
+                    Some(position) => write_location(&mut *output, source_code, position)?,
+                    None => writeln!(output, "the program has finished")?,
+                }
+            }
+            Ok(StopReason::Interrupted(position)) => {
+                writeln!(output, "interrupted")?;
+                write_location(&mut *output, source_code, position)?;
+            }
+            Ok(StopReason::Finished(outcome)) => writeln!(
+                output,
+                "the program has finished, {} bytes read and {} bytes written",
+                outcome.bytes_read, outcome.bytes_written
+            )?,
+            Ok(StopReason::Beginning) => {
+                writeln!(output, "back at the beginning of the program")?;
+                if let Some(position) = debugger.position() {
+                    write_location(&mut *output, source_code, position)?;
+                }
+            }
+            Err(error) => writeln!(output, "error: {error}")?,
+        }
+    }
+}
+
+/// Parses a line of the console into a command, `None` if the line is empty
+fn parse_command(line: &str) -> Result<Option<Command>, String> {
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        return Ok(None);
+    };
+    let argument = words.next();
+    if words.next().is_some() {
+        return Err(format!("too many arguments for '{name}'"));
+    }
+    let command = match (name, argument) {
+        ("step" | "s", None) => Command::Step(1),
+        ("step" | "s", Some(count)) => Command::Step(parse_count(count)?),
+        ("next" | "n", None) => Command::Next,
+        ("out" | "o", None) => Command::Out,
+        ("continue" | "c", None) => Command::Continue,
+        ("back" | "k", None) => Command::Back(1),
+        ("back" | "k", Some(count)) => Command::Back(parse_count(count)?),
+        ("lastwrite" | "lw", Some(cell)) => Command::LastWrite(parse_number(cell)?),
+        ("goto" | "g", Some(count)) => Command::Goto(parse_number(count)?),
+        ("count", None) => Command::Count,
+        ("break" | "b", Some(position)) => {
+            let (line, column) = parse_position(position)?;
+            Command::Break(line, column)
+        }
+        ("delete" | "d", Some(position)) => {
+            let (line, column) = parse_position(position)?;
+            Command::Delete(line, column)
+        }
+        ("breakpoints" | "bl", None) => Command::Breakpoints,
+        ("watch", Some(watchpoint)) => Command::Watch(parse_watchpoint(watchpoint)?),
+        ("unwatch", Some(watchpoint)) => Command::Unwatch(parse_watchpoint(watchpoint)?),
+        ("watchpoints" | "wl", None) => Command::Watchpoints,
+        ("tape" | "t", None) => Command::Tape(TAPE_RADIUS),
+        ("tape" | "t", Some(radius)) => Command::Tape(parse_number(radius)?),
+        ("where" | "w", None) => Command::Where,
+        ("help" | "h", None) => Command::Help,
+        ("quit" | "q", None) => Command::Quit,
+        ("break" | "b" | "delete" | "d", None) => {
+            return Err(format!("'{name}' needs a position, e.g. '{name} 1:5'"))
+        }
+        ("lastwrite" | "lw", None) => {
+            return Err(format!("'{name}' needs a cell, e.g. '{name} 3'"))
+        }
+        ("goto" | "g", None) => {
+            return Err(format!(
+                "'{name}' needs a number of executed instructions, e.g. '{name} 100'"
+            ))
+        }
+        ("watch" | "unwatch", None) => {
+            return Err(format!(
+                "'{name}' needs a cell, e.g. '{name} 3', '{name} 3=65' or '{name} 0-9'"
+            ))
+        }
+        (
+            "next" | "n" | "out" | "o" | "continue" | "c" | "count" | "breakpoints" | "bl"
+            | "watchpoints" | "wl" | "where" | "w" | "help" | "h" | "quit" | "q",
+            Some(_),
+        ) => return Err(format!("too many arguments for '{name}'")),
+        _ => return Err(format!("unknown command '{name}', try 'help'")),
+    };
+    Ok(Some(command))
+}
+
+fn parse_number<N: FromStr>(number: &str) -> Result<N, String> {
+    number
+        .parse()
+        .map_err(|_| format!("'{number}' is not a number"))
+}
+
+/// Parses the number of times to repeat a command, which has to be at least one
+fn parse_count(count: &str) -> Result<usize, String> {
+    match parse_number(count)? {
+        0 => Err(String::from("the count has to be at least 1")),
+        count => Ok(count),
+    }
+}
+
+/// Parses a watchpoint given as `CELL`, `CELL=VALUE` or `FIRST-LAST`
+fn parse_watchpoint(watchpoint: &str) -> Result<Watchpoint, String> {
+    if let Some((cell, value)) = watchpoint.split_once('=') {
+        return Ok(Watchpoint::Value(parse_number(cell)?, parse_number(value)?));
+    }
+    if let Some((first, last)) = watchpoint.split_once('-') {
+        let (first, last) = (parse_number(first)?, parse_number(last)?);
+        if first > last {
+            return Err(format!("'{watchpoint}' is an empty range of cells"));
+        }
+        return Ok(Watchpoint::Pointer(first, last));
+    }
+    Ok(Watchpoint::Write(parse_number(watchpoint)?))
+}
+
+/// Parses a position in the source code given as `LINE:COLUMN`
+fn parse_position(position: &str) -> Result<(usize, usize), String> {
+    position
+        .split_once(':')
+        .and_then(|(line, column)| Some((line.parse().ok()?, column.parse().ok()?)))
+        .ok_or_else(|| format!("'{position}' is not a position, e.g. '1:5'"))
+}
+
+/// Writes the position of an instruction and its line of the source code, marking its column
+fn write_location(
+    output: &mut impl Write,
+    source_code: &str,
+    position: Position,
+) -> io::Result<()> {
+    let start = source_code[..position.offset]
+        .rfind('\n')
+        .map_or(0, |idx| idx + 1);
+    let end = source_code[position.offset..]
+        .find('\n')
+        .map_or(source_code.len(), |idx| position.offset + idx);
+    let line = source_code[start..end].trim_end().replace('\t', " ");
+    writeln!(output, "at {position}")?;
+    writeln!(output, "  {line}")?;
+    writeln!(output, "  {}^", " ".repeat(position.column - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        interpreter::Interpreter,
+        policy::OverflowPolicy,
+        program::{Parser, Program},
+    };
+
+    fn debug(source_code: &str, commands: &str) -> String {
+        let output = RefCell::new(vec![]);
+        let program = Program::parse(source_code);
+        let mut input = Cursor::new(b"a".to_vec());
+        let mut program_output = SharedWriter(&output);
+        let mut interpreter = Interpreter::new(&mut input, &mut program_output, 20)
+            .with_overflow_policy(OverflowPolicy::Trap);
+        let mut debugger = interpreter.debug(&program).with_history(100);
+        let mut commands = Cursor::new(commands.as_bytes());
+        run(&mut debugger, source_code, &mut commands, &output).unwrap();
+        drop(interpreter);
+        String::from_utf8(output.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn console_parses_commands_with_their_arguments() {
+        assert_eq!(Ok(None), parse_command("  \n"));
+        assert_eq!(Ok(Some(Command::Step(1))), parse_command("s\n"));
+        assert_eq!(Ok(Some(Command::Step(3))), parse_command("step 3"));
+        assert_eq!(Ok(Some(Command::Break(2, 7))), parse_command("b 2:7"));
+        assert_eq!(Ok(Some(Command::Tape(5))), parse_command("tape"));
+        assert_eq!(Ok(Some(Command::Tape(2))), parse_command("t 2"));
+        assert_eq!(
+            Err(String::from("'2' is not a position, e.g. '1:5'")),
+            parse_command("break 2")
+        );
+        assert_eq!(
+            Err(String::from("too many arguments for 'next'")),
+            parse_command("next 2")
+        );
+        assert_eq!(
+            Err(String::from("unknown command 'jump', try 'help'")),
+            parse_command("jump")
+        );
+        assert_eq!(
+            Ok(Some(Command::Watch(Watchpoint::Write(3)))),
+            parse_command("watch 3")
+        );
+        assert_eq!(
+            Ok(Some(Command::Watch(Watchpoint::Value(3, 65)))),
+            parse_command("watch 3=65")
+        );
+        assert_eq!(
+            Ok(Some(Command::Unwatch(Watchpoint::Pointer(0, 9)))),
+            parse_command("unwatch 0-9")
+        );
+        assert_eq!(
+            Err(String::from("'9-0' is an empty range of cells")),
+            parse_command("watch 9-0")
+        );
+        assert_eq!(Ok(Some(Command::Back(2))), parse_command("k 2"));
+        assert_eq!(
+            Err(String::from("the count has to be at least 1")),
+            parse_command("s 0")
+        );
+        assert_eq!(
+            Err(String::from("the count has to be at least 1")),
+            parse_command("back 0")
+        );
+        assert_eq!(Ok(Some(Command::LastWrite(3))), parse_command("lw 3"));
+        assert_eq!(Ok(Some(Command::Goto(100))), parse_command("goto 100"));
+    }
+
+    #[test]
+    fn console_shows_the_location_of_the_next_instruction() {
+        let output = debug("++\n >[-]", "s 2\nwhere\n");
+        assert!(output.starts_with("at line 1, column 1\n  ++\n  ^\n(bf) "));
+        assert!(output.contains("(bf) at line 2, column 2\n   >[-]\n   ^\n"));
+        assert!(output.ends_with("(bf) at line 2, column 2\n   >[-]\n   ^\n(bf) "));
+    }
+
+    #[test]
+    fn console_shows_the_tape_around_the_pointer() {
+        let output = debug(">>+++>,", "c\nt 2\nt\n");
+        assert!(output.contains("(bf)    1: 0\n   2: 3\n-> 3: 97\n   4: 0\n   5: 0\n(bf) "));
+        assert!(output.contains("(bf)    0: 0\n   1: 0\n   2: 3\n-> 3: 97\n   4: 0\n"));
+    }
+
+    #[test]
+    fn console_stops_at_breakpoints_and_reports_the_end_of_the_program() {
+        let output = debug("+[.-]\n+.", "b 1:4\nb 3:1\nbl\nc\nc\nd 1:4\nc\nq\ns\n");
+        assert!(output.contains("(bf) breakpoint at line 1, column 4\n"));
+        assert!(output.contains("(bf) no instruction at or after line 3, column 1\n"));
+        assert!(output.contains("(bf) breakpoint at line 1, column 4\n(bf) \u{1}breakpoint hit\n"));
+        assert!(output.contains("(bf) removed breakpoint at line 1, column 4\n"));
+        assert!(output.contains(
+            "(bf) \u{1}the program has finished, 0 bytes read and 2 bytes written\n(bf) "
+        ));
+        assert!(output.ends_with("(bf) "));
+    }
+
+    #[test]
+    fn console_reports_failures_of_the_program() {
+        let output = debug("+\n--", "c\nw\ns\n");
+        assert!(output.contains("(bf) error: cell overflow at line 2, column 2\n"));
+        assert!(output.contains("(bf) at line 2, column 2\n  --\n   ^\n(bf) error"));
+    }
+
+    #[test]
+    fn console_pauses_at_debug_instructions_and_shows_the_tape() {
+        let output = RefCell::new(vec![]);
+        let program = Parser::new().with_debug_char('#').parse("+>++#").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut program_output = SharedWriter(&output);
+        let mut interpreter = Interpreter::new(&mut input, &mut program_output, 3);
+        let mut debugger = interpreter.debug(&program);
+        let mut commands = Cursor::new(b"c\nc\n".as_slice());
+        run(&mut debugger, "+>++#", &mut commands, &output).unwrap();
+        drop(interpreter);
+        let output = String::from_utf8(output.into_inner()).unwrap();
+        assert!(output.contains(
+            "(bf) debug instruction\nat line 1, column 5\n  +>++#\n      ^\n   0: 1\n-> 1: 2\n   2: 0\n(bf) the program"
+        ));
+    }
+
+    #[test]
+    fn console_stops_at_watchpoints() {
+        let output = debug(
+            "+>++<[->+<]>.",
+            "watch 1=3\nwatch 1\nwl\nc\nunwatch 1\nwatch 1-1\nc\n",
+        );
+        assert!(output.contains("(bf) watching cell 1 set to 3\n(bf) watching write to cell 1\n"));
+        assert!(output.contains(
+            "(bf) watching cell 1 set to 3\nwatching write to cell 1\n(bf) watchpoint hit: write to cell 1 by the instruction at line 1, column 3\nat line 1, column 4\n"
+        ));
+        assert!(output.contains("(bf) stopped watching write to cell 1\n"));
+        assert!(output.contains(
+            "(bf) watchpoint hit: pointer entering cells 1 to 1 by the instruction at line 1, column 8\n"
+        ));
+    }
+
+    #[test]
+    fn console_goes_back_in_time() {
+        let output = debug("+>+++[-<+>]", "c\ncount\nk 2\nlw 0\ng 1\nt 1\nk 3\n");
+        assert!(output.contains("(bf) 18 instructions executed\n"));
+        assert!(output.contains("(bf) at line 1, column 9\n"));
+        assert!(output.contains(
+            "(bf) last write to cell 0 by the instruction at line 1, column 9\nat line 1, column 10\n"
+        ));
+        assert!(output.contains("(bf) at line 1, column 2\n  +>+++[-<+>]\n   ^\n(bf) -> 0: 1\n"));
+        assert!(output.contains("(bf) back at the beginning of the program\nat line 1, column 1\n"));
+    }
+}
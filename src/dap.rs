@@ -0,0 +1,527 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead, Cursor, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    cell::Cell,
+    console::SharedWriter,
+    debugger::{Debugger, StopReason},
+    error::BfError,
+    file_error,
+    interpreter::Interpreter,
+    parse_source,
+    program::Program,
+    read_file_contents, DapArgs,
+};
+
+/// The only thread, as programs are executed by a single one
+const THREAD_ID: u64 = 1;
+
+/// Reference of the variables of the memory tape, one for each cell
+const TAPE_REFERENCE: u64 = 1;
+
+/// Reference of the variables of the current cell, its index and value
+const CURRENT_CELL_REFERENCE: u64 = 2;
+
+/// Program to debug, given by the `launch` request
+struct Launch {
+    path: String,
+    program: Program,
+    input: Vec<u8>,
+    stop_on_entry: bool,
+}
+
+/// Requests sent by the thread reading them, after the ones received before the program was launched
+struct Requests {
+    receiver: Receiver<io::Result<Option<Value>>>,
+    deferred: VecDeque<Value>,
+}
+
+impl Requests {
+    /// Next request to handle, `None` at the end of the input
+    fn next(&mut self) -> io::Result<Option<Value>> {
+        match self.deferred.pop_front() {
+            Some(request) => Ok(Some(request)),
+            None => self.receiver.recv().unwrap_or(Ok(None)),
+        }
+    }
+}
+
+/// Client of the adapter, which sends it requests and receives responses and events
+struct Client<'o, O> {
+    output: &'o mut O,
+    /// Sequence number of the last message sent to the client
+    seq: u64,
+}
+
+impl<O> Client<'_, O>
+where
+    O: Write,
+{
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let content = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{content}",
+            content.len()
+        )?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+}
+
+/// Runs the Debug Adapter Protocol server of the `dap` subcommand, reading the requests from the input and
+/// writing the responses and events to the output until the client disconnects or the input ends
+/// The program is given by the `launch` request, with its input read from the file given as `input`,
+/// and its output is sent in `output` events
+/// Lines and columns start from 1, and the requests are read by another thread, so a running program
+/// is stopped before its next instruction when pausing or disconnecting
+pub(crate) fn run<T: Cell>(
+    args: &DapArgs,
+    requests: &mut (impl BufRead + Send),
+    output: &mut impl Write,
+) -> io::Result<()> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(|| read_requests(requests, sender, &interrupt));
+        let requests = Requests {
+            receiver,
+            deferred: VecDeque::new(),
+        };
+        serve::<T>(args, requests, &interrupt, &mut Client { output, seq: 0 })
+    })
+}
+
+/// Reads the requests and sends them to be handled, setting the interrupt for the ones stopping a running
+/// program, until the client disconnects or the input ends
+fn read_requests(
+    input: &mut impl BufRead,
+    sender: Sender<io::Result<Option<Value>>>,
+    interrupt: &AtomicBool,
+) {
+    loop {
+        let message = read_message(input);
+        let command = match &message {
+            Ok(Some(request)) => request["command"].as_str().unwrap_or_default(),
+            _ => "",
+        };
+        if matches!(command, "pause" | "disconnect" | "terminate") {
+            interrupt.store(true, Ordering::Relaxed);
+        }
+        let last = matches!(command, "" | "disconnect" | "terminate");
+        if sender.send(message).is_err() || last {
+            return;
+        }
+    }
+}
+
+/// Handles the requests until the program is launched, deferring the configuration ones until then
+fn serve<T: Cell>(
+    args: &DapArgs,
+    mut requests: Requests,
+    interrupt: &Arc<AtomicBool>,
+    client: &mut Client<'_, impl Write>,
+) -> io::Result<()> {
+    let mut deferred = Some(VecDeque::new());
+    while let Some(request) = requests.next()? {
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                client.respond(
+                    &request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                    }),
+                )?;
+                client.event("initialized", json!({}))?;
+            }
+            "launch" => match launch(args, &request["arguments"]) {
+                Ok(launch) => {
+                    client.respond(&request, json!({}))?;
+                    requests.deferred = deferred.unwrap_or_default();
+                    return debug::<T>(args, &launch, requests, interrupt, client);
+                }
+                Err(message) => {
+                    client.fail(&request, &message)?;
+                    for request in deferred.take().into_iter().flatten() {
+                        client.fail(&request, "no program has been launched")?;
+                    }
+                }
+            },
+            "disconnect" | "terminate" => return client.respond(&request, json!({})),
+            command => match &mut deferred {
+                Some(deferred)
+                    if matches!(
+                        command,
+                        "setBreakpoints" | "setExceptionBreakpoints" | "configurationDone"
+                    ) =>
+                {
+                    deferred.push_back(request)
+                }
+                _ => client.fail(&request, "no program has been launched")?,
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Reads and parses the program given by the arguments of the `launch` request, together with its input
+fn launch(args: &DapArgs, arguments: &Value) -> Result<Launch, String> {
+    let Some(path) = arguments["program"].as_str() else {
+        return Err(String::from("the path of the program is missing"));
+    };
+    let program = parse_source(&read_file_contents(path)?, args.strict, args.debug_char)?;
+    let input = match arguments["input"].as_str() {
+        Some(input_file) => fs::read(input_file).map_err(|error| file_error(input_file, error))?,
+        None => vec![],
+    };
+    Ok(Launch {
+        path: String::from(path),
+        program,
+        input,
+        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or_default(),
+    })
+}
+
+/// Handles the requests for the launched program until the client disconnects or the input ends
+fn debug<T: Cell>(
+    args: &DapArgs,
+    launch: &Launch,
+    mut requests: Requests,
+    interrupt: &Arc<AtomicBool>,
+    client: &mut Client<'_, impl Write>,
+) -> io::Result<()> {
+    let mut program_input = Cursor::new(launch.input.as_slice());
+    let program_output = RefCell::new(vec![]);
+    let mut shared_output = SharedWriter(&program_output);
+    let mut interpreter = Interpreter::<_, _, T>::with_cell_type(
+        &mut program_input,
+        &mut shared_output,
+        args.memory_size,
+    )
+    .with_eof_policy(args.eof)
+    .with_output_encoding(args.output_encoding)
+    .with_overflow_policy(args.overflow)
+    .with_boundary_policy(args.boundary);
+    let mut debugger = interpreter
+        .debug(&launch.program)
+        .with_interrupt(Arc::clone(interrupt));
+    let source = json!({
+        "name": Path::new(&launch.path).file_name().map(|name| name.to_string_lossy()),
+        "path": launch.path,
+    });
+
+    while let Some(request) = requests.next()? {
+        let arguments = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+        if command == "pause" {
+            interrupt.store(false, Ordering::Relaxed);
+        }
+        let result = match command {
+            "setBreakpoints" => {
+                for position in debugger.breakpoints() {
+                    debugger.remove_breakpoint(position.line, position.column);
+                }
+                let breakpoints: Vec<Value> = arguments["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|breakpoint| {
+                        let line = breakpoint["line"].as_u64().unwrap_or(1) as usize;
+                        let column = breakpoint["column"].as_u64().unwrap_or(1) as usize;
+                        match debugger.add_breakpoint(line, column) {
+                            Some(position) => json!({
+                                "verified": true,
+                                "source": source,
+                                "line": position.line,
+                                "column": position.column,
+                            }),
+                            None => json!({
+                                "verified": false,
+                                "message": "no instruction at or after the position",
+                            }),
+                        }
+                    })
+                    .collect();
+                client.respond(&request, json!({ "breakpoints": breakpoints }))?;
+                continue;
+            }
+            "configurationDone" => {
+                client.respond(&request, json!({}))?;
+                if launch.stop_on_entry && !debugger.is_finished() {
+                    stopped(client, "entry", None)?;
+                    continue;
+                }
+                debugger.resume()
+            }
+            "threads" => {
+                client.respond(
+                    &request,
+                    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+                )?;
+                continue;
+            }
+            "stackTrace" => {
+                let frames = stack_frames(&debugger, &source);
+                client.respond(
+                    &request,
+                    json!({ "stackFrames": frames, "totalFrames": frames.len() }),
+                )?;
+                continue;
+            }
+            "scopes" => {
+                client.respond(
+                    &request,
+                    json!({
+                        "scopes": [
+                            {
+                                "name": "Current cell",
+                                "variablesReference": CURRENT_CELL_REFERENCE,
+                                "namedVariables": 2,
+                                "expensive": false,
+                            },
+                            {
+                                "name": "Tape",
+                                "variablesReference": TAPE_REFERENCE,
+                                "indexedVariables": debugger.memory().cells().len(),
+                                "expensive": false,
+                            },
+                        ]
+                    }),
+                )?;
+                continue;
+            }
+            "variables" => {
+                let variables = variables(&debugger, arguments);
+                client.respond(&request, json!({ "variables": variables }))?;
+                continue;
+            }
+            "continue" => {
+                client.respond(&request, json!({ "allThreadsContinued": true }))?;
+                debugger.resume()
+            }
+            "next" => {
+                client.respond(&request, json!({}))?;
+                debugger.step_over()
+            }
+            "stepIn" => {
+                client.respond(&request, json!({}))?;
+                debugger.step()
+            }
+            "stepOut" => {
+                client.respond(&request, json!({}))?;
+                debugger.step_out()
+            }
+            "pause" if debugger.is_finished() => {
+                client.fail(&request, "the program has finished")?;
+                continue;
+            }
+            "pause" => {
+                client.respond(&request, json!({}))?;
+                stopped(client, "pause", None)?;
+                continue;
+            }
+            "setExceptionBreakpoints" => {
+                client.respond(&request, json!({ "breakpoints": [] }))?;
+                continue;
+            }
+            "disconnect" | "terminate" => return client.respond(&request, json!({})),
+            command => {
+                client.fail(&request, &format!("unsupported request '{command}'"))?;
+                continue;
+            }
+        };
+        report(client, &program_output, result)?;
+    }
+    Ok(())
+}
+
+/// Sends the output of the program written since the last time, then why the debugger stopped
+fn report(
+    client: &mut Client<'_, impl Write>,
+    program_output: &RefCell<Vec<u8>>,
+    result: Result<StopReason, BfError>,
+) -> io::Result<()> {
+    let written = program_output.take();
+    if !written.is_empty() {
+        client.event(
+            "output",
+            json!({
+                "category": "stdout",
+                "output": String::from_utf8_lossy(&written),
+            }),
+        )?;
+    }
+    match result {
+        Ok(StopReason::Step(_)) => stopped(client, "step", None),
+        Ok(StopReason::Breakpoint(_)) => stopped(client, "breakpoint", None),
+        Ok(StopReason::Debug(_)) => stopped(client, "pause", Some("debug instruction")),
+        Ok(StopReason::Watchpoint(watchpoint, _)) => {
+            stopped(client, "data breakpoint", Some(&watchpoint.to_string()))
+        }
+        Ok(StopReason::Beginning) => stopped(client, "entry", None),
+        // stopped by the request interrupting it, which reports it when handled
+        Ok(StopReason::Interrupted(_)) => Ok(()),
+        Ok(StopReason::Finished(_)) => {
+            client.event("exited", json!({ "exitCode": 0 }))?;
+            client.event("terminated", json!({}))
+        }
+        Err(error) => {
+            let error = error.to_string();
+            client.event(
+                "output",
+                json!({ "category": "stderr", "output": format!("error: {error}\n") }),
+            )?;
+            stopped(client, "exception", Some(&error))
+        }
+    }
+}
+
+fn stopped(
+    client: &mut Client<'_, impl Write>,
+    reason: &str,
+    text: Option<&str>,
+) -> io::Result<()> {
+    client.event(
+        "stopped",
+        json!({
+            "reason": reason,
+            "text": text,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        }),
+    )
+}
+
+/// Frames of the loops around the next instruction, from the innermost one, each at the position it is
+/// executing, and the whole program around them
+fn stack_frames<R, W, T>(debugger: &Debugger<'_, '_, R, W, T>, source: &Value) -> Vec<Value>
+where
+    R: Read,
+    W: Write,
+    T: Cell,
+{
+    let Some(position) = debugger.position() else {
+        return vec![];
+    };
+    let loops = debugger.loops();
+    let names = loops
+        .iter()
+        .map(|start| format!("loop at {start}"))
+        .chain([String::from("program")]);
+    let positions = [position].into_iter().chain(loops.iter().copied());
+    names
+        .zip(positions)
+        .enumerate()
+        .map(|(id, (name, position))| {
+            json!({
+                "id": id,
+                "name": name,
+                "source": source,
+                "line": position.line,
+                "column": position.column,
+            })
+        })
+        .collect()
+}
+
+/// Variables of the reference given by the arguments of the `variables` request, the cells of the tape are
+/// paged by the `start` and `count` arguments
+fn variables<R, W, T>(debugger: &Debugger<'_, '_, R, W, T>, arguments: &Value) -> Vec<Value>
+where
+    R: Read,
+    W: Write,
+    T: Cell,
+{
+    let memory = debugger.memory();
+    let variable = |name: String, value: u64| json!({ "name": name, "value": value.to_string(), "variablesReference": 0 });
+    match arguments["variablesReference"].as_u64() {
+        Some(TAPE_REFERENCE) => {
+            let cells = memory.cells();
+            let start = (arguments["start"].as_u64().unwrap_or(0) as usize).min(cells.len());
+            let end = match arguments["count"].as_u64() {
+                Some(count) if count > 0 => (start + count as usize).min(cells.len()),
+                _ => cells.len(),
+            };
+            (start..end)
+                .map(|idx| variable(idx.to_string(), cells[idx].to_u64()))
+                .collect()
+        }
+        Some(CURRENT_CELL_REFERENCE) => vec![
+            variable(String::from("index"), memory.pointer() as u64),
+            variable(String::from("value"), memory.read().to_u64()),
+        ],
+        _ => vec![],
+    }
+}
+
+/// Reads a message of the protocol, its headers followed by its JSON content, `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length header",
+        ));
+    };
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    Ok(Some(serde_json::from_slice(&content)?))
+}
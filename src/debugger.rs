@@ -0,0 +1,1059 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt,
+    io::{Read, Write},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use crate::{
+    bytecode::Code,
+    cell::Cell,
+    error::BfError,
+    interpreter::{ExecutionOutcome, Interpreter},
+    memory::Memory,
+    position::Position,
+    program::{Instruction, Node, Program},
+};
+
+/// Why the debugger stopped executing the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StopReason {
+    /// The requested step was completed, the next instruction is the one at the position
+    Step(Position),
+    /// The next instruction, at the position, has a breakpoint
+    Breakpoint(Position),
+    /// The next instruction, at the position, is a debug instruction, which only pauses the debugger
+    Debug(Position),
+    /// The watchpoint was triggered by the instruction at the position
+    Watchpoint(Watchpoint, Position),
+    /// The program has finished
+    Finished(ExecutionOutcome),
+    /// There is nothing to go back to, the program is at its beginning or at the oldest instruction
+    /// kept in the history
+    Beginning,
+    /// The flag given to [`Debugger::with_interrupt`] was set, the next instruction is the one at the position
+    Interrupted(Position),
+}
+
+/// What the debugger watches for, to stop right after the instruction that does it
+/// The cells are given by their index on the tape, growing the tape to the left shifts the indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Watchpoint {
+    /// Any write to the cell at the index, even of the value it already holds
+    Write(usize),
+    /// A write of the value to the cell at the index
+    Value(usize, u64),
+    /// A move of the data pointer from outside of the cells from the first to the last index into them
+    Pointer(usize, usize),
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Write(idx) => write!(f, "write to cell {idx}"),
+            Watchpoint::Value(idx, value) => write!(f, "cell {idx} set to {value}"),
+            Watchpoint::Pointer(first, last) => {
+                write!(f, "pointer entering cells {first} to {last}")
+            }
+        }
+    }
+}
+
+/// The changes made by an executed instruction, together with the ends of the loops right after it,
+/// enough to undo it or to replay it without executing it again
+#[derive(Debug, Clone, Copy)]
+struct JournalEntry<T> {
+    pc: usize,
+    next_pc: usize,
+    pointer: usize,
+    next_pointer: usize,
+    /// The cell written by the instruction, with its old and new value
+    write: Option<(usize, T, T)>,
+    /// Number of cells the tape grew with when the instruction moved the pointer off it
+    grown: usize,
+}
+
+/// Debugger executing a program an instruction at a time, created by [`Interpreter::debug`]
+/// The program isn't optimized, so it stops at every instruction of the source code, except the ends of
+/// the loops, which are executed together with the instruction before them
+/// Debug instructions pause it, like breakpoints, instead of showing the cells around the current one
+///
+/// With [`Debugger::with_history`] the last executed instructions are recorded in a journal, so the debugger
+/// can also go back in time over them
+/// Going back restores the cells, the data pointer and the next instruction, but not the input and output,
+/// instead the instructions gone back over are replayed from the journal when going forward again, without
+/// reading the input or writing the output a second time
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use vtashkov_bf::{Interpreter, Program, StopReason};
+///
+/// let program = Program::parse("++[->+<]>.");
+/// let mut input = Cursor::new(vec![]);
+/// let mut output = vec![];
+/// let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+/// let mut debugger = interpreter.debug(&program).with_history(100);
+/// debugger.step().unwrap();
+/// debugger.step().unwrap();
+/// assert_eq!(2, *debugger.memory().read());
+/// let breakpoint = debugger.add_breakpoint(1, 9).unwrap();
+/// assert_eq!(StopReason::Breakpoint(breakpoint), debugger.resume().unwrap());
+/// assert_eq!((0, 2), (debugger.memory().pointer(), *debugger.memory().read_at(1)));
+/// debugger.back_to_write(1);
+/// debugger.back_to_write(1);
+/// assert_eq!((6, 1), (debugger.executed(), debugger.memory().cells()[1]));
+/// ```
+pub struct Debugger<'d, 'a, R, W, T>
+where
+    R: Read,
+    W: Write,
+    T: Cell,
+{
+    interpreter: &'d mut Interpreter<'a, R, W, T>,
+    code: Vec<Code>,
+    /// Position of each instruction, `None` for the ends of the loops, whose position isn't known
+    positions: Vec<Option<Position>>,
+    /// Index of the start of the innermost loop around each instruction
+    enclosing_loops: Vec<Option<usize>>,
+    /// Index of the next instruction to execute
+    pc: usize,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    /// The cells written by the current instruction with their new values, reported by the memory's write hook
+    writes: Arc<Mutex<Vec<(usize, T)>>>,
+    outcome: Option<ExecutionOutcome>,
+    /// The last executed instructions, the ones after the executed ones were gone back over
+    journal: VecDeque<JournalEntry<T>>,
+    /// Number of executed instructions before the first one in the journal
+    journal_start: usize,
+    /// Most instructions kept in the journal
+    history: usize,
+    /// Number of executed instructions
+    executed: usize,
+    /// Whether anything was executed, before which resuming can stop at the first instruction
+    started: bool,
+    /// Set from elsewhere, e.g. another thread, to stop executing
+    interrupt: Option<Arc<AtomicBool>>,
+}
+
+impl<'d, 'a, R, W, T> Debugger<'d, 'a, R, W, T>
+where
+    R: Read,
+    W: Write,
+    T: Cell,
+{
+    pub(crate) fn new(
+        interpreter: &'d mut Interpreter<'a, R, W, T>,
+        program: &Program,
+    ) -> Debugger<'d, 'a, R, W, T> {
+        let (code, positions, enclosing_loops) = compile_unoptimized(program.nodes());
+        interpreter.reset();
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let hook_writes = Arc::clone(&writes);
+        interpreter.memory_mut().set_write_hook(move |idx, value| {
+            hook_writes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((idx, value))
+        });
+        Debugger {
+            interpreter,
+            code,
+            positions,
+            enclosing_loops,
+            pc: 0,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            writes,
+            outcome: None,
+            journal: VecDeque::new(),
+            journal_start: 0,
+            history: 0,
+            executed: 0,
+            started: false,
+            interrupt: None,
+        }
+    }
+
+    /// Sets the number of the last executed instructions the debugger can go back over, none by default,
+    /// as each of them takes memory to record
+    pub fn with_history(mut self, history: usize) -> Debugger<'d, 'a, R, W, T> {
+        self.history = history;
+        self
+    }
+
+    /// Sets a flag that stops executing instructions while it is set, e.g. by another thread to pause
+    /// a running program, which is then stopped before the next instruction until the flag is cleared
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Debugger<'d, 'a, R, W, T> {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Position of the next instruction to execute, `None` if the program has finished
+    pub fn position(&self) -> Option<Position> {
+        self.positions.get(self.pc).copied().flatten()
+    }
+
+    /// The memory tape of the program being debugged
+    pub fn memory(&self) -> &Memory<T> {
+        self.interpreter.memory()
+    }
+
+    /// Checks if the program has finished, after which the debugger doesn't execute anything
+    pub fn is_finished(&self) -> bool {
+        self.pc == self.code.len()
+    }
+
+    /// Positions of the starts of the loops around the next instruction, from the innermost one
+    pub fn loops(&self) -> Vec<Position> {
+        let mut loops = Vec::new();
+        let mut enclosing_loop = self.enclosing_loops.get(self.pc).copied().flatten();
+        while let Some(start) = enclosing_loop {
+            loops.extend(self.positions[start]);
+            enclosing_loop = self.enclosing_loops[start];
+        }
+        loops
+    }
+
+    /// Number of instructions executed since the beginning of the program, counting the ends of the loops
+    /// together with the instruction before them
+    pub fn executed(&self) -> usize {
+        self.executed
+    }
+
+    /// Executes the next instruction
+    /// Fails if the instruction fails, after which the debugger stays at it
+    pub fn step(&mut self) -> Result<StopReason, BfError> {
+        self.run_until(|_| true)
+    }
+
+    /// Executes the next instruction, or if it is the start of a loop, the whole loop
+    /// Stops earlier if an instruction inside the loop has a breakpoint
+    pub fn step_over(&mut self) -> Result<StopReason, BfError> {
+        match self.code.get(self.pc) {
+            Some(&Code::JumpIfZero(loop_end)) => {
+                let start = self.pc;
+                self.run_until(|pc| !(start..loop_end).contains(&pc))
+            }
+            _ => self.step(),
+        }
+    }
+
+    /// Executes the rest of the innermost loop around the next instruction, or the rest of the program
+    /// if it isn't in a loop
+    /// Stops earlier if an instruction on the way has a breakpoint
+    pub fn step_out(&mut self) -> Result<StopReason, BfError> {
+        // The instructions of the loop, which it has been left once the next instruction isn't one of them,
+        // as the end of an outer loop right after its end may jump back before its start
+        let instructions = match self.enclosing_loops.get(self.pc).copied().flatten() {
+            Some(start) => match self.code[start] {
+                Code::JumpIfZero(loop_end) => start..loop_end,
+                _ => unreachable!("loops start with a jump"),
+            },
+            None => 0..self.code.len(),
+        };
+        self.run_until(|pc| !instructions.contains(&pc))
+    }
+
+    /// Executes the program until it finishes or reaches an instruction with a breakpoint or a debug instruction
+    /// The first instruction stops it only if nothing was executed before
+    pub fn resume(&mut self) -> Result<StopReason, BfError> {
+        if !self.started {
+            self.started = true;
+            if let Some(reason) = self.paused() {
+                return Ok(reason);
+            }
+        }
+        self.run_until(|_| false)
+    }
+
+    /// Goes back to before the last executed instruction
+    pub fn step_back(&mut self) -> StopReason {
+        match self.undo() {
+            Some(position) => StopReason::Step(position),
+            None => StopReason::Beginning,
+        }
+    }
+
+    /// Goes back to right after the previous write to the cell at the index, even of the value it already held
+    /// Goes back to the beginning of the program or of the history if there is no such write
+    pub fn back_to_write(&mut self, cell: usize) -> StopReason {
+        while self.undo().is_some() {
+            let Some(previous) = self.executed.checked_sub(self.journal_start + 1) else {
+                break;
+            };
+            let entry = self.journal[previous];
+            if matches!(entry.write, Some((idx, _, _)) if idx == cell) {
+                let position =
+                    self.positions[entry.pc].expect("executed instructions have positions");
+                return StopReason::Watchpoint(Watchpoint::Write(cell), position);
+            }
+        }
+        StopReason::Beginning
+    }
+
+    /// Goes back or forward to right after the given number of executed instructions, ignoring breakpoints,
+    /// debug instructions and watchpoints
+    /// Stops earlier at the beginning of the history or if the program finishes, fails if an instruction
+    /// on the way fails
+    pub fn go_to(&mut self, executed: usize) -> Result<StopReason, BfError> {
+        while self.executed > executed && self.undo().is_some() {}
+        while self.executed < executed && !self.is_finished() {
+            self.execute_instruction()?;
+        }
+        match self.position() {
+            Some(position) => Ok(StopReason::Step(position)),
+            None => self.finish(),
+        }
+    }
+
+    /// Adds a breakpoint at the first instruction at or after the line and column, returns the instruction's
+    /// position or `None` if there is no such instruction
+    pub fn add_breakpoint(&mut self, line: usize, column: usize) -> Option<Position> {
+        let (idx, position) = self.instruction_at(line, column)?;
+        self.breakpoints.insert(idx);
+        Some(position)
+    }
+
+    /// Removes the breakpoint of the instruction which one added at the line and column would be at,
+    /// returns the instruction's position or `None` if it has no breakpoint
+    pub fn remove_breakpoint(&mut self, line: usize, column: usize) -> Option<Position> {
+        let (idx, position) = self.instruction_at(line, column)?;
+        self.breakpoints.remove(&idx).then_some(position)
+    }
+
+    /// Positions of the instructions with breakpoints, in the order of the source code
+    pub fn breakpoints(&self) -> Vec<Position> {
+        self.breakpoints
+            .iter()
+            .filter_map(|idx| self.positions[*idx])
+            .collect()
+    }
+
+    /// Adds a watchpoint, returns `false` if it was already added
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+        self.watchpoints.push(watchpoint);
+        true
+    }
+
+    /// Removes a watchpoint, returns `false` if it wasn't added
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|added| *added != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    /// The watchpoints, in the order they were added
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// The first instruction at or after the line and column, with its position
+    fn instruction_at(&self, line: usize, column: usize) -> Option<(usize, Position)> {
+        self.positions
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, position)| Some((idx, (*position)?)))
+            .find(|(_, position)| (position.line, position.column) >= (line, column))
+    }
+
+    /// Executes instructions until the index of the next one is one the step is completed at,
+    /// the program finishes, the next one has a breakpoint or is a debug instruction, a watchpoint is triggered
+    /// or the debugger is interrupted
+    fn run_until(&mut self, completed: impl Fn(usize) -> bool) -> Result<StopReason, BfError> {
+        self.started = true;
+        while let Some(executed) = self.position() {
+            let pointer = self.memory().pointer();
+            self.execute_instruction()?;
+            if let Some(watchpoint) = self.triggered_watchpoint(pointer) {
+                return Ok(StopReason::Watchpoint(watchpoint, executed));
+            }
+            let Some(position) = self.position() else {
+                break;
+            };
+            if completed(self.pc) {
+                return Ok(StopReason::Step(position));
+            }
+            if let Some(reason) = self.paused() {
+                return Ok(reason);
+            }
+            if self
+                .interrupt
+                .as_ref()
+                .is_some_and(|interrupt| interrupt.load(Ordering::Relaxed))
+            {
+                return Ok(StopReason::Interrupted(position));
+            }
+        }
+        self.finish()
+    }
+
+    /// Why the debugger pauses before the next instruction, if it has a breakpoint or is a debug instruction
+    fn paused(&self) -> Option<StopReason> {
+        let position = self.position()?;
+        if self.breakpoints.contains(&self.pc) {
+            return Some(StopReason::Breakpoint(position));
+        }
+        if let Code::Debug { .. } = self.code[self.pc] {
+            return Some(StopReason::Debug(position));
+        }
+        None
+    }
+
+    /// Finishes the program once, when the debugger first reaches its end
+    fn finish(&mut self) -> Result<StopReason, BfError> {
+        let outcome = match self.outcome {
+            Some(outcome) => outcome,
+            None => *self.outcome.insert(self.interpreter.finish()?),
+        };
+        Ok(StopReason::Finished(outcome))
+    }
+
+    /// The first watchpoint triggered by the instruction just executed, which started with the data pointer
+    /// at the given cell
+    fn triggered_watchpoint(&self, pointer: usize) -> Option<Watchpoint> {
+        let writes = mem::take(&mut *self.writes());
+        let current = self.memory().pointer();
+        self.watchpoints
+            .iter()
+            .copied()
+            .find(|watchpoint| match *watchpoint {
+                Watchpoint::Write(cell) => writes.iter().any(|(idx, _)| *idx == cell),
+                Watchpoint::Value(cell, expected) => writes
+                    .iter()
+                    .any(|(idx, value)| *idx == cell && value.to_u64() == expected),
+                Watchpoint::Pointer(first, last) => {
+                    !(first..=last).contains(&pointer) && (first..=last).contains(&current)
+                }
+            })
+    }
+
+    /// Executes the next instruction, together with the ends of the loops right after it, and records it
+    /// in the journal, or replays it from the journal if the debugger went back over it
+    /// Debug instructions have already paused the debugger, so they are skipped
+    fn execute_instruction(&mut self) -> Result<(), BfError> {
+        self.writes().clear();
+        if let Some(&entry) = self.journal.get(self.executed - self.journal_start) {
+            self.redo(entry);
+            return Ok(());
+        }
+        let pc = self.pc;
+        let memory = self.interpreter.memory();
+        let (pointer, size) = (memory.pointer(), memory.cells().len());
+        // The cell the instruction may write, with its value before it
+        let target = match self.code[pc] {
+            Code::Add { offset, .. } | Code::Input { offset } if memory.contains(offset) => {
+                Some((pointer.wrapping_add_signed(offset), *memory.read_at(offset)))
+            }
+            _ => None,
+        };
+        self.pc = match self.code[self.pc] {
+            Code::Debug { .. } => self.pc + 1,
+            _ => self.interpreter.execute_at(&self.code, self.pc)?,
+        };
+        while let Some(Code::JumpUnlessZero(_)) = self.code.get(self.pc) {
+            let end = self.pc;
+            self.pc = self.interpreter.execute_at(&self.code, end)?;
+            // The end of an empty loop jumps to itself, so its start, checking the same cell, is next instead
+            if self.pc == end {
+                self.pc = end - 1;
+                break;
+            }
+        }
+        // Every instruction is compiled on its own, so it writes at most the cell it targets
+        let memory = self.interpreter.memory();
+        let write = self.writes().last().and_then(|&(idx, new)| match target {
+            Some((target, old)) if target == idx => Some((idx, old, new)),
+            _ => None,
+        });
+        self.journal.push_back(JournalEntry {
+            pc,
+            next_pc: self.pc,
+            pointer,
+            next_pointer: memory.pointer(),
+            write,
+            grown: memory.cells().len() - size,
+        });
+        if self.journal.len() > self.history {
+            self.journal.pop_front();
+            self.journal_start += 1;
+        }
+        self.executed += 1;
+        Ok(())
+    }
+
+    /// Replays an instruction from the journal, reporting its write like the memory's write hook does
+    fn redo(&mut self, entry: JournalEntry<T>) {
+        let offset = self.move_offset(entry.pc);
+        let memory = self.interpreter.memory_mut();
+        if entry.grown > 0 {
+            memory.grow(offset);
+        }
+        if let Some((idx, _, new)) = entry.write {
+            memory.set_cell(idx, new);
+        }
+        memory.set_pointer(entry.next_pointer);
+        self.writes()
+            .extend(entry.write.map(|(idx, _, new)| (idx, new)));
+        self.pc = entry.next_pc;
+        self.executed += 1;
+    }
+
+    /// Undoes the last executed instruction, returns the position of the instruction or `None` if there is
+    /// nothing to undo
+    fn undo(&mut self) -> Option<Position> {
+        let previous = self.executed.checked_sub(self.journal_start + 1)?;
+        let entry = self.journal[previous];
+        let offset = self.move_offset(entry.pc);
+        let memory = self.interpreter.memory_mut();
+        if let Some((idx, old, _)) = entry.write {
+            memory.set_cell(idx, old);
+        }
+        if entry.grown > 0 {
+            memory.shrink(offset, entry.grown);
+        }
+        memory.set_pointer(entry.pointer);
+        self.pc = entry.pc;
+        self.executed -= 1;
+        self.positions[self.pc]
+    }
+
+    /// The cells written by the current instruction, as reported by the memory's write hook
+    fn writes(&self) -> MutexGuard<'_, Vec<(usize, T)>> {
+        self.writes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Offset of the instruction at the index if it moves the pointer, otherwise 0
+    fn move_offset(&self, pc: usize) -> isize {
+        match self.code[pc] {
+            Code::Move { offset, .. } => offset,
+            _ => 0,
+        }
+    }
+}
+
+/// Compiles the parsed instructions into bytecode with an instruction for each of them, together with
+/// the position of each instruction and the start of the innermost loop around it
+#[allow(clippy::type_complexity)]
+fn compile_unoptimized(nodes: &[Node]) -> (Vec<Code>, Vec<Option<Position>>, Vec<Option<usize>>) {
+    let mut code = Vec::new();
+    let mut positions = Vec::new();
+    let mut enclosing_loops = Vec::new();
+    let mut blocks = vec![(nodes.iter(), None)];
+
+    while let Some((nodes, loop_start)) = blocks.last_mut() {
+        let enclosing_loop = *loop_start;
+        let Some(node) = nodes.next() else {
+            let (_, loop_start) = blocks.pop().expect("the block is on the stack");
+            let Some(start) = loop_start else {
+                break;
+            };
+            code.push(Code::JumpUnlessZero(start + 1));
+            positions.push(None);
+            enclosing_loops.push(Some(start));
+            code[start] = Code::JumpIfZero(code.len());
+            continue;
+        };
+        let position = node.position;
+        let instruction = match &node.instruction {
+            Instruction::IncrementData => Code::Add {
+                offset: 0,
+                delta: 1,
+                position,
+            },
+            Instruction::DecrementData => Code::Add {
+                offset: 0,
+                delta: -1,
+                position,
+            },
+            Instruction::NextCell => Code::Move {
+                offset: 1,
+                position,
+            },
+            Instruction::PreviousCell => Code::Move {
+                offset: -1,
+                position,
+            },
+            Instruction::OutputData => Code::Output { offset: 0 },
+            Instruction::InputData => Code::Input { offset: 0 },
+            Instruction::Debug => Code::Debug { position },
+            Instruction::Loop(loop_nodes) => {
+                blocks.push((loop_nodes.iter(), Some(code.len())));
+                Code::JumpIfZero(0)
+            }
+        };
+        code.push(instruction);
+        positions.push(Some(position));
+        enclosing_loops.push(enclosing_loop);
+    }
+
+    (code, positions, enclosing_loops)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, thread, time::Duration};
+
+    use super::*;
+    use crate::{
+        policy::{BoundaryPolicy, OverflowPolicy},
+        program::Parser,
+    };
+
+    fn column(reason: StopReason) -> usize {
+        match reason {
+            StopReason::Step(position)
+            | StopReason::Breakpoint(position)
+            | StopReason::Debug(position)
+            | StopReason::Watchpoint(_, position)
+            | StopReason::Interrupted(position) => position.column,
+            StopReason::Finished(_) => panic!("the program has finished"),
+            StopReason::Beginning => panic!("the program is at its beginning"),
+        }
+    }
+
+    #[test]
+    fn debugger_steps_through_every_instruction() {
+        let program = Program::parse("+ >-");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        assert_eq!(Some(1), debugger.position().map(|position| position.column));
+        assert_eq!(3, column(debugger.step().unwrap()));
+        assert_eq!(1, *debugger.memory().read());
+        assert_eq!(4, column(debugger.step().unwrap()));
+        assert_eq!(1, debugger.memory().pointer());
+        assert!(matches!(debugger.step().unwrap(), StopReason::Finished(_)));
+        assert_eq!(255, *debugger.memory().read());
+        assert!(debugger.is_finished());
+        assert_eq!(None, debugger.position());
+    }
+
+    #[test]
+    fn debugger_executes_the_ends_of_loops_with_the_instruction_before_them() {
+        let program = Program::parse("++[-]+");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        let columns: Vec<usize> = (0..5).map(|_| column(debugger.step().unwrap())).collect();
+        assert_eq!(vec![2, 3, 4, 4, 6], columns);
+    }
+
+    #[test]
+    fn debugger_steps_over_and_out_of_loops() {
+        let program = Program::parse("++[->+<]>[[-].]+");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        debugger.step().unwrap();
+        debugger.step().unwrap();
+        assert_eq!(9, column(debugger.step_over().unwrap()));
+        assert_eq!(
+            (0, 2),
+            (*debugger.memory().read(), *debugger.memory().read_at(1))
+        );
+        debugger.step().unwrap();
+        debugger.step().unwrap();
+        assert_eq!(12, column(debugger.step().unwrap()));
+        assert_eq!(14, column(debugger.step_out().unwrap()));
+        assert_eq!(16, column(debugger.step_out().unwrap()));
+        assert!(matches!(
+            debugger.step_out().unwrap(),
+            StopReason::Finished(_)
+        ));
+        drop(interpreter);
+        assert_eq!(vec![0], output);
+    }
+
+    #[test]
+    fn debugger_steps_over_and_out_of_loops_ending_with_an_outer_loop() {
+        let program = Program::parse("++[>++[-]]<+.");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program).with_history(100);
+        for _ in 0..6 {
+            debugger.step().unwrap();
+        }
+        assert_eq!(Some(7), debugger.position().map(|position| position.column));
+        assert_eq!(11, column(debugger.step_over().unwrap()));
+        debugger.go_to(7).unwrap();
+        assert_eq!(Some(8), debugger.position().map(|position| position.column));
+        assert_eq!(11, column(debugger.step_out().unwrap()));
+    }
+
+    #[test]
+    fn debugger_stops_at_breakpoints_at_or_after_their_position() {
+        let program = Program::parse("+++[\n  >+< -\n]\n.");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        let breakpoint = debugger.add_breakpoint(2, 1).unwrap();
+        assert_eq!((2, 3), (breakpoint.line, breakpoint.column));
+        assert_eq!(7, debugger.add_breakpoint(2, 7).unwrap().column);
+        assert_eq!(None, debugger.add_breakpoint(4, 2));
+        assert_eq!(2, debugger.breakpoints().len());
+
+        assert_eq!(
+            StopReason::Breakpoint(breakpoint),
+            debugger.resume().unwrap()
+        );
+        assert_eq!(7, column(debugger.resume().unwrap()));
+        assert_eq!(Some(breakpoint), debugger.remove_breakpoint(2, 1));
+        assert_eq!(None, debugger.remove_breakpoint(2, 1));
+        assert_eq!(7, column(debugger.resume().unwrap()));
+        assert_eq!(2, *debugger.memory().read());
+        assert_eq!(3, column(debugger.step_over().unwrap()));
+        assert_eq!(
+            Some(Position {
+                offset: 11,
+                line: 2,
+                column: 7
+            }),
+            debugger.remove_breakpoint(2, 7)
+        );
+        assert!(matches!(
+            debugger.resume().unwrap(),
+            StopReason::Finished(_)
+        ));
+    }
+
+    #[test]
+    fn debugger_lists_the_loops_around_the_next_instruction() {
+        let program = Program::parse("+[>+[-]\n[<]]");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        assert!(debugger.loops().is_empty());
+        debugger.add_breakpoint(1, 6);
+        debugger.resume().unwrap();
+        let loops: Vec<(usize, usize)> = debugger
+            .loops()
+            .iter()
+            .map(|position| (position.line, position.column))
+            .collect();
+        assert_eq!(vec![(1, 5), (1, 2)], loops);
+    }
+
+    #[test]
+    fn debugger_stops_at_a_breakpoint_at_the_first_instruction_once() {
+        let program = Program::parse("+[-]+");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        let breakpoint = debugger.add_breakpoint(1, 1).unwrap();
+        assert_eq!(
+            StopReason::Breakpoint(breakpoint),
+            debugger.resume().unwrap()
+        );
+        assert_eq!(0, debugger.executed());
+        assert!(matches!(
+            debugger.resume().unwrap(),
+            StopReason::Finished(_)
+        ));
+    }
+
+    #[test]
+    fn debugger_stops_at_the_failing_instruction() {
+        let program = Program::parse("+-\n-");
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10)
+            .with_overflow_policy(OverflowPolicy::Trap);
+        let mut debugger = interpreter.debug(&program);
+        assert!(matches!(
+            debugger.resume(),
+            Err(BfError::Overflow(Position { line: 2, .. }))
+        ));
+        assert_eq!(Some(2), debugger.position().map(|position| position.line));
+    }
+
+    #[test]
+    fn debugger_reports_the_outcome_when_the_program_finishes() {
+        let program = Program::parse(",.,.");
+        let mut input = Cursor::new(vec![7]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        let outcome = ExecutionOutcome {
+            bytes_read: 1,
+            bytes_written: 2,
+        };
+        assert_eq!(StopReason::Finished(outcome), debugger.resume().unwrap());
+        assert_eq!(StopReason::Finished(outcome), debugger.step().unwrap());
+        drop(interpreter);
+        assert_eq!(vec![7, 7], output);
+    }
+
+    #[test]
+    fn debugger_handles_deeply_nested_loops() {
+        let depth = 100_000;
+        let source_code = "[".repeat(depth) + &"]".repeat(depth) + "+";
+        let program = Program::parse(&source_code);
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        assert_eq!(depth * 2 + 1, column(debugger.step_over().unwrap()));
+    }
+
+    #[test]
+    fn debugger_pauses_at_debug_instructions_without_showing_the_cells() {
+        let program = Parser::new().with_debug_char('#').parse("+#+#").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut debug_output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 10).with_debug_output(&mut debug_output);
+        let mut debugger = interpreter.debug(&program);
+        assert_eq!(2, column(debugger.step().unwrap()));
+        assert_eq!(3, column(debugger.step().unwrap()));
+        assert!(matches!(debugger.resume().unwrap(), StopReason::Debug(_)));
+        assert_eq!(2, *debugger.memory().read());
+        assert!(matches!(
+            debugger.resume().unwrap(),
+            StopReason::Finished(_)
+        ));
+        drop(interpreter);
+        assert!(debug_output.is_empty());
+    }
+
+    #[test]
+    fn debugger_stops_after_writes_to_watched_cells() {
+        let program = Parser::new().parse("+>+++[-<+>]").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        assert!(debugger.add_watchpoint(Watchpoint::Value(0, 3)));
+        assert!(debugger.add_watchpoint(Watchpoint::Write(1)));
+        assert!(!debugger.add_watchpoint(Watchpoint::Write(1)));
+        let StopReason::Watchpoint(watchpoint, position) = debugger.resume().unwrap() else {
+            panic!("expected a watchpoint");
+        };
+        assert_eq!((Watchpoint::Write(1), 3), (watchpoint, position.column));
+        assert_eq!(Some(4), debugger.position().map(|position| position.column));
+        assert!(debugger.remove_watchpoint(Watchpoint::Write(1)));
+        assert!(!debugger.remove_watchpoint(Watchpoint::Write(1)));
+        let StopReason::Watchpoint(watchpoint, position) = debugger.resume().unwrap() else {
+            panic!("expected a watchpoint");
+        };
+        assert_eq!((Watchpoint::Value(0, 3), 9), (watchpoint, position.column));
+        assert_eq!(1, *debugger.memory().read_at(1));
+        assert_eq!(&[Watchpoint::Value(0, 3)], debugger.watchpoints());
+    }
+
+    #[test]
+    fn debugger_stops_when_the_pointer_enters_watched_cells() {
+        let program = Parser::new().parse(">>>><<<<>>>").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        debugger.add_watchpoint(Watchpoint::Pointer(2, 4));
+        let reasons: Vec<StopReason> = (0..3).map(|_| debugger.resume().unwrap()).collect();
+        assert!(matches!(
+            reasons[..],
+            [
+                StopReason::Watchpoint(_, Position { column: 2, .. }),
+                StopReason::Watchpoint(_, Position { column: 10, .. }),
+                StopReason::Finished(_),
+            ]
+        ));
+    }
+
+    #[test]
+    fn debugger_goes_back_and_replays_without_reading_or_writing_again() {
+        let program = Parser::new().parse(",.>+").unwrap();
+        let mut input = Cursor::new(vec![7, 8]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program).with_history(100);
+        let finished = debugger.resume().unwrap();
+        assert_eq!(4, debugger.executed());
+        assert_eq!(4, column(debugger.step_back()));
+        assert_eq!(
+            (1, 0),
+            (debugger.memory().pointer(), *debugger.memory().read())
+        );
+        assert_eq!(1, column(debugger.go_to(0).unwrap()));
+        assert_eq!(
+            (0, 0),
+            (debugger.memory().pointer(), *debugger.memory().read())
+        );
+        assert_eq!(StopReason::Beginning, debugger.step_back());
+        assert_eq!(finished, debugger.resume().unwrap());
+        assert_eq!(&[7, 1], &debugger.memory().cells()[..2]);
+        drop(interpreter);
+        assert_eq!(vec![7], output);
+    }
+
+    #[test]
+    fn debugger_journals_moves_without_the_writes_before_them() {
+        let program = Parser::new().parse("+>>>").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program).with_history(100);
+        for executed in [4, 0, 1, 3, 2] {
+            debugger.go_to(executed).unwrap();
+        }
+        assert_eq!(
+            (1, 1),
+            (debugger.memory().pointer(), debugger.memory().cells()[0])
+        );
+        assert_eq!(
+            Some(1),
+            match debugger.back_to_write(0) {
+                StopReason::Watchpoint(_, position) => Some(position.column),
+                _ => None,
+            }
+        );
+        assert_eq!(1, debugger.executed());
+        assert_eq!(StopReason::Beginning, debugger.back_to_write(0));
+        assert_eq!(0, debugger.memory().cells()[0]);
+    }
+
+    #[test]
+    fn debugger_goes_back_to_the_previous_writes_to_a_cell() {
+        let program = Parser::new().parse("+>+++[-<+>]").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program).with_history(100);
+        debugger.resume().unwrap();
+        assert_eq!(4, debugger.memory().cells()[0]);
+        let StopReason::Watchpoint(Watchpoint::Write(0), position) = debugger.back_to_write(0)
+        else {
+            panic!("expected a write");
+        };
+        assert_eq!((9, 4), (position.column, debugger.memory().cells()[0]));
+        assert_eq!(
+            Some(10),
+            debugger.position().map(|position| position.column)
+        );
+        debugger.back_to_write(0);
+        debugger.back_to_write(0);
+        assert_eq!(2, debugger.memory().cells()[0]);
+        assert_eq!(1, column(debugger.back_to_write(0)));
+        assert_eq!((1, 1), (debugger.executed(), debugger.memory().cells()[0]));
+        assert_eq!(StopReason::Beginning, debugger.back_to_write(0));
+        assert_eq!(0, debugger.memory().cells()[0]);
+    }
+
+    #[test]
+    fn debugger_goes_forward_past_the_journal_by_executing() {
+        let program = Parser::new().parse("++[-]").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program).with_history(100);
+        debugger.step().unwrap();
+        assert_eq!(4, column(debugger.go_to(4).unwrap()));
+        assert_eq!((4, 1), (debugger.executed(), *debugger.memory().read()));
+        assert!(matches!(
+            debugger.go_to(100).unwrap(),
+            StopReason::Finished(_)
+        ));
+        assert_eq!(5, debugger.executed());
+    }
+
+    #[test]
+    fn debugger_undoes_the_growth_of_the_tape() {
+        let program = Parser::new().parse("+<+>>>+").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 2)
+            .with_boundary_policy(BoundaryPolicy::GrowBoth);
+        let mut debugger = interpreter.debug(&program).with_history(100);
+        debugger.resume().unwrap();
+        let cells = debugger.memory().cells().to_vec();
+        assert_eq!(vec![0, 1, 1, 0, 1, 0, 0, 0], cells);
+        debugger.go_to(1).unwrap();
+        assert_eq!(&[1, 0], debugger.memory().cells());
+        assert_eq!(0, debugger.memory().pointer());
+        debugger.resume().unwrap();
+        assert_eq!(cells, debugger.memory().cells());
+        assert_eq!(4, debugger.memory().pointer());
+    }
+
+    #[test]
+    fn debugger_goes_back_only_over_the_instructions_in_its_history() {
+        let program = Parser::new().parse("+++++").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let mut debugger = interpreter.debug(&program);
+        debugger.step().unwrap();
+        assert_eq!(StopReason::Beginning, debugger.step_back());
+        assert_eq!((1, 1), (debugger.executed(), *debugger.memory().read()));
+        let mut debugger = debugger.with_history(2);
+        debugger.resume().unwrap();
+        assert_eq!(4, column(debugger.go_to(0).unwrap()));
+        assert_eq!((3, 3), (debugger.executed(), *debugger.memory().read()));
+        assert_eq!(StopReason::Beginning, debugger.back_to_write(0));
+        debugger.resume().unwrap();
+        assert_eq!((5, 5), (debugger.executed(), *debugger.memory().read()));
+    }
+
+    #[test]
+    fn debugger_stops_while_interrupted() {
+        let program = Parser::new().parse("+[]").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let mut debugger = interpreter
+            .debug(&program)
+            .with_interrupt(Arc::clone(&interrupt));
+        debugger.step().unwrap();
+        interrupt.store(true, Ordering::Relaxed);
+        assert_eq!(
+            StopReason::Interrupted(Position {
+                offset: 1,
+                line: 1,
+                column: 2
+            }),
+            debugger.resume().unwrap()
+        );
+        assert_eq!(2, column(debugger.step().unwrap()));
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                interrupt.store(true, Ordering::Relaxed);
+            });
+            interrupt.store(false, Ordering::Relaxed);
+            assert_eq!(2, column(debugger.resume().unwrap()));
+        });
+    }
+
+    #[test]
+    fn running_the_interpreter_again_removes_the_watch_on_its_memory() {
+        let program = Parser::new().parse("+").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 10);
+        let writes = Arc::clone(&interpreter.debug(&program).writes);
+        interpreter.run(&program).unwrap();
+        assert_eq!(1, Arc::strong_count(&writes));
+        assert!(writes.lock().unwrap().is_empty());
+    }
+}
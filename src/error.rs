@@ -0,0 +1,168 @@
+use std::{error::Error, fmt, io};
+
+use crate::position::Position;
+
+/// Errors that can occur while parsing or executing a Brainfuck program
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BfError {
+    /// Writing a value to the output stream failed
+    Output(io::Error),
+    /// Reading a value from the input stream failed for a reason other than reaching its end
+    Input(io::Error),
+    /// The input stream reached its end and the end-of-input policy doesn't allow that
+    EndOfInput,
+    /// A cell written to the output as UTF-8 doesn't hold a valid Unicode code point
+    InvalidCodePoint(u64),
+    /// A cell overflowed at the instruction at the given position and the overflow policy is to trap
+    Overflow(Position),
+    /// The data pointer was moved off the tape by the instruction at the given position
+    /// and the boundary policy doesn't allow that
+    OutOfBounds(Position),
+    /// A `[` at the given position has no matching `]` and the program is parsed strictly
+    UnmatchedLoopStart(Position),
+    /// A `]` at the given position has no matching `[` and the program is parsed strictly
+    UnmatchedLoopEnd(Position),
+    /// A program can't be compiled with a tape of no cells
+    EmptyTape,
+    /// A program can't be compiled with a tape of that many cells, the target supports at most `max`
+    TapeTooBig { memory_size: usize, max: usize },
+}
+
+impl fmt::Display for BfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BfError::Output(error) => write!(f, "failed to write output: {error}"),
+            BfError::Input(error) => write!(f, "failed to read input: {error}"),
+            BfError::EndOfInput => write!(f, "unexpected end of input"),
+            BfError::InvalidCodePoint(value) => {
+                write!(f, "cannot output {value} as UTF-8: not a valid code point")
+            }
+            BfError::Overflow(position) => write!(f, "cell overflow at {position}"),
+            BfError::OutOfBounds(position) => {
+                write!(f, "pointer moved off the tape at {position}")
+            }
+            BfError::UnmatchedLoopStart(position) => {
+                write!(f, "unmatched '[' at {position}")
+            }
+            BfError::UnmatchedLoopEnd(position) => write!(f, "unmatched ']' at {position}"),
+            BfError::EmptyTape => write!(f, "the tape needs at least one cell"),
+            BfError::TapeTooBig { memory_size, max } => write!(
+                f,
+                "a tape of {memory_size} cells is too big for the target, it supports at most {max}"
+            ),
+        }
+    }
+}
+
+impl Error for BfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BfError::Output(error) | BfError::Input(error) => Some(error),
+            BfError::EndOfInput
+            | BfError::InvalidCodePoint(_)
+            | BfError::Overflow(_)
+            | BfError::OutOfBounds(_)
+            | BfError::UnmatchedLoopStart(_)
+            | BfError::UnmatchedLoopEnd(_)
+            | BfError::EmptyTape
+            | BfError::TapeTooBig { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_error_is_displayed_with_its_cause() {
+        let error = BfError::Output(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert_eq!("failed to write output: broken pipe", error.to_string());
+    }
+
+    #[test]
+    fn input_error_is_displayed_with_its_cause() {
+        let error = BfError::Input(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!("failed to read input: permission denied", error.to_string());
+    }
+
+    #[test]
+    fn end_of_input_error_is_displayed() {
+        assert_eq!("unexpected end of input", BfError::EndOfInput.to_string());
+    }
+
+    #[test]
+    fn invalid_code_point_error_is_displayed_with_the_value() {
+        assert_eq!(
+            "cannot output 55296 as UTF-8: not a valid code point",
+            BfError::InvalidCodePoint(0xd800).to_string()
+        );
+    }
+
+    #[test]
+    fn overflow_error_is_displayed_with_the_position() {
+        let error = BfError::Overflow(Position {
+            offset: 7,
+            line: 2,
+            column: 3,
+        });
+        assert_eq!("cell overflow at line 2, column 3", error.to_string());
+    }
+
+    #[test]
+    fn out_of_bounds_error_is_displayed_with_the_position() {
+        let error = BfError::OutOfBounds(Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        });
+        assert_eq!(
+            "pointer moved off the tape at line 1, column 1",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn unmatched_bracket_errors_are_displayed_with_the_position() {
+        let position = Position {
+            offset: 4,
+            line: 2,
+            column: 1,
+        };
+        assert_eq!(
+            "unmatched '[' at line 2, column 1",
+            BfError::UnmatchedLoopStart(position).to_string()
+        );
+        assert_eq!(
+            "unmatched ']' at line 2, column 1",
+            BfError::UnmatchedLoopEnd(position).to_string()
+        );
+    }
+
+    #[test]
+    fn empty_tape_error_is_displayed() {
+        assert_eq!(
+            "the tape needs at least one cell",
+            BfError::EmptyTape.to_string()
+        );
+    }
+
+    #[test]
+    fn tape_too_big_error_is_displayed_with_the_sizes() {
+        let error = BfError::TapeTooBig {
+            memory_size: 5,
+            max: 4,
+        };
+        assert_eq!(
+            "a tape of 5 cells is too big for the target, it supports at most 4",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn error_exposes_its_source() {
+        let error = BfError::Output(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert!(error.source().is_some());
+    }
+}
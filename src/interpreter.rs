@@ -14,24 +14,68 @@
 //! let mut input = Cursor::new(vec![]);
 //! let mut output = vec![];
 //! let mut interpreter = Interpreter::new(&mut input, &mut output, 30000);
-//! interpreter.execute(&source_code);
+//! interpreter.execute(&source_code).unwrap();
 //! assert_eq!("Hello World!\n", str::from_utf8(output.as_slice()).unwrap());
 //! ```
 //!
 
-use std::io::{Read, Write};
+use std::io::{self, ErrorKind, Read, Write};
 
-use crate::memory::Memory;
+#[cfg(feature = "jit")]
+use std::any::TypeId;
+
+#[cfg(feature = "jit")]
+use crate::jit::Callbacks;
+#[cfg(test)]
+use crate::optimizer::Op;
+
+use crate::{
+    bytecode::Code,
+    cell::Cell,
+    debugger::Debugger,
+    error::BfError,
+    memory::Memory,
+    optimizer::Optimizations,
+    policy::{BoundaryPolicy, EofPolicy, OutputEncoding, OverflowPolicy},
+    position::Position,
+    program::Program,
+};
+
+/// Summary of a successful program execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExecutionOutcome {
+    /// Number of bytes read from the input stream
+    pub bytes_read: usize,
+    /// Number of bytes written to the output stream
+    pub bytes_written: usize,
+}
+
+/// Number of cells shown on each side of the current one by a debug instruction
+const DEBUG_RADIUS: usize = 5;
 
 /// Brainfuck interpreter
-pub struct Interpreter<'a, R, W>
+/// The cells of its memory are of type `T`, which is a byte by default
+pub struct Interpreter<'a, R, W, T = u8>
 where
     R: Read,
     W: Write,
+    T: Cell,
 {
-    memory: Memory<u8>,
+    memory: Memory<T>,
+    streams: Streams<'a, R, W>,
+    overflow_policy: OverflowPolicy,
+    boundary_policy: BoundaryPolicy,
+    /// Where the debug instructions write, the standard error if not set
+    debug_output: Option<&'a mut (dyn Write + Send)>,
+}
+
+/// Input and output streams of the interpreter, together with how cells are read from and written to them
+struct Streams<'a, R, W> {
     input: &'a mut R,
     output: &'a mut W,
+    outcome: ExecutionOutcome,
+    eof_policy: EofPolicy,
+    output_encoding: OutputEncoding,
 }
 
 impl<'a, R, W> Interpreter<'a, R, W>
@@ -41,83 +85,398 @@ where
 {
     /// Creates new interpreter with the given input stream, output stream and number of cells
     pub fn new(input: &'a mut R, output: &'a mut W, memory_size: usize) -> Interpreter<'a, R, W> {
+        Interpreter::with_cell_type(input, output, memory_size)
+    }
+}
+
+impl<'a, R, W, T> Interpreter<'a, R, W, T>
+where
+    R: Read,
+    W: Write,
+    T: Cell,
+{
+    /// Creates new interpreter with the given input stream, output stream and number of cells of type `T`,
+    /// e.g. `Interpreter::<_, _, u16>::with_cell_type(&mut input, &mut output, 30000)` for 16-bit cells
+    pub fn with_cell_type(
+        input: &'a mut R,
+        output: &'a mut W,
+        memory_size: usize,
+    ) -> Interpreter<'a, R, W, T> {
         Interpreter {
             memory: Memory::new(memory_size),
-            input,
-            output,
+            streams: Streams {
+                input,
+                output,
+                outcome: ExecutionOutcome::default(),
+                eof_policy: EofPolicy::default(),
+                output_encoding: OutputEncoding::default(),
+            },
+            overflow_policy: OverflowPolicy::default(),
+            boundary_policy: BoundaryPolicy::default(),
+            debug_output: None,
         }
     }
 
-    /// Executes a program
-    pub fn execute(&mut self, source_code: &str) {
+    /// Sets what the `,` instruction does when there is no more input, by default the cell is unchanged
+    pub fn with_eof_policy(mut self, eof_policy: EofPolicy) -> Interpreter<'a, R, W, T> {
+        self.streams.eof_policy = eof_policy;
+        self
+    }
+
+    /// Sets how the `.` instruction writes the current cell, by default only its lowest byte is written
+    pub fn with_output_encoding(
+        mut self,
+        output_encoding: OutputEncoding,
+    ) -> Interpreter<'a, R, W, T> {
+        self.streams.output_encoding = output_encoding;
+        self
+    }
+
+    /// Sets what happens when a cell goes beyond its range, by default it wraps around
+    pub fn with_overflow_policy(
+        mut self,
+        overflow_policy: OverflowPolicy,
+    ) -> Interpreter<'a, R, W, T> {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets what happens when the data pointer is moved off either end of the tape,
+    /// by default it wraps around to the other end
+    pub fn with_boundary_policy(
+        mut self,
+        boundary_policy: BoundaryPolicy,
+    ) -> Interpreter<'a, R, W, T> {
+        self.boundary_policy = boundary_policy;
+        self
+    }
+
+    /// Sets where the debug instructions show the cells around the current one, by default the standard error
+    /// The output is sent along with the interpreter, so that the interpreter can be moved to another thread
+    pub fn with_debug_output(
+        mut self,
+        debug_output: &'a mut (dyn Write + Send),
+    ) -> Interpreter<'a, R, W, T> {
+        self.debug_output = Some(debug_output);
+        self
+    }
+
+    /// Parses and executes a program
+    /// Fails if the output stream can't be written to or the input stream can't be read from
+    pub fn execute(&mut self, source_code: &str) -> Result<ExecutionOutcome, BfError> {
+        self.run(&Program::parse(source_code))
+    }
+
+    /// Executes an already parsed program, so that it can be run many times without parsing it again
+    /// Fails if the output stream can't be written to or the input stream can't be read from
+    pub fn run(&mut self, program: &Program) -> Result<ExecutionOutcome, BfError> {
+        self.reset();
+        let optimizations = Optimizations::for_policies(
+            self.overflow_policy,
+            self.boundary_policy,
+            self.memory.cells().len(),
+        );
+        if !self.execute_native(program)? {
+            self.execute_code(&program.code(optimizations))?;
+        }
+        self.finish()
+    }
+
+    /// Starts debugging an already parsed program, which the returned debugger executes an instruction at a time
+    pub fn debug(&mut self, program: &Program) -> Debugger<'_, 'a, R, W, T> {
+        Debugger::new(self, program)
+    }
+
+    /// Clears the memory, its write hook and the outcome before a program is executed
+    pub(crate) fn reset(&mut self) {
         self.memory.clear();
-        let instructions = parse(&mut source_code.chars());
-        self.execute_instructions(&instructions);
-    }
-
-    fn execute_instructions(&mut self, instructions: &Vec<Instruction>) {
-        for instruction in instructions {
-            match instruction {
-                Instruction::NextCell => self.memory.next(),
-                Instruction::PreviousCell => self.memory.previous(),
-                Instruction::IncrementData => self.memory.increment(),
-                Instruction::DecrementData => self.memory.decrement(),
-                Instruction::OutputData => {
-                    let value = self.memory.read();
-                    self.output.write(&[*value]).unwrap();
+        self.memory.remove_write_hook();
+        self.streams.outcome = ExecutionOutcome::default();
+    }
+
+    /// Flushes the output after a program is executed and returns the outcome of the execution
+    pub(crate) fn finish(&mut self) -> Result<ExecutionOutcome, BfError> {
+        self.streams.output.flush().map_err(BfError::Output)?;
+        Ok(self.streams.outcome)
+    }
+
+    pub(crate) fn memory(&self) -> &Memory<T> {
+        &self.memory
+    }
+
+    pub(crate) fn memory_mut(&mut self) -> &mut Memory<T> {
+        &mut self.memory
+    }
+
+    /// Executes a program like [`Interpreter::run`], but by walking the tree of the optimized operations
+    #[cfg(test)]
+    fn run_reference(&mut self, program: &Program) -> Result<ExecutionOutcome, BfError> {
+        self.reset();
+        let optimizations = Optimizations::for_policies(
+            self.overflow_policy,
+            self.boundary_policy,
+            self.memory.cells().len(),
+        );
+        self.execute_ops(&program.ops(optimizations))?;
+        self.finish()
+    }
+
+    /// Executes the program compiled to native code, if the cells are bytes, both of them and the pointer
+    /// wrap around and the code can be compiled, returns whether it was executed
+    #[cfg(feature = "jit")]
+    fn execute_native(&mut self, program: &Program) -> Result<bool, BfError> {
+        if TypeId::of::<T>() != TypeId::of::<u8>()
+            || self.overflow_policy != OverflowPolicy::Wrap
+            || self.boundary_policy != BoundaryPolicy::Wrap
+        {
+            return Ok(false);
+        }
+        let (cells, current_idx) = self.memory.cells_mut();
+        let Some(native_code) = program.native_code(cells.len()) else {
+            return Ok(false);
+        };
+        // SAFETY: `T` is `u8`, as checked above
+        let cells = unsafe { &mut *(cells as *mut [T] as *mut [u8]) };
+        native_code.run(cells, current_idx, &mut self.streams)?;
+        Ok(true)
+    }
+
+    /// Native code is compiled only with the `jit` feature, so the bytecode is always interpreted
+    #[cfg(not(feature = "jit"))]
+    fn execute_native(&mut self, _program: &Program) -> Result<bool, BfError> {
+        Ok(false)
+    }
+
+    /// Executes the bytecode in a single dispatch loop, jumping to the precomputed targets of the loops
+    fn execute_code(&mut self, code: &[Code]) -> Result<(), BfError> {
+        let mut pc = 0;
+        while pc < code.len() {
+            pc = self.execute_at(code, pc)?;
+        }
+        Ok(())
+    }
+
+    /// Executes the instruction of the bytecode at `pc` and returns the index of the next one
+    #[inline(always)]
+    pub(crate) fn execute_at(&mut self, code: &[Code], pc: usize) -> Result<usize, BfError> {
+        match code[pc] {
+            Code::Add {
+                offset,
+                delta,
+                position,
+            } => self.add(offset, delta, position)?,
+            Code::Move { offset, position } => self.move_by(offset, position)?,
+            Code::SetZero => self.memory.write(T::default()),
+            Code::MulAdd { offset, factor } => self.mul_add(offset, factor),
+            Code::ScanZero { step, position } => self.scan_zero(step, position)?,
+            Code::Output { offset } => self.output(offset)?,
+            Code::Input { offset } => self.input(offset)?,
+            Code::Debug { position } => self.debug_instruction(position)?,
+            Code::JumpIfZero(target) => {
+                if *self.memory.read() == T::default() {
+                    return Ok(target);
                 }
-                Instruction::InputData => {
-                    let mut value = [0_u8];
-                    if self.input.read_exact(&mut value).is_ok() {
-                        self.memory.write(value[0]);
-                    }
+            }
+            Code::JumpUnlessZero(target) => {
+                if *self.memory.read() != T::default() {
+                    return Ok(target);
                 }
-                Instruction::Loop(loop_instructions) => {
-                    while *self.memory.read() != 0 {
-                        self.execute_instructions(loop_instructions)
+            }
+        }
+        Ok(pc + 1)
+    }
+
+    /// Executes the optimized operations by walking their tree, recursing into the loops
+    /// Kept as the reference implementation the bytecode is tested against
+    #[cfg(test)]
+    fn execute_ops(&mut self, ops: &[Op]) -> Result<(), BfError> {
+        for op in ops {
+            match op {
+                Op::Add {
+                    offset,
+                    delta,
+                    position,
+                } => self.add(*offset, *delta, *position)?,
+                Op::Move { offset, position } => self.move_by(*offset, *position)?,
+                Op::SetZero => self.memory.write(T::default()),
+                Op::MulAdd { offset, factor } => self.mul_add(*offset, *factor),
+                Op::ScanZero { step, position } => self.scan_zero(*step, *position)?,
+                Op::Output { offset } => self.output(*offset)?,
+                Op::Input { offset } => self.input(*offset)?,
+                Op::Debug { position } => self.debug_instruction(*position)?,
+                Op::Loop(loop_ops) => {
+                    while *self.memory.read() != T::default() {
+                        self.execute_ops(loop_ops)?;
                     }
                 }
             }
         }
+        Ok(())
+    }
+
+    fn mul_add(&mut self, offset: isize, factor: i32) {
+        self.reach(offset);
+        let value = self.memory.read().wrapping_mul(T::from_i32(factor));
+        self.memory.add_at(offset, value);
+    }
+
+    fn output(&mut self, offset: isize) -> Result<(), BfError> {
+        self.reach(offset);
+        self.streams.write_cell(*self.memory.read_at(offset))
+    }
+
+    fn input(&mut self, offset: isize) -> Result<(), BfError> {
+        self.reach(offset);
+        if let Some(value) = self.streams.read_cell()? {
+            self.memory.write_at(offset, value);
+        }
+        Ok(())
+    }
+
+    /// Shows the position of the debug instruction and the cells around the current one
+    fn debug_instruction(&mut self, position: Position) -> Result<(), BfError> {
+        let window = self.memory.window(DEBUG_RADIUS);
+        let result = match &mut self.debug_output {
+            Some(debug_output) => write!(debug_output, "debug instruction at {position}\n{window}")
+                .and_then(|()| debug_output.flush()),
+            None => write!(io::stderr(), "debug instruction at {position}\n{window}"),
+        };
+        result.map_err(BfError::Output)
+    }
+
+    fn move_by(&mut self, offset: isize, position: Position) -> Result<(), BfError> {
+        match self.boundary_policy {
+            BoundaryPolicy::Wrap => {}
+            BoundaryPolicy::Error if !self.memory.contains(offset) => {
+                return Err(BfError::OutOfBounds(position));
+            }
+            BoundaryPolicy::Grow if !self.memory.contains(offset) && offset < 0 => {
+                return Err(BfError::OutOfBounds(position));
+            }
+            _ => self.memory.grow(offset),
+        }
+        self.memory.move_by(offset);
+        Ok(())
+    }
+
+    fn scan_zero(&mut self, step: isize, position: Position) -> Result<(), BfError> {
+        if self.boundary_policy == BoundaryPolicy::Wrap {
+            self.memory.scan_zero(step);
+            return Ok(());
+        }
+        match self.memory.find_zero(step) {
+            Some(offset) => self.memory.move_by(offset),
+            None => {
+                // All cells past the end of the tape are zero, so the scan stops at the first of them
+                let mut offset = 0;
+                while self.memory.contains(offset) {
+                    offset += step;
+                }
+                self.move_by(offset, position)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Grows the tape if needed, so that an access at an offset from the current cell stays on it
+    fn reach(&mut self, offset: isize) {
+        if matches!(
+            self.boundary_policy,
+            BoundaryPolicy::Grow | BoundaryPolicy::GrowBoth
+        ) {
+            self.memory.grow(offset);
+        }
+    }
+
+    fn add(&mut self, offset: isize, delta: i32, position: Position) -> Result<(), BfError> {
+        self.reach(offset);
+        if self.overflow_policy == OverflowPolicy::Wrap {
+            self.memory.add_at(offset, T::from_i32(delta));
+            return Ok(());
+        }
+        let value = *self.memory.read_at(offset);
+        let result = match self.overflow_policy {
+            OverflowPolicy::Saturate => value.saturating_add_signed(delta),
+            _ => value
+                .checked_add_signed(delta)
+                .ok_or(BfError::Overflow(position))?,
+        };
+        self.memory.write_at(offset, result);
+        Ok(())
     }
 }
 
-#[derive(PartialEq, Debug)]
-enum Instruction {
-    NextCell,
-    PreviousCell,
-    IncrementData,
-    DecrementData,
-    OutputData,
-    InputData,
-    Loop(Vec<Instruction>),
+impl<R, W> Streams<'_, R, W>
+where
+    R: Read,
+    W: Write,
+{
+    /// Reads the next byte of the input as a cell, `None` if there is no more input
+    /// and the cell stays unchanged
+    fn read_cell<T: Cell>(&mut self) -> Result<Option<T>, BfError> {
+        let mut value = [0_u8];
+        match self.input.read_exact(&mut value) {
+            Ok(()) => {
+                self.outcome.bytes_read += 1;
+                Ok(Some(T::from_u8(value[0])))
+            }
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => match self.eof_policy {
+                EofPolicy::Unchanged => Ok(None),
+                EofPolicy::Zero => Ok(Some(T::default())),
+                EofPolicy::MaxValue => Ok(Some(T::MAX)),
+                EofPolicy::Error => Err(BfError::EndOfInput),
+            },
+            Err(error) => Err(BfError::Input(error)),
+        }
+    }
+
+    fn write_cell<T: Cell>(&mut self, value: T) -> Result<(), BfError> {
+        let mut buffer = [0_u8; 4];
+        let bytes = match self.output_encoding {
+            OutputEncoding::LowByte => {
+                buffer[0] = value.low_byte();
+                &buffer[..1]
+            }
+            OutputEncoding::Utf8 => u32::try_from(value.to_u64())
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or(BfError::InvalidCodePoint(value.to_u64()))?
+                .encode_utf8(&mut buffer)
+                .as_bytes(),
+        };
+        self.output.write_all(bytes).map_err(BfError::Output)?;
+        self.outcome.bytes_written += bytes.len();
+        Ok(())
+    }
 }
 
-fn parse(chars: &mut impl Iterator<Item = char>) -> Vec<Instruction> {
-    let mut instructions: Vec<Instruction> = Vec::new();
-
-    while let Some(char) = chars.next() {
-        instructions.push(match char {
-            '>' => Instruction::NextCell,
-            '<' => Instruction::PreviousCell,
-            '+' => Instruction::IncrementData,
-            '-' => Instruction::DecrementData,
-            '.' => Instruction::OutputData,
-            ',' => Instruction::InputData,
-            '[' => Instruction::Loop(parse(chars)),
-            ']' => break,
-            _ => continue,
-        });
+#[cfg(feature = "jit")]
+impl<R, W> Callbacks for Streams<'_, R, W>
+where
+    R: Read,
+    W: Write,
+{
+    fn output(&mut self, value: u8) -> Result<(), BfError> {
+        self.write_cell(value)
     }
 
-    instructions
+    fn input(&mut self, cell: &mut u8) -> Result<(), BfError> {
+        if let Some(value) = self.read_cell()? {
+            *cell = value;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::{
+        io::{self, Cursor},
+        str, thread,
+    };
 
     use super::*;
+    use crate::program::Parser;
 
     #[test]
     fn interpreter_can_be_created() {
@@ -131,7 +490,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute("");
+        interpreter.execute("").unwrap();
     }
 
     #[test]
@@ -139,7 +498,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(" !\"#$%&'()*/0123456789:;=?@ABCDEFGHIJKLMNOPQRSTUVWXYZ\\^_`abcdefghijklmnopqrstuvwxyz{|}~");
+        interpreter.execute(" !\"#$%&'()*/0123456789:;=?@ABCDEFGHIJKLMNOPQRSTUVWXYZ\\^_`abcdefghijklmnopqrstuvwxyz{|}~").unwrap();
         let expected: Vec<u8> = vec![];
         assert_eq!(expected, output)
     }
@@ -149,7 +508,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(".");
+        interpreter.execute(".").unwrap();
         assert_eq!(vec![0], output)
     }
 
@@ -158,7 +517,7 @@ mod tests {
         let mut input = Cursor::new(vec![1]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(",.");
+        interpreter.execute(",.").unwrap();
         assert_eq!(vec![1], output)
     }
 
@@ -167,7 +526,7 @@ mod tests {
         let mut input = Cursor::new(vec![1, 2]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(".,.,.");
+        interpreter.execute(".,.,.").unwrap();
         assert_eq!(vec![0, 1, 2], output)
     }
 
@@ -176,7 +535,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(",.");
+        interpreter.execute(",.").unwrap();
         assert_eq!(vec![0], output)
     }
 
@@ -185,7 +544,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute("+.");
+        interpreter.execute("+.").unwrap();
         assert_eq!(vec![1], output)
     }
 
@@ -194,7 +553,7 @@ mod tests {
         let mut input = Cursor::new(vec![2]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(",.-.");
+        interpreter.execute(",.-.").unwrap();
         assert_eq!(vec![2, 1], output)
     }
 
@@ -203,7 +562,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(".+.+.+.-.-.-.");
+        interpreter.execute(".+.+.+.-.-.-.").unwrap();
         assert_eq!(vec![0, 1, 2, 3, 2, 1, 0], output)
     }
 
@@ -212,7 +571,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute(".+.>.");
+        interpreter.execute(".+.>.").unwrap();
         assert_eq!(vec![0, 1, 0], output)
     }
 
@@ -221,7 +580,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute(".+.>.+.+.<.-.>.");
+        interpreter.execute(".+.>.+.+.<.-.>.").unwrap();
         assert_eq!(vec![0, 1, 0, 1, 2, 1, 0, 2], output)
     }
 
@@ -230,7 +589,7 @@ mod tests {
         let mut input = Cursor::new(vec![1, 2, 3]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
-        interpreter.execute(",>,>,>.>.>.");
+        interpreter.execute(",>,>,>.>.>.").unwrap();
         assert_eq!(vec![1, 2, 3], output)
     }
 
@@ -239,7 +598,7 @@ mod tests {
         let mut input = Cursor::new(vec![1, 2, 3]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
-        interpreter.execute(",>,>,><.<.<.");
+        interpreter.execute(",>,>,><.<.<.").unwrap();
         assert_eq!(vec![3, 2, 1], output)
     }
 
@@ -248,7 +607,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(".[.].");
+        interpreter.execute(".[.].").unwrap();
         assert_eq!(vec![0, 0], output)
     }
 
@@ -257,7 +616,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(".+[.-].");
+        interpreter.execute(".+[.-].").unwrap();
         assert_eq!(vec![0, 1, 0], output)
     }
 
@@ -266,7 +625,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
-        interpreter.execute(".++[.-].");
+        interpreter.execute(".++[.-].").unwrap();
         assert_eq!(vec![0, 2, 1, 0], output)
     }
 
@@ -275,7 +634,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute("+[>++[.-].<.-].");
+        interpreter.execute("+[>++[.-].<.-].").unwrap();
         assert_eq!(vec![2, 1, 0, 1, 0], output)
     }
 
@@ -284,7 +643,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
-        interpreter.execute("+[>+[.-]+[.-].<.-].");
+        interpreter.execute("+[>+[.-]+[.-].<.-].").unwrap();
         assert_eq!(vec![1, 1, 0, 1, 0], output)
     }
 
@@ -293,7 +652,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
-        interpreter.execute("+[->++[->+[.-]<]]");
+        interpreter.execute("+[->++[->+[.-]<]]").unwrap();
         assert_eq!(vec![1, 1], output)
     }
 
@@ -302,7 +661,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
-        interpreter.execute(".+.[-.");
+        interpreter.execute(".+.[-.").unwrap();
         assert_eq!(vec![0, 1, 0], output)
     }
 
@@ -311,7 +670,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute("+[>++[.-].<.-");
+        interpreter.execute("+[>++[.-].<.-").unwrap();
         assert_eq!(vec![2, 1, 0, 1], output)
     }
 
@@ -320,7 +679,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute("+[>++[.-.<.-].");
+        interpreter.execute("+[>++[.-.<.-].").unwrap();
         assert_eq!(vec![2, 1, 1, 0], output)
     }
 
@@ -329,7 +688,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute("+[>++[.-.<.-");
+        interpreter.execute("+[>++[.-.<.-").unwrap();
         assert_eq!(vec![2, 1, 1], output)
     }
 
@@ -338,7 +697,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute("+[>+[.-]+[.-].<.-].");
+        interpreter.execute("+[>+[.-]+[.-].<.-].").unwrap();
         assert_eq!(vec![1, 1, 0, 1, 0], output)
     }
 
@@ -347,7 +706,7 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute("+[->++[->+[.-<]]");
+        interpreter.execute("+[->++[->+[.-<]]").unwrap();
         assert_eq!(vec![1, 1], output)
     }
 
@@ -356,7 +715,524 @@ mod tests {
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
         let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
-        interpreter.execute(".].");
+        interpreter.execute(".].").unwrap();
         assert_eq!(vec![0], output)
     }
+
+    struct FailingStream(io::ErrorKind);
+
+    impl Read for FailingStream {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::from(self.0))
+        }
+    }
+
+    impl Write for FailingStream {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(self.0))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn interpreter_returns_error_if_output_fails() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = FailingStream(io::ErrorKind::BrokenPipe);
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
+        let result = interpreter.execute(".");
+        assert!(
+            matches!(result, Err(BfError::Output(error)) if error.kind() == io::ErrorKind::BrokenPipe)
+        );
+    }
+
+    #[test]
+    fn interpreter_returns_error_if_input_fails() {
+        let mut input = FailingStream(io::ErrorKind::PermissionDenied);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
+        let result = interpreter.execute(",");
+        assert!(
+            matches!(result, Err(BfError::Input(error)) if error.kind() == io::ErrorKind::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn interpreter_does_not_return_error_at_the_end_of_input() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
+        assert!(interpreter.execute(",,").is_ok());
+    }
+
+    #[test]
+    fn interpreter_reports_number_of_bytes_read_and_written() {
+        let mut input = Cursor::new(vec![1, 2]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
+        let outcome = interpreter.execute(",.,.,.").unwrap();
+        assert_eq!(
+            ExecutionOutcome {
+                bytes_read: 2,
+                bytes_written: 3
+            },
+            outcome
+        );
+    }
+
+    #[test]
+    fn interpreter_clears_cell_in_both_directions() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
+        interpreter.execute("+++[-].+++++[+].").unwrap();
+        assert_eq!(vec![0, 0], output)
+    }
+
+    #[test]
+    fn interpreter_executes_multiply_loops() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
+        interpreter.execute("+++++[->++>+++<<].>.>.").unwrap();
+        assert_eq!(vec![0, 10, 15], output)
+    }
+
+    #[test]
+    fn interpreter_executes_multiply_loops_that_increment_with_wrapping() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
+        interpreter.execute("-[+>+<].>.").unwrap();
+        assert_eq!(vec![0, 1], output)
+    }
+
+    #[test]
+    fn interpreter_executes_multiply_loops_wrapping_around_the_tape() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
+        interpreter.execute("++[-<+++>]<.").unwrap();
+        assert_eq!(vec![6], output)
+    }
+
+    #[test]
+    fn interpreter_keeps_cells_reached_around_a_tiny_tape_apart() {
+        for (source_code, memory_size, expected) in [
+            ("+<+.>+.", 1, vec![2, 3]),
+            (">+>+<<.>>+", 2, vec![1]),
+            ("+++[->+>--<<]>.", 2, vec![1]),
+        ] {
+            let program = Program::parse(source_code);
+            let mut input = Cursor::new(vec![]);
+            let mut output = vec![];
+            let mut interpreter = Interpreter::new(&mut input, &mut output, memory_size);
+            interpreter.run(&program).unwrap();
+            interpreter.run_reference(&program).unwrap();
+            assert_eq!(
+                [expected.clone(), expected].concat(),
+                output,
+                "{source_code}"
+            );
+        }
+    }
+
+    #[test]
+    fn interpreter_executes_scan_loops() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 5);
+        interpreter.execute("+>+>+<<[>]+++.<[<]>.").unwrap();
+        assert_eq!(vec![3, 1], output)
+    }
+
+    #[test]
+    fn interpreter_runs_parsed_program() {
+        let program = Program::parse(",[.-]");
+        let mut input = Cursor::new(vec![3]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 1);
+        interpreter.run(&program).unwrap();
+        assert_eq!(vec![3, 2, 1], output)
+    }
+
+    #[test]
+    fn interpreter_runs_parsed_program_many_times_from_a_clean_memory() {
+        let program = Program::parse(">,[<+>-]<.");
+        let mut input = Cursor::new(vec![1, 2]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
+        interpreter.run(&program).unwrap();
+        interpreter.run(&program).unwrap();
+        assert_eq!(vec![1, 2], output)
+    }
+
+    #[test]
+    fn interpreter_sets_cell_to_zero_at_the_end_of_input_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_eof_policy(EofPolicy::Zero);
+        interpreter.execute("+,.").unwrap();
+        assert_eq!(vec![0], output)
+    }
+
+    #[test]
+    fn interpreter_sets_cell_to_max_value_at_the_end_of_input_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_eof_policy(EofPolicy::MaxValue);
+        interpreter.execute("+,.").unwrap();
+        assert_eq!(vec![255], output)
+    }
+
+    #[test]
+    fn interpreter_returns_error_at_the_end_of_input_if_configured() {
+        let mut input = Cursor::new(vec![1]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_eof_policy(EofPolicy::Error);
+        let result = interpreter.execute(",.,.");
+        assert!(matches!(result, Err(BfError::EndOfInput)));
+        drop(interpreter);
+        assert_eq!(vec![1], output)
+    }
+
+    #[test]
+    fn interpreter_with_wide_cells_does_not_wrap_at_a_byte() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::<_, _, u16>::with_cell_type(&mut input, &mut output, 2);
+        interpreter
+            .execute("++++++++++++++++[>++++++++++++++++<-]>[[-]<+>]<.")
+            .unwrap();
+        assert_eq!(vec![1], output)
+    }
+
+    #[test]
+    fn interpreter_with_byte_cells_wraps_at_a_byte() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 2);
+        interpreter
+            .execute("++++++++++++++++[>++++++++++++++++<-]>[[-]<+>]<.")
+            .unwrap();
+        assert_eq!(vec![0], output)
+    }
+
+    #[test]
+    fn interpreter_with_wide_cells_outputs_low_byte() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::<_, _, u32>::with_cell_type(&mut input, &mut output, 1);
+        interpreter.execute("-.").unwrap();
+        assert_eq!(vec![255], output)
+    }
+
+    #[test]
+    fn interpreter_with_wide_cells_sets_max_value_at_the_end_of_input() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::<_, _, u16>::with_cell_type(&mut input, &mut output, 1)
+            .with_eof_policy(EofPolicy::MaxValue)
+            .with_output_encoding(OutputEncoding::Utf8);
+        interpreter.execute(",.").unwrap();
+        assert_eq!("\u{ffff}", str::from_utf8(&output).unwrap());
+    }
+
+    #[test]
+    fn interpreter_outputs_cells_as_utf8_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::<_, _, u64>::with_cell_type(&mut input, &mut output, 2)
+            .with_output_encoding(OutputEncoding::Utf8);
+        let outcome = interpreter
+            .execute("++++++++++++++++[>++++++++++++++++<-]>+.")
+            .unwrap();
+        assert_eq!("ā", str::from_utf8(&output).unwrap());
+        assert_eq!(2, outcome.bytes_written);
+    }
+
+    #[test]
+    fn interpreter_returns_error_for_invalid_code_points_in_utf8_output() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::<_, _, u32>::with_cell_type(&mut input, &mut output, 1)
+            .with_output_encoding(OutputEncoding::Utf8);
+        let result = interpreter.execute("-.");
+        assert!(matches!(
+            result,
+            Err(BfError::InvalidCodePoint(0xffff_ffff))
+        ));
+    }
+
+    #[test]
+    fn interpreter_saturates_cells_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 2)
+            .with_overflow_policy(OverflowPolicy::Saturate);
+        interpreter
+            .execute("-.+-.++++++++[>++++++++++++++++++++++++++++++++++++++++<-]>.")
+            .unwrap();
+        assert_eq!(vec![0, 0, 255], output)
+    }
+
+    #[test]
+    fn interpreter_saturates_wide_cells_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::<_, _, u16>::with_cell_type(&mut input, &mut output, 1)
+            .with_overflow_policy(OverflowPolicy::Saturate)
+            .with_output_encoding(OutputEncoding::Utf8);
+        interpreter.execute("-+.").unwrap();
+        assert_eq!("\u{1}", str::from_utf8(&output).unwrap());
+    }
+
+    #[test]
+    fn interpreter_reports_position_of_overflow_if_trapping() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_overflow_policy(OverflowPolicy::Trap);
+        let result = interpreter.execute("+.\n+-\n--");
+        let expected = Position {
+            offset: 7,
+            line: 3,
+            column: 2,
+        };
+        assert!(matches!(result, Err(BfError::Overflow(position)) if position == expected));
+        drop(interpreter);
+        assert_eq!(vec![1], output)
+    }
+
+    #[test]
+    fn interpreter_reports_position_of_overflow_inside_loops_if_trapping() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 2).with_overflow_policy(OverflowPolicy::Trap);
+        let result = interpreter.execute(&format!("++[->{}<]", "+".repeat(130)));
+        let expected = Position {
+            offset: 130,
+            line: 1,
+            column: 131,
+        };
+        assert!(matches!(result, Err(BfError::Overflow(position)) if position == expected));
+    }
+
+    #[test]
+    fn interpreter_clears_cells_without_overflow_if_trapping() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_overflow_policy(OverflowPolicy::Trap);
+        interpreter.execute("+++[-].").unwrap();
+        assert_eq!(vec![0], output)
+    }
+
+    #[test]
+    fn interpreter_wraps_pointer_around_by_default() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 3);
+        interpreter.execute("<+++>>>.").unwrap();
+        assert_eq!(vec![3], output)
+    }
+
+    #[test]
+    fn interpreter_reports_position_of_move_off_the_tape_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 2)
+            .with_boundary_policy(BoundaryPolicy::Error);
+        let result = interpreter.execute(">.\n>.");
+        let expected = Position {
+            offset: 3,
+            line: 2,
+            column: 1,
+        };
+        assert!(matches!(result, Err(BfError::OutOfBounds(position)) if position == expected));
+        drop(interpreter);
+        assert_eq!(vec![0], output)
+    }
+
+    #[test]
+    fn interpreter_reports_position_of_scan_off_the_tape_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 3)
+            .with_boundary_policy(BoundaryPolicy::Error);
+        let result = interpreter.execute("+>+>+<<[>]");
+        let expected = Position {
+            offset: 8,
+            line: 1,
+            column: 9,
+        };
+        assert!(matches!(result, Err(BfError::OutOfBounds(position)) if position == expected));
+    }
+
+    #[test]
+    fn interpreter_grows_tape_to_the_right_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_boundary_policy(BoundaryPolicy::Grow);
+        interpreter.execute("+[>]>>>++[->+++<]>.<<<<<.").unwrap();
+        assert_eq!(vec![6, 1], output)
+    }
+
+    #[test]
+    fn interpreter_reports_move_off_the_left_end_if_growing_to_the_right() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_boundary_policy(BoundaryPolicy::Grow);
+        let result = interpreter.execute(">>+<+<+[<]");
+        let expected = Position {
+            offset: 8,
+            line: 1,
+            column: 9,
+        };
+        assert!(matches!(result, Err(BfError::OutOfBounds(position)) if position == expected));
+    }
+
+    #[test]
+    fn interpreter_grows_tape_in_both_directions_if_configured() {
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&mut input, &mut output, 1)
+            .with_boundary_policy(BoundaryPolicy::GrowBoth);
+        interpreter
+            .execute("+<<<++[->>>>>+<<<<<]>>>>>.<<<<<<<[<].")
+            .unwrap();
+        assert_eq!(vec![2, 0], output)
+    }
+
+    /// Runs a program both as bytecode and by walking the tree of operations under the given policies,
+    /// and checks that the results, the outputs and the final memory are the same
+    fn assert_same_as_reference(
+        source_code: &str,
+        input: &[u8],
+        overflow_policy: OverflowPolicy,
+        boundary_policy: BoundaryPolicy,
+    ) {
+        let program = Program::parse(source_code);
+        let run = |reference: bool| {
+            let mut input = Cursor::new(input.to_vec());
+            let mut output = vec![];
+            let mut interpreter = Interpreter::new(&mut input, &mut output, 64)
+                .with_eof_policy(EofPolicy::MaxValue)
+                .with_overflow_policy(overflow_policy)
+                .with_boundary_policy(boundary_policy);
+            let result = if reference {
+                interpreter.run_reference(&program)
+            } else {
+                interpreter.run(&program)
+            };
+            let memory = (-8..8)
+                .map(|offset| *interpreter.memory.read_at(offset))
+                .collect::<Vec<u8>>();
+            (format!("{result:?}"), memory, output)
+        };
+        assert_eq!(
+            run(true),
+            run(false),
+            "{source_code} with {overflow_policy:?} and {boundary_policy:?}"
+        );
+    }
+
+    const BOUNDARY_POLICIES: [BoundaryPolicy; 4] = [
+        BoundaryPolicy::Wrap,
+        BoundaryPolicy::Error,
+        BoundaryPolicy::Grow,
+        BoundaryPolicy::GrowBoth,
+    ];
+
+    #[test]
+    fn bytecode_behaves_like_the_tree_of_operations() {
+        let programs = [
+            include_str!("../examples/hello_world.bf"),
+            "+[>+<-]>.[<]>>>[[-]<]<<.",
+            ",[->+>++<<]>[->>+<<]<<<<,.>>>>.>.>.",
+            "-->>>+++[<]>.[[-]>]<<<<<<<.",
+            ">+++++[<+++++>-]<[>>+<<-]>>[[>]+[<]>-]>[.>]",
+            "+++++[-<+>]<[->>>>+<<<<]>>>>.",
+        ];
+        let overflow_policies = [
+            OverflowPolicy::Wrap,
+            OverflowPolicy::Saturate,
+            OverflowPolicy::Trap,
+        ];
+        for source_code in programs {
+            for overflow_policy in overflow_policies {
+                for boundary_policy in BOUNDARY_POLICIES {
+                    assert_same_as_reference(
+                        source_code,
+                        b"Hello, World!",
+                        overflow_policy,
+                        boundary_policy,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bytecode_behaves_like_the_tree_of_operations_for_rot13() {
+        // rot13 stops on the end of input only if the maximum value wraps around to zero or traps
+        for overflow_policy in [OverflowPolicy::Wrap, OverflowPolicy::Trap] {
+            for boundary_policy in BOUNDARY_POLICIES {
+                assert_same_as_reference(
+                    include_str!("../examples/rot13.bf"),
+                    b"Hello, World!",
+                    overflow_policy,
+                    boundary_policy,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn interpreter_shows_the_cells_at_debug_instructions() {
+        let program = Parser::new()
+            .with_debug_char('#')
+            .parse("++>+++\n<#>[-]#")
+            .unwrap();
+        for boundary_policy in BOUNDARY_POLICIES {
+            let mut input = Cursor::new(vec![]);
+            let mut output = vec![];
+            let mut debug_output = vec![];
+            let mut interpreter = Interpreter::new(&mut input, &mut output, 4)
+                .with_boundary_policy(boundary_policy)
+                .with_debug_output(&mut debug_output);
+            interpreter.run(&program).unwrap();
+            drop(interpreter);
+            assert_eq!(
+                "debug instruction at line 2, column 2\n-> 0: 2\n   1: 3\n   2: 0\n   3: 0\n\
+                 debug instruction at line 2, column 7\n   0: 2\n-> 1: 0\n   2: 0\n   3: 0\n",
+                str::from_utf8(&debug_output).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn interpreter_with_a_debug_output_can_be_moved_to_another_thread() {
+        let program = Parser::new().with_debug_char('#').parse("+#").unwrap();
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let mut debug_output = vec![];
+        let mut interpreter =
+            Interpreter::new(&mut input, &mut output, 1).with_debug_output(&mut debug_output);
+        thread::scope(|scope| {
+            scope.spawn(move || interpreter.run(&program).unwrap());
+        });
+        assert_eq!(
+            "debug instruction at line 1, column 2\n-> 0: 1\n",
+            str::from_utf8(&debug_output).unwrap()
+        );
+    }
 }
@@ -0,0 +1,506 @@
+use std::{
+    ffi::c_void,
+    fmt, mem, ptr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{bytecode::Code, error::BfError};
+
+/// Receives the cells written by `.` and provides the ones read by `,` while native code runs
+pub(crate) trait Callbacks {
+    fn output(&mut self, value: u8) -> Result<(), BfError>;
+
+    /// Reads the next value into the cell, which is left unchanged if there is no more input
+    /// and the end-of-input policy says so
+    fn input(&mut self, cell: &mut u8) -> Result<(), BfError>;
+}
+
+/// Bytecode compiled to x86-64 machine code for byte cells that wrap around on a tape of a fixed size
+/// The code is in its own executable memory mapping and calls back into the [`Callbacks`] it is run with
+/// for input and output, so it can be compiled once and run many times by any interpreter
+pub(crate) struct NativeCode {
+    code: *mut c_void,
+    size: usize,
+    memory_size: usize,
+}
+
+// SAFETY: the mapping is never written after it is compiled and is only unmapped when the code is dropped
+unsafe impl Send for NativeCode {}
+unsafe impl Sync for NativeCode {}
+
+/// The native code compiled for the tape size of the last run of a program, `None` if it couldn't be
+/// compiled, so that running a program again on a tape of the same size doesn't compile it again
+#[derive(Default)]
+pub(crate) struct NativeCodeCache(Mutex<Option<(usize, Option<Arc<NativeCode>>)>>);
+
+impl NativeCodeCache {
+    /// The code compiled for a tape of the given size, compiling it if it was last compiled for another one
+    pub(crate) fn get_or_compile(
+        &self,
+        code: &[Code],
+        memory_size: usize,
+    ) -> Option<Arc<NativeCode>> {
+        let mut cached = self.0.lock().unwrap_or_else(|error| error.into_inner());
+        match &*cached {
+            Some((size, native_code)) if *size == memory_size => native_code.clone(),
+            _ => {
+                let native_code = NativeCode::compile(code, memory_size).map(Arc::new);
+                *cached = Some((memory_size, native_code.clone()));
+                native_code
+            }
+        }
+    }
+
+    fn cached(&self) -> Option<(usize, Option<Arc<NativeCode>>)> {
+        self.0
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .clone()
+    }
+}
+
+impl Clone for NativeCodeCache {
+    fn clone(&self) -> NativeCodeCache {
+        NativeCodeCache(Mutex::new(self.cached()))
+    }
+}
+
+impl fmt::Debug for NativeCodeCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let memory_size = self.cached().map(|(memory_size, _)| memory_size);
+        f.debug_struct("NativeCodeCache")
+            .field("memory_size", &memory_size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Signature of the compiled code, it returns non-zero if a callback failed
+type Entry = unsafe extern "C" fn(
+    cells: *mut u8,
+    current_idx: *mut usize,
+    memory_size: usize,
+    context: *mut c_void,
+) -> u8;
+
+/// Passed to the compiled code and back to the callbacks, keeps the error of a failed callback
+struct Context<'c> {
+    callbacks: &'c mut dyn Callbacks,
+    error: Option<BfError>,
+}
+
+impl NativeCode {
+    /// Compiles the bytecode for a tape of the given size, `None` if the size is too big to address cells
+    /// with 32-bit offsets, the code has debug instructions, which are left to the interpreter,
+    /// or the code can't be mapped
+    pub(crate) fn compile(code: &[Code], memory_size: usize) -> Option<NativeCode> {
+        if memory_size == 0 || memory_size > i32::MAX as usize {
+            return None;
+        }
+        if code
+            .iter()
+            .any(|instruction| matches!(instruction, Code::Debug { .. }))
+        {
+            return None;
+        }
+        let callbacks = (output as *const () as usize, input as *const () as usize);
+        let machine_code = assemble(code, memory_size, callbacks);
+        let size = machine_code.len();
+        // SAFETY: a new private mapping is created, filled and made executable instead of writable,
+        // so the only code executed from it is the one assembled above
+        unsafe {
+            let mapping = libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if mapping == libc::MAP_FAILED {
+                return None;
+            }
+            ptr::copy_nonoverlapping(machine_code.as_ptr(), mapping.cast::<u8>(), size);
+            if libc::mprotect(mapping, size, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                libc::munmap(mapping, size);
+                return None;
+            }
+            Some(NativeCode {
+                code: mapping,
+                size,
+                memory_size,
+            })
+        }
+    }
+
+    /// Runs the code on the cells, starting from and updating the index of the current one
+    /// Stops with the error of the first callback that fails
+    pub(crate) fn run(
+        &self,
+        cells: &mut [u8],
+        current_idx: &mut usize,
+        callbacks: &mut dyn Callbacks,
+    ) -> Result<(), BfError> {
+        assert_eq!(self.memory_size, cells.len(), "compiled for another tape");
+        assert!(
+            *current_idx < cells.len(),
+            "the current cell is off the tape"
+        );
+        let mut context = Context {
+            callbacks,
+            error: None,
+        };
+        // SAFETY: the mapping holds a function with the signature of `Entry`, which only accesses
+        // the cells within the tape of the size it was compiled for and calls the callbacks below
+        let failed = unsafe {
+            let entry: Entry = mem::transmute::<*mut c_void, Entry>(self.code);
+            entry(
+                cells.as_mut_ptr(),
+                current_idx,
+                cells.len(),
+                ptr::addr_of_mut!(context).cast::<c_void>(),
+            )
+        };
+        match context.error {
+            Some(error) => Err(error),
+            None => {
+                debug_assert_eq!(0, failed);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for NativeCode {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created by `compile` with this size and nothing refers to it anymore
+        unsafe {
+            libc::munmap(self.code, self.size);
+        }
+    }
+}
+
+extern "C" fn output(context: *mut c_void, value: u8) -> u8 {
+    // SAFETY: the compiled code passes back the context `NativeCode::run` gave it
+    let context = unsafe { &mut *context.cast::<Context>() };
+    match context.callbacks.output(value) {
+        Ok(()) => 0,
+        Err(error) => {
+            context.error = Some(error);
+            1
+        }
+    }
+}
+
+extern "C" fn input(context: *mut c_void, cell: *mut u8) -> u8 {
+    // SAFETY: the compiled code passes back the context `NativeCode::run` gave it
+    // and a pointer to the current cell, which is on the tape
+    let (context, cell) = unsafe { (&mut *context.cast::<Context>(), &mut *cell) };
+    match context.callbacks.input(cell) {
+        Ok(()) => 0,
+        Err(error) => {
+            context.error = Some(error);
+            1
+        }
+    }
+}
+
+/// Target of a jump, resolved once all of the code is assembled
+#[derive(Clone, Copy)]
+enum Label {
+    /// The machine code of the bytecode instruction at the index
+    Instruction(usize),
+    /// Where the code returns after a callback failed
+    Failure,
+}
+
+/// Assembles the machine code of the bytecode, calling the functions at the addresses of the callbacks
+/// for output and input, while it runs:
+/// - `rbx` points to the cells
+/// - `r12` is the index of the current cell
+/// - `r13` is the number of cells
+/// - `r14` points to the context of the callbacks
+/// - `r15` points to where the index of the current cell is stored at the end
+fn assemble(code: &[Code], memory_size: usize, callbacks: (usize, usize)) -> Vec<u8> {
+    let (output, input) = callbacks;
+    let mut assembler = Assembler {
+        bytes: Vec::new(),
+        memory_size,
+        starts: Vec::with_capacity(code.len() + 1),
+        jumps: Vec::new(),
+    };
+
+    // push rbx, r12, r13, r14 and r15, which keeps the stack aligned to 16 bytes for the calls
+    assembler.emit(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+    assembler.emit(&[0x48, 0x89, 0xfb]); // mov rbx, rdi
+    assembler.emit(&[0x49, 0x89, 0xf7]); // mov r15, rsi
+    assembler.emit(&[0x4c, 0x8b, 0x26]); // mov r12, [rsi]
+    assembler.emit(&[0x49, 0x89, 0xd5]); // mov r13, rdx
+    assembler.emit(&[0x49, 0x89, 0xce]); // mov r14, rcx
+
+    for instruction in code {
+        assembler.starts.push(assembler.bytes.len());
+        match *instruction {
+            Code::Add { offset, delta, .. } => {
+                assembler.load_cell_idx(offset);
+                assembler.emit(&[0x80, 0x04, 0x03, delta as u8]); // add byte [rbx + rax], delta
+            }
+            Code::Move { offset, .. } => assembler.move_by(offset),
+            Code::SetZero => {
+                assembler.load_cell_idx(0);
+                assembler.emit(&[0xc6, 0x04, 0x03, 0x00]); // mov byte [rbx + rax], 0
+            }
+            Code::MulAdd { offset, factor } => {
+                assembler.load_cell_idx(0);
+                assembler.emit(&[0x0f, 0xb6, 0x0c, 0x03]); // movzx ecx, byte [rbx + rax]
+                assembler.emit(&[0x69, 0xc9]); // imul ecx, ecx, factor
+                assembler.emit(&factor.to_le_bytes());
+                assembler.load_cell_idx(offset);
+                assembler.emit(&[0x00, 0x0c, 0x03]); // add byte [rbx + rax], cl
+            }
+            Code::ScanZero { step, .. } => {
+                let scan = assembler.bytes.len();
+                assembler.load_cell_idx(0);
+                assembler.emit(&[0x80, 0x3c, 0x03, 0x00]); // cmp byte [rbx + rax], 0
+                assembler.emit(&[0x0f, 0x84, 0, 0, 0, 0]); // je to the end of the scan
+                let found = assembler.bytes.len();
+                assembler.move_by(step);
+                assembler.emit(&[0xe9]); // jmp to the start of the scan
+                assembler.emit_rel32(scan);
+                let end = assembler.bytes.len();
+                assembler.patch_rel32(found, end);
+            }
+            Code::Output { offset } => {
+                assembler.load_cell_idx(offset);
+                assembler.emit(&[0x0f, 0xb6, 0x34, 0x03]); // movzx esi, byte [rbx + rax]
+                assembler.call(output);
+            }
+            Code::Input { offset } => {
+                assembler.load_cell_idx(offset);
+                assembler.emit(&[0x48, 0x8d, 0x34, 0x03]); // lea rsi, [rbx + rax]
+                assembler.call(input);
+            }
+            Code::Debug { .. } => unreachable!("code with debug instructions isn't compiled"),
+            Code::JumpIfZero(target) => {
+                assembler.load_cell_idx(0);
+                assembler.emit(&[0x80, 0x3c, 0x03, 0x00]); // cmp byte [rbx + rax], 0
+                assembler.emit(&[0x0f, 0x84]); // je target
+                assembler.jump_to(Label::Instruction(target));
+            }
+            Code::JumpUnlessZero(target) => {
+                assembler.load_cell_idx(0);
+                assembler.emit(&[0x80, 0x3c, 0x03, 0x00]); // cmp byte [rbx + rax], 0
+                assembler.emit(&[0x0f, 0x85]); // jne target
+                assembler.jump_to(Label::Instruction(target));
+            }
+        }
+    }
+
+    assembler.starts.push(assembler.bytes.len());
+    assembler.emit(&[0x31, 0xc0]); // xor eax, eax
+    let exit = assembler.bytes.len();
+    assembler.emit(&[0x4d, 0x89, 0x27]); // mov [r15], r12
+                                         // pop r15, r14, r13, r12 and rbx
+    assembler.emit(&[0x41, 0x5f, 0x41, 0x5e, 0x41, 0x5d, 0x41, 0x5c, 0x5b]);
+    assembler.emit(&[0xc3]); // ret
+    let failure = assembler.bytes.len();
+    assembler.emit(&[0xb8, 0x01, 0x00, 0x00, 0x00]); // mov eax, 1
+    assembler.emit(&[0xe9]); // jmp exit
+    assembler.emit_rel32(exit);
+
+    for (at, label) in mem::take(&mut assembler.jumps) {
+        let target = match label {
+            Label::Instruction(idx) => assembler.starts[idx],
+            Label::Failure => failure,
+        };
+        assembler.patch_rel32(at, target);
+    }
+
+    assembler.bytes
+}
+
+struct Assembler {
+    bytes: Vec<u8>,
+    memory_size: usize,
+    /// Start of the machine code of each bytecode instruction, followed by the end of the program
+    starts: Vec<usize>,
+    /// Jumps to labels, by the end of their 32-bit displacement
+    jumps: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Emits the 32-bit displacement of a jump to an already assembled target
+    fn emit_rel32(&mut self, target: usize) {
+        let end = self.bytes.len() + 4;
+        self.emit(&(target as i32 - end as i32).to_le_bytes());
+    }
+
+    /// Sets the 32-bit displacement ending at `end` to jump to the target
+    fn patch_rel32(&mut self, end: usize, target: usize) {
+        let displacement = (target as i32 - end as i32).to_le_bytes();
+        self.bytes[end - 4..end].copy_from_slice(&displacement);
+    }
+
+    /// Emits the 32-bit displacement of a jump to a label, patched once all of the code is assembled
+    fn jump_to(&mut self, label: Label) {
+        self.emit(&[0, 0, 0, 0]);
+        self.jumps.push((self.bytes.len(), label));
+    }
+
+    /// The offset as the number of cells to move forward, so that moves wrap around the tape
+    fn forward(&self, offset: isize) -> [u8; 4] {
+        (offset.rem_euclid(self.memory_size as isize) as u32).to_le_bytes()
+    }
+
+    /// Sets `rax` to the index of the cell at the offset from the current one
+    fn load_cell_idx(&mut self, offset: isize) {
+        let forward = self.forward(offset);
+        if forward == [0; 4] {
+            self.emit(&[0x4c, 0x89, 0xe0]); // mov rax, r12
+            return;
+        }
+        self.emit(&[0x49, 0x8d, 0x84, 0x24]); // lea rax, [r12 + forward]
+        self.emit(&forward);
+        self.emit(&[0x4c, 0x39, 0xe8]); // cmp rax, r13
+        self.emit(&[0x72, 0x03]); // jb over the next instruction
+        self.emit(&[0x4c, 0x29, 0xe8]); // sub rax, r13
+    }
+
+    /// Moves the index of the current cell by the offset
+    fn move_by(&mut self, offset: isize) {
+        let forward = self.forward(offset);
+        if forward == [0; 4] {
+            return;
+        }
+        self.emit(&[0x49, 0x81, 0xc4]); // add r12, forward
+        self.emit(&forward);
+        self.emit(&[0x4d, 0x39, 0xec]); // cmp r12, r13
+        self.emit(&[0x72, 0x03]); // jb over the next instruction
+        self.emit(&[0x4d, 0x29, 0xec]); // sub r12, r13
+    }
+
+    /// Calls a callback with the context and the argument in `rsi`, returns if it fails
+    fn call(&mut self, callback: usize) {
+        self.emit(&[0x4c, 0x89, 0xf7]); // mov rdi, r14
+        self.emit(&[0x48, 0xb8]); // mov rax, callback
+        self.emit(&(callback as u64).to_le_bytes());
+        self.emit(&[0xff, 0xd0]); // call rax
+        self.emit(&[0x84, 0xc0]); // test al, al
+        self.emit(&[0x0f, 0x85]); // jnz failure
+        self.jump_to(Label::Failure);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{bytecode::compile, optimizer::optimize, program::Program};
+
+    /// Input of the given bytes, zero at the end, and output to a vector
+    struct Streams {
+        input: Vec<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Callbacks for Streams {
+        fn output(&mut self, value: u8) -> Result<(), BfError> {
+            if self.output.len() == 3 {
+                return Err(BfError::Output(io::Error::from(io::ErrorKind::BrokenPipe)));
+            }
+            self.output.push(value);
+            Ok(())
+        }
+
+        fn input(&mut self, cell: &mut u8) -> Result<(), BfError> {
+            *cell = if self.input.is_empty() {
+                0
+            } else {
+                self.input.remove(0)
+            };
+            Ok(())
+        }
+    }
+
+    fn run_native(
+        source_code: &str,
+        input: &[u8],
+        memory_size: usize,
+    ) -> (Result<(), BfError>, Vec<u8>, Vec<u8>, usize) {
+        let program = Program::parse(source_code);
+        let code = compile(optimize(program.nodes(), Default::default()));
+        let native = NativeCode::compile(&code, memory_size).unwrap();
+        let mut streams = Streams {
+            input: input.to_vec(),
+            output: vec![],
+        };
+        let mut cells = vec![0; memory_size];
+        let mut current_idx = 0;
+        let result = native.run(&mut cells, &mut current_idx, &mut streams);
+        (result, streams.output, cells, current_idx)
+    }
+
+    #[test]
+    fn native_code_adds_and_moves() {
+        let (result, output, cells, current_idx) = run_native("+++>--->>+<", &[], 4);
+        assert!(result.is_ok());
+        assert!(output.is_empty());
+        assert_eq!(vec![3, 253, 0, 1], cells);
+        assert_eq!(2, current_idx);
+    }
+
+    #[test]
+    fn native_code_wraps_around_the_tape() {
+        let (_, _, cells, current_idx) = run_native("<+<<<<<++>>>>>>>>>>>+++", &[], 3);
+        assert_eq!(vec![2, 0, 4], cells);
+        assert_eq!(2, current_idx);
+    }
+
+    #[test]
+    fn native_code_runs_loops_and_idioms() {
+        let (_, _, cells, current_idx) = run_native("++++[->+++<]>[>>+<<-]+[-<]>>>[-]", &[], 6);
+        assert_eq!(vec![0; 6], cells);
+        assert_eq!(3, current_idx);
+        let (_, _, cells, _) = run_native("+++++[->++>>+++<<<]>[>]", &[], 6);
+        assert_eq!(vec![0, 10, 0, 15, 0, 0], cells);
+    }
+
+    #[test]
+    fn native_code_calls_back_for_input_and_output() {
+        let (result, output, _, _) = run_native(",[.,]", b"ab", 1);
+        assert!(result.is_ok());
+        assert_eq!(b"ab".to_vec(), output);
+    }
+
+    #[test]
+    fn native_code_stops_when_a_callback_fails() {
+        let (result, output, cells, _) = run_native("+[.+]", &[], 1);
+        assert!(matches!(result, Err(BfError::Output(_))));
+        assert_eq!(vec![1, 2, 3], output);
+        assert_eq!(vec![4], cells);
+    }
+
+    #[test]
+    fn native_code_is_not_compiled_for_tapes_too_big_for_its_offsets() {
+        assert!(NativeCode::compile(&[], i32::MAX as usize + 1).is_none());
+    }
+
+    #[test]
+    fn native_code_is_cached_for_the_tape_size_of_the_last_run() {
+        let program = Program::parse("+.");
+        let native = program.native_code(3).unwrap();
+        assert!(Arc::ptr_eq(&native, &program.native_code(3).unwrap()));
+        assert!(Arc::ptr_eq(
+            &native,
+            &program.clone().native_code(3).unwrap()
+        ));
+        let other = program.native_code(4).unwrap();
+        assert!(!Arc::ptr_eq(&native, &other));
+        assert!(Arc::ptr_eq(&other, &program.native_code(4).unwrap()));
+        assert!(program.native_code(0).is_none());
+    }
+}
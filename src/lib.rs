@@ -14,69 +14,483 @@
 //! let mut input = Cursor::new(vec![]);
 //! let mut output = vec![];
 //! let mut interpreter = Interpreter::new(&mut input, &mut output, 30000);
-//! interpreter.execute(&source_code);
+//! interpreter.execute(&source_code).unwrap();
 //! assert_eq!("Hello World!\n", str::from_utf8(output.as_slice()).unwrap());
 //! ```
 //!
 
+/// Flat bytecode with resolved jumps, executed by the interpreter
+mod bytecode;
+
+/// Types of the memory cells
+mod cell;
+
+/// Interactive console of the `debug` subcommand
+mod console;
+
+/// Compilation of programs to other languages
+mod codegen;
+
+/// Debug Adapter Protocol server of the `dap` subcommand
+mod dap;
+
+/// Execution of programs an instruction at a time, for debugging them
+mod debugger;
+
+/// Errors reported by the interpreter
+mod error;
+
 /// The Brainfuck interpreter
 mod interpreter;
 
+/// Compilation of the bytecode to native x86-64 code at runtime
+#[cfg(feature = "jit")]
+mod jit;
+
+#[cfg(all(feature = "jit", not(all(target_arch = "x86_64", target_os = "linux"))))]
+compile_error!("the `jit` feature is only supported on x86-64 Linux");
+
 /// Memory cells for the interpreter (memory tape)
 mod memory;
 
+/// Optimized intermediate representation of the parsed program
+mod optimizer;
+
+/// Policies for the behaviour that differs between Brainfuck implementations
+mod policy;
+
+/// Positions of instructions in the source code
+mod position;
+
+/// Parsed program that can be executed many times
+mod program;
+
 use std::{
+    cell::RefCell,
     fs,
-    io::{self, Read, Write},
+    io::{self, BufReader, Cursor, Read, Write},
 };
 
-use clap::Parser;
+use clap::{Subcommand, ValueEnum};
 
 // re-exports
-pub use interpreter::Interpreter;
+pub use cell::Cell;
+pub use codegen::Target;
+pub use debugger::{Debugger, StopReason, Watchpoint};
+pub use error::BfError;
+pub use interpreter::{ExecutionOutcome, Interpreter};
+pub use memory::Memory;
+pub use policy::{BoundaryPolicy, EofPolicy, OutputEncoding, OverflowPolicy};
+pub use position::Position;
+pub use program::{Instruction, Node, Parser, Program};
 
 /// Command-line arguments for the interpreter
 /// input_file - the path to the file to be interpreted
 /// memory_size - the number of the cells in the memory, defaults to 30 000
-#[derive(Parser, Debug)]
-#[command(author, version, about)]
+/// eof - what happens when there is no more input, defaults to leaving the cell unchanged
+/// cell_size - the number of bits in a memory cell, defaults to 8
+/// output_encoding - how a cell is written to the output, defaults to its lowest byte
+/// overflow - what happens when a cell goes beyond its range, defaults to wrapping around
+/// boundary - what happens when the pointer moves off the tape, defaults to wrapping around
+/// strict - whether unbalanced brackets are rejected instead of being closed or ignored
+/// debug_char - the character parsed as a debug instruction, if any
+/// command - what to do instead of interpreting the program, e.g. compile it
+#[derive(clap::Parser, Debug)]
+#[command(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the file to be interpreted
-    #[arg()]
+    #[arg(required = true)]
+    input_file: Option<String>,
+
+    /// Number of the cells in the memory, defaults to 30 000
+    #[arg(short, long, default_value_t = 30000)]
+    memory_size: usize,
+
+    /// What `,` does with the current cell when there is no more input
+    #[arg(long, value_enum, default_value_t = EofPolicy::Unchanged)]
+    eof: EofPolicy,
+
+    /// Number of bits in a memory cell
+    #[arg(short, long, value_enum, default_value_t = CellSize::Bits8)]
+    cell_size: CellSize,
+
+    /// How `.` writes the current cell to the output
+    #[arg(long, value_enum, default_value_t = OutputEncoding::LowByte)]
+    output_encoding: OutputEncoding,
+
+    /// What happens when a cell is incremented above its maximum value or decremented below zero
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Wrap)]
+    overflow: OverflowPolicy,
+
+    /// What happens when the data pointer is moved off either end of the tape
+    #[arg(long, value_enum, default_value_t = BoundaryPolicy::Wrap)]
+    boundary: BoundaryPolicy,
+
+    /// Reject programs with unbalanced brackets instead of closing them at the end or ignoring everything after them
+    #[arg(long)]
+    strict: bool,
+
+    /// Character parsed as a debug instruction, which shows the current position and the cells around
+    /// the current one on the standard error, `#` if the option is given without a value
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "#")]
+    debug_char: Option<char>,
+}
+
+/// What to do with the program instead of interpreting it
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compile the program to a standalone program in another language
+    Compile(CompileArgs),
+    /// Debug the program interactively, stepping through it and inspecting its memory
+    Debug(DebugArgs),
+    /// Serve the Debug Adapter Protocol on the standard input and output, for debugging from editors
+    Dap(DapArgs),
+}
+
+/// Command-line arguments for compiling a program
+#[derive(clap::Args, Debug)]
+struct CompileArgs {
+    /// Path to the file to be compiled
+    input_file: String,
+
+    /// Language to compile the program to
+    #[arg(short, long, value_enum)]
+    target: Target,
+
+    /// Number of the cells in the memory, defaults to 30 000
+    #[arg(short, long, default_value_t = 30000)]
+    memory_size: usize,
+
+    /// Path to the file to write the compiled program to, defaults to the standard output
+    #[arg(short, long)]
+    output_file: Option<String>,
+
+    /// Reject programs with unbalanced brackets instead of closing them at the end or ignoring everything after them
+    #[arg(long)]
+    strict: bool,
+}
+
+/// Command-line arguments for debugging a program
+#[derive(clap::Args, Debug)]
+struct DebugArgs {
+    /// Path to the file to be debugged
     input_file: String,
 
+    /// Path to the file the program reads its input from, as the standard input reads the debugger's commands,
+    /// defaults to no input
+    #[arg(short, long)]
+    input: Option<String>,
+
+    /// Number of the cells in the memory, defaults to 30 000
+    #[arg(short, long, default_value_t = 30000)]
+    memory_size: usize,
+
+    /// What `,` does with the current cell when there is no more input
+    #[arg(long, value_enum, default_value_t = EofPolicy::Unchanged)]
+    eof: EofPolicy,
+
+    /// Number of bits in a memory cell
+    #[arg(short, long, value_enum, default_value_t = CellSize::Bits8)]
+    cell_size: CellSize,
+
+    /// How `.` writes the current cell to the output
+    #[arg(long, value_enum, default_value_t = OutputEncoding::LowByte)]
+    output_encoding: OutputEncoding,
+
+    /// What happens when a cell is incremented above its maximum value or decremented below zero
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Wrap)]
+    overflow: OverflowPolicy,
+
+    /// What happens when the data pointer is moved off either end of the tape
+    #[arg(long, value_enum, default_value_t = BoundaryPolicy::Wrap)]
+    boundary: BoundaryPolicy,
+
+    /// Reject programs with unbalanced brackets instead of closing them at the end or ignoring everything after them
+    #[arg(long)]
+    strict: bool,
+
+    /// Character parsed as a debug instruction, which pauses the debugger and shows the cells around
+    /// the current one, `#` if the option is given without a value
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "#")]
+    debug_char: Option<char>,
+
+    /// Number of the last executed instructions the debugger can go back over, defaults to 1 000 000
+    #[arg(long, default_value_t = 1_000_000)]
+    history: usize,
+}
+
+/// Command-line arguments for serving the Debug Adapter Protocol, the program and its input are given
+/// by the editor when launching it
+#[derive(clap::Args, Debug)]
+struct DapArgs {
     /// Number of the cells in the memory, defaults to 30 000
     #[arg(short, long, default_value_t = 30000)]
     memory_size: usize,
+
+    /// What `,` does with the current cell when there is no more input
+    #[arg(long, value_enum, default_value_t = EofPolicy::Unchanged)]
+    eof: EofPolicy,
+
+    /// Number of bits in a memory cell
+    #[arg(short, long, value_enum, default_value_t = CellSize::Bits8)]
+    cell_size: CellSize,
+
+    /// How `.` writes the current cell to the output
+    #[arg(long, value_enum, default_value_t = OutputEncoding::LowByte)]
+    output_encoding: OutputEncoding,
+
+    /// What happens when a cell is incremented above its maximum value or decremented below zero
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Wrap)]
+    overflow: OverflowPolicy,
+
+    /// What happens when the data pointer is moved off either end of the tape
+    #[arg(long, value_enum, default_value_t = BoundaryPolicy::Wrap)]
+    boundary: BoundaryPolicy,
+
+    /// Reject programs with unbalanced brackets instead of closing them at the end or ignoring everything after them
+    #[arg(long)]
+    strict: bool,
+
+    /// Character parsed as a debug instruction, which pauses the debugger, `#` if the option is given
+    /// without a value
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "#")]
+    debug_char: Option<char>,
+}
+
+/// Number of bits in a memory cell, as selected on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum CellSize {
+    #[value(name = "8")]
+    Bits8,
+    #[value(name = "16")]
+    Bits16,
+    #[value(name = "32")]
+    Bits32,
+    #[value(name = "64")]
+    Bits64,
+}
+
+/// Runs the interpreter using the arguments passed - file to read the source from, memory size and cell size,
+/// or compiles the program if the arguments are for the `compile` subcommand,
+/// or debugs it reading the debugger's commands from the input if they are for the `debug` subcommand,
+/// or serves the Debug Adapter Protocol on the input and output if they are for the `dap` subcommand
+pub fn run_cmd(
+    args: Args,
+    input: &mut (impl Read + Send),
+    output: &mut impl Write,
+) -> Result<(), String> {
+    match &args.command {
+        Some(Command::Compile(compile_args)) => return compile_cmd(compile_args, output),
+        Some(Command::Debug(debug_args)) => return debug_cmd(debug_args, input, output),
+        Some(Command::Dap(dap_args)) => return dap_cmd(dap_args, input, output),
+        None => {}
+    }
+    let Some(input_file) = &args.input_file else {
+        return Err(String::from("no input file"));
+    };
+    let program = parse_file(input_file, args.strict, args.debug_char)?;
+    match args.cell_size {
+        CellSize::Bits8 => run_program::<u8>(&args, &program, input, output),
+        CellSize::Bits16 => run_program::<u16>(&args, &program, input, output),
+        CellSize::Bits32 => run_program::<u32>(&args, &program, input, output),
+        CellSize::Bits64 => run_program::<u64>(&args, &program, input, output),
+    }
+}
+
+fn run_program<T: Cell>(
+    args: &Args,
+    program: &Program,
+    input: &mut impl Read,
+    output: &mut impl Write,
+) -> Result<(), String> {
+    let mut interpreter = Interpreter::<_, _, T>::with_cell_type(input, output, args.memory_size)
+        .with_eof_policy(args.eof)
+        .with_output_encoding(args.output_encoding)
+        .with_overflow_policy(args.overflow)
+        .with_boundary_policy(args.boundary);
+    interpreter
+        .run(program)
+        .map_err(|error| error.to_string())?;
+    Ok(())
+}
+
+fn compile_cmd(args: &CompileArgs, output: &mut impl Write) -> Result<(), String> {
+    let program = parse_file(&args.input_file, args.strict, None)?;
+    let compiled = program
+        .compile(args.target, args.memory_size)
+        .map_err(|error| error.to_string())?;
+    match &args.output_file {
+        Some(output_file) => fs::write(output_file, compiled)
+            .and_then(|()| make_executable(output_file, args.target)),
+        None => output.write_all(&compiled).and_then(|()| output.flush()),
+    }
+    .map_err(|error| error.to_string())
 }
 
-/// Runs the interpreter using the arguments passed - file to read the source from and memory size
-pub fn run_cmd(args: Args, input: &mut impl Read, output: &mut impl Write) -> Result<(), String> {
+fn debug_cmd(
+    args: &DebugArgs,
+    commands: &mut impl Read,
+    output: &mut impl Write,
+) -> Result<(), String> {
+    match args.cell_size {
+        CellSize::Bits8 => debug_program::<u8>(args, commands, output),
+        CellSize::Bits16 => debug_program::<u16>(args, commands, output),
+        CellSize::Bits32 => debug_program::<u32>(args, commands, output),
+        CellSize::Bits64 => debug_program::<u64>(args, commands, output),
+    }
+}
+
+fn debug_program<T: Cell>(
+    args: &DebugArgs,
+    commands: &mut impl Read,
+    output: &mut impl Write,
+) -> Result<(), String> {
     let source_code = read_file_contents(&args.input_file)?;
-    let mut interpreter = Interpreter::new(input, output, args.memory_size);
-    interpreter.execute(&source_code);
+    let program = parse_source(&source_code, args.strict, args.debug_char)?;
+    let program_input = match &args.input {
+        Some(input_file) => fs::read(input_file).map_err(|error| file_error(input_file, error))?,
+        None => vec![],
+    };
+    let mut program_input = Cursor::new(program_input);
+    let output = RefCell::new(output);
+    let mut program_output = console::SharedWriter(&output);
+    let mut interpreter = Interpreter::<_, _, T>::with_cell_type(
+        &mut program_input,
+        &mut program_output,
+        args.memory_size,
+    )
+    .with_eof_policy(args.eof)
+    .with_output_encoding(args.output_encoding)
+    .with_overflow_policy(args.overflow)
+    .with_boundary_policy(args.boundary);
+    let mut debugger = interpreter.debug(&program).with_history(args.history);
+    console::run(
+        &mut debugger,
+        &source_code,
+        &mut BufReader::new(commands),
+        &output,
+    )
+    .map_err(|error| error.to_string())
+}
+
+fn dap_cmd(
+    args: &DapArgs,
+    requests: &mut (impl Read + Send),
+    output: &mut impl Write,
+) -> Result<(), String> {
+    let requests = &mut BufReader::new(requests);
+    match args.cell_size {
+        CellSize::Bits8 => dap::run::<u8>(args, requests, output),
+        CellSize::Bits16 => dap::run::<u16>(args, requests, output),
+        CellSize::Bits32 => dap::run::<u32>(args, requests, output),
+        CellSize::Bits64 => dap::run::<u64>(args, requests, output),
+    }
+    .map_err(|error| error.to_string())
+}
+
+/// Allows the compiled program to be executed if the target is an executable
+#[cfg(unix)]
+fn make_executable(path: &str, target: Target) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if target != Target::X86_64Linux {
+        return Ok(());
+    }
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &str, _target: Target) -> io::Result<()> {
     Ok(())
 }
 
+fn parse_file(
+    input_file_path: &str,
+    strict: bool,
+    debug_char: Option<char>,
+) -> Result<Program, String> {
+    parse_source(&read_file_contents(input_file_path)?, strict, debug_char)
+}
+
+fn parse_source(
+    source_code: &str,
+    strict: bool,
+    debug_char: Option<char>,
+) -> Result<Program, String> {
+    let mut parser = Parser::new().with_strict(strict);
+    if let Some(debug_char) = debug_char {
+        if "><+-.,[]".contains(debug_char) {
+            return Err(format!(
+                "'{debug_char}' is an instruction and can't be the debug character"
+            ));
+        }
+        parser = parser.with_debug_char(debug_char);
+    }
+    parser.parse(source_code).map_err(|error| error.to_string())
+}
+
 fn read_file_contents(input_file_path: &str) -> Result<String, String> {
-    fs::read_to_string(input_file_path).map_err(|error| match error.kind() {
-        io::ErrorKind::NotFound => format!("no such file: '{input_file_path}'"),
+    fs::read_to_string(input_file_path).map_err(|error| file_error(input_file_path, error))
+}
+
+fn file_error(file_path: &str, error: io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::NotFound => format!("no such file: '{file_path}'"),
         _ => error.to_string(),
-    })
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use clap::Parser as _;
     use io::Cursor;
-    use std::str;
+    use std::{env, path::PathBuf, process, str};
 
     use super::*;
 
+    /// File of its own in the temporary directory, deleted when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            TempFile(env::temp_dir().join(format!("vtashkov-bf-{}-{name}", process::id())))
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
     #[test]
     fn run_cmd_can_be_invoked() {
         let args = Args {
-            input_file: String::from(""),
+            command: None,
+            input_file: Some(String::from("")),
             memory_size: 1,
+            eof: EofPolicy::Unchanged,
+            cell_size: CellSize::Bits8,
+            output_encoding: OutputEncoding::LowByte,
+            overflow: OverflowPolicy::Wrap,
+            boundary: BoundaryPolicy::Wrap,
+            strict: false,
+            debug_char: None,
         };
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
@@ -87,8 +501,16 @@ mod tests {
     fn run_cmd_with_wrong_input_file_returns_no_such_file() {
         let invalid_file_name = "./examples/invalid.bf";
         let args = Args {
-            input_file: String::from(invalid_file_name),
+            command: None,
+            input_file: Some(String::from(invalid_file_name)),
             memory_size: 1,
+            eof: EofPolicy::Unchanged,
+            cell_size: CellSize::Bits8,
+            output_encoding: OutputEncoding::LowByte,
+            overflow: OverflowPolicy::Wrap,
+            boundary: BoundaryPolicy::Wrap,
+            strict: false,
+            debug_char: None,
         };
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
@@ -101,8 +523,16 @@ mod tests {
     #[test]
     fn run_cmd_can_execute_hello_world() {
         let args = Args {
-            input_file: String::from("./examples/hello_world.bf"),
+            command: None,
+            input_file: Some(String::from("./examples/hello_world.bf")),
             memory_size: 30000,
+            eof: EofPolicy::Unchanged,
+            cell_size: CellSize::Bits8,
+            output_encoding: OutputEncoding::LowByte,
+            overflow: OverflowPolicy::Wrap,
+            boundary: BoundaryPolicy::Wrap,
+            strict: false,
+            debug_char: None,
         };
         let mut input = Cursor::new(vec![]);
         let mut output = vec![];
@@ -110,4 +540,395 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!("Hello World!\n", str::from_utf8(output.as_slice()).unwrap());
     }
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn run_cmd_with_closed_output_returns_error_message() {
+        let args = Args {
+            command: None,
+            input_file: Some(String::from("./examples/hello_world.bf")),
+            memory_size: 30000,
+            eof: EofPolicy::Unchanged,
+            cell_size: CellSize::Bits8,
+            output_encoding: OutputEncoding::LowByte,
+            overflow: OverflowPolicy::Wrap,
+            boundary: BoundaryPolicy::Wrap,
+            strict: false,
+            debug_char: None,
+        };
+        let mut input = Cursor::new(vec![]);
+        let result = run_cmd(args, &mut input, &mut BrokenPipe);
+        assert_eq!("failed to write output: broken pipe", result.err().unwrap())
+    }
+
+    #[test]
+    fn run_cmd_applies_eof_policy() {
+        let args = Args {
+            command: None,
+            input_file: Some(String::from("./examples/rot13.bf")),
+            memory_size: 30000,
+            eof: EofPolicy::Error,
+            cell_size: CellSize::Bits8,
+            output_encoding: OutputEncoding::LowByte,
+            overflow: OverflowPolicy::Wrap,
+            boundary: BoundaryPolicy::Wrap,
+            strict: false,
+            debug_char: None,
+        };
+        let mut input = Cursor::new(b"Hello".to_vec());
+        let mut output = vec![];
+        let result = run_cmd(args, &mut input, &mut output);
+        assert_eq!("unexpected end of input", result.err().unwrap());
+        assert_eq!("Uryyb", str::from_utf8(output.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn args_accept_eof_policy() {
+        let args = Args::parse_from(["vtashkov-bf", "--eof", "max-value", "program.bf"]);
+        assert_eq!(EofPolicy::MaxValue, args.eof);
+    }
+
+    #[test]
+    fn args_accept_cell_size_and_output_encoding() {
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "--cell-size",
+            "16",
+            "--output-encoding",
+            "utf8",
+            "program.bf",
+        ]);
+        assert_eq!(CellSize::Bits16, args.cell_size);
+        assert_eq!(OutputEncoding::Utf8, args.output_encoding);
+    }
+
+    #[test]
+    fn run_cmd_can_execute_with_every_cell_size() {
+        for cell_size in [
+            CellSize::Bits8,
+            CellSize::Bits16,
+            CellSize::Bits32,
+            CellSize::Bits64,
+        ] {
+            let args = Args {
+                command: None,
+                input_file: Some(String::from("./examples/hello_world.bf")),
+                memory_size: 30000,
+                eof: EofPolicy::Unchanged,
+                cell_size,
+                output_encoding: OutputEncoding::LowByte,
+                overflow: OverflowPolicy::Wrap,
+                boundary: BoundaryPolicy::Wrap,
+                strict: false,
+                debug_char: None,
+            };
+            let mut input = Cursor::new(vec![]);
+            let mut output = vec![];
+            let result = run_cmd(args, &mut input, &mut output);
+            assert!(result.is_ok());
+            assert_eq!("Hello World!\n", str::from_utf8(output.as_slice()).unwrap());
+        }
+    }
+
+    #[test]
+    fn args_accept_overflow_policy() {
+        let args = Args::parse_from(["vtashkov-bf", "--overflow", "trap", "program.bf"]);
+        assert_eq!(OverflowPolicy::Trap, args.overflow);
+    }
+
+    #[test]
+    fn args_accept_boundary_policy() {
+        let args = Args::parse_from(["vtashkov-bf", "--boundary", "grow-both", "program.bf"]);
+        assert_eq!(BoundaryPolicy::GrowBoth, args.boundary);
+    }
+
+    #[test]
+    fn run_cmd_in_strict_mode_reports_unbalanced_brackets() {
+        let input_file = TempFile::new("unbalanced.bf");
+        fs::write(input_file.path(), "+[.\n-]]").unwrap();
+        let args = Args {
+            command: None,
+            input_file: Some(String::from(input_file.path())),
+            memory_size: 30000,
+            eof: EofPolicy::Unchanged,
+            cell_size: CellSize::Bits8,
+            output_encoding: OutputEncoding::LowByte,
+            overflow: OverflowPolicy::Wrap,
+            boundary: BoundaryPolicy::Wrap,
+            strict: true,
+            debug_char: None,
+        };
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let result = run_cmd(args, &mut input, &mut output);
+        assert_eq!("unmatched ']' at line 2, column 3", result.err().unwrap());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn args_accept_strict_mode() {
+        let args = Args::parse_from(["vtashkov-bf", "--strict", "program.bf"]);
+        assert!(args.strict);
+    }
+
+    #[test]
+    fn args_accept_input_file_without_subcommand() {
+        let args = Args::parse_from(["vtashkov-bf", "compile.bf"]);
+        assert!(args.command.is_none());
+        assert_eq!(Some(String::from("compile.bf")), args.input_file);
+    }
+
+    #[test]
+    fn args_require_input_file_without_subcommand() {
+        assert!(Args::try_parse_from(["vtashkov-bf", "--strict"]).is_err());
+    }
+
+    #[test]
+    fn args_accept_compile_subcommand() {
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "compile",
+            "--target",
+            "c",
+            "-m",
+            "100",
+            "program.bf",
+        ]);
+        let Some(Command::Compile(compile_args)) = args.command else {
+            panic!("expected the compile subcommand");
+        };
+        assert_eq!(Target::C, compile_args.target);
+        assert_eq!(100, compile_args.memory_size);
+        assert_eq!("program.bf", compile_args.input_file);
+    }
+
+    #[test]
+    fn args_require_target_of_compile_subcommand() {
+        assert!(Args::try_parse_from(["vtashkov-bf", "compile", "program.bf"]).is_err());
+    }
+
+    #[test]
+    fn run_cmd_compiles_to_c() {
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "compile",
+            "--target",
+            "c",
+            "./examples/hello_world.bf",
+        ]);
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        run_cmd(args, &mut input, &mut output).unwrap();
+        let c = str::from_utf8(output.as_slice()).unwrap();
+        assert!(c.contains("#define MEMORY_SIZE 30000"));
+        assert!(c.contains("putchar("));
+    }
+
+    #[test]
+    fn run_cmd_does_not_compile_a_tape_without_cells() {
+        for target in Target::value_variants() {
+            let target = target.to_possible_value().unwrap();
+            let args = Args::parse_from([
+                "vtashkov-bf",
+                "compile",
+                "--target",
+                target.get_name(),
+                "-m",
+                "0",
+                "./examples/hello_world.bf",
+            ]);
+            let mut input = Cursor::new(vec![]);
+            let mut output = vec![];
+            let result = run_cmd(args, &mut input, &mut output);
+            assert_eq!(
+                Err(String::from("the tape needs at least one cell")),
+                result,
+                "{}",
+                target.get_name()
+            );
+        }
+    }
+
+    #[test]
+    fn run_cmd_does_not_compile_a_tape_too_big_for_the_target() {
+        for target in ["x86_64-linux", "x86_64-linux-asm"] {
+            let args = Args::parse_from([
+                "vtashkov-bf",
+                "compile",
+                "--target",
+                target,
+                "-m",
+                "5000000000",
+                "./examples/hello_world.bf",
+            ]);
+            let mut input = Cursor::new(vec![]);
+            let mut output = vec![];
+            let result = run_cmd(args, &mut input, &mut output);
+            assert_eq!(
+                Err(String::from(
+                    "a tape of 5000000000 cells is too big for the target, it supports at most 2147483647"
+                )),
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn run_cmd_compiles_to_output_file() {
+        let output_file = TempFile::new("compiled.c");
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "compile",
+            "--target",
+            "c",
+            "-o",
+            output_file.path(),
+            "./examples/hello_world.bf",
+        ]);
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        run_cmd(args, &mut input, &mut output).unwrap();
+        let c = fs::read_to_string(output_file.path()).unwrap();
+        assert!(output.is_empty());
+        assert!(c.contains("int main(void)"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_cmd_compiles_to_an_executable_output_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let output_file = TempFile::new("compiled-elf");
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "compile",
+            "--target",
+            "x86_64-linux",
+            "-o",
+            output_file.path(),
+            "./examples/hello_world.bf",
+        ]);
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        run_cmd(args, &mut input, &mut output).unwrap();
+        let permissions = fs::metadata(output_file.path()).unwrap().permissions();
+        assert_eq!(0o111, permissions.mode() & 0o111);
+    }
+
+    #[test]
+    fn args_accept_debug_subcommand() {
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "debug",
+            "--input",
+            "input.txt",
+            "--overflow",
+            "trap",
+            "program.bf",
+        ]);
+        let Some(Command::Debug(debug_args)) = args.command else {
+            panic!("expected the debug subcommand");
+        };
+        assert_eq!(Some(String::from("input.txt")), debug_args.input);
+        assert_eq!(OverflowPolicy::Trap, debug_args.overflow);
+        assert_eq!("program.bf", debug_args.input_file);
+        assert_eq!(1_000_000, debug_args.history);
+    }
+
+    #[test]
+    fn args_accept_dap_subcommand() {
+        let args = Args::parse_from(["vtashkov-bf", "dap", "--cell-size", "16", "--debug-char"]);
+        let Some(Command::Dap(dap_args)) = args.command else {
+            panic!("expected the dap subcommand");
+        };
+        assert_eq!(CellSize::Bits16, dap_args.cell_size);
+        assert_eq!(Some('#'), dap_args.debug_char);
+    }
+
+    #[test]
+    fn run_cmd_debugs_with_commands_from_the_input() {
+        let input_file = TempFile::new("debug-input.txt");
+        fs::write(input_file.path(), "a").unwrap();
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "debug",
+            "-i",
+            input_file.path(),
+            "./examples/rot13.bf",
+        ]);
+        let mut commands = Cursor::new(b"b 5:1\nc\nt 1\nd 5:1\nc\n".to_vec());
+        let mut output = vec![];
+        run_cmd(args, &mut commands, &mut output).unwrap();
+        let output = str::from_utf8(output.as_slice()).unwrap();
+        assert!(output.starts_with("at line 1, column 1\n"));
+        assert!(output.contains("(bf) breakpoint at line 5, column 9\n(bf) breakpoint hit\n"));
+        assert!(output.contains("(bf)    1: 0\n-> 2: 0\n   3: 32\n"));
+        assert!(
+            output.contains("(bf) nthe program has finished, 1 bytes read and 1 bytes written\n")
+        );
+    }
+
+    #[test]
+    fn run_cmd_debug_with_wrong_input_returns_no_such_file() {
+        let args = Args::parse_from([
+            "vtashkov-bf",
+            "debug",
+            "--input",
+            "./examples/invalid.txt",
+            "./examples/hello_world.bf",
+        ]);
+        let mut commands = Cursor::new(vec![]);
+        let mut output = vec![];
+        let result = run_cmd(args, &mut commands, &mut output);
+        assert_eq!(
+            "no such file: './examples/invalid.txt'",
+            result.err().unwrap()
+        );
+    }
+
+    #[test]
+    fn args_accept_debug_char_with_or_without_value() {
+        let args = Args::parse_from(["vtashkov-bf", "program.bf"]);
+        assert_eq!(None, args.debug_char);
+        let args = Args::parse_from(["vtashkov-bf", "--debug-char", "program.bf"]);
+        assert_eq!(Some('#'), args.debug_char);
+        assert_eq!(Some(String::from("program.bf")), args.input_file);
+        let args = Args::parse_from(["vtashkov-bf", "--debug-char=!", "program.bf"]);
+        assert_eq!(Some('!'), args.debug_char);
+    }
+
+    #[test]
+    fn run_cmd_rejects_instruction_as_debug_char() {
+        let args = Args::parse_from(["vtashkov-bf", "--debug-char=+", "./examples/hello_world.bf"]);
+        let mut input = Cursor::new(vec![]);
+        let mut output = vec![];
+        let result = run_cmd(args, &mut input, &mut output);
+        assert_eq!(
+            "'+' is an instruction and can't be the debug character",
+            result.err().unwrap()
+        );
+    }
+
+    #[test]
+    fn run_cmd_debug_pauses_at_debug_char() {
+        let input_file = TempFile::new("debug-char.bf");
+        fs::write(input_file.path(), "++!+.").unwrap();
+        let args = Args::parse_from(["vtashkov-bf", "debug", "--debug-char=!", input_file.path()]);
+        let mut commands = Cursor::new(b"c\n".to_vec());
+        let mut output = vec![];
+        run_cmd(args, &mut commands, &mut output).unwrap();
+        let output = str::from_utf8(output.as_slice()).unwrap();
+        assert!(output.contains("(bf) debug instruction\nat line 1, column 3\n"));
+    }
 }
@@ -1,43 +1,82 @@
-use std::{
-    num::Wrapping,
-    ops::{AddAssign, SubAssign},
-};
+use std::fmt;
+
+use crate::cell::Cell;
 
 /// Memory tape, as need for the Brainfuck interpreter
 pub struct Memory<T> {
-    cells: Vec<Wrapping<T>>,
+    cells: Vec<T>,
     current_idx: usize,
+    write_hook: Option<Box<dyn FnMut(usize, T) + Send>>,
 }
 
 impl<T> Memory<T>
 where
-    T: Default + Clone,
-    Wrapping<T>: AddAssign<Wrapping<u8>> + SubAssign<Wrapping<u8>>,
+    T: Cell,
 {
-    const ONE: Wrapping<u8> = Wrapping(1);
-
     /// Creates new Memory tape with a given size
     pub fn new(size: usize) -> Memory<T> {
         Memory {
-            cells: vec![Wrapping(T::default()); size],
+            cells: vec![T::default(); size],
             current_idx: 0,
+            write_hook: None,
+        }
+    }
+
+    /// Sets a hook called with the index and the new value of each cell written, e.g. to watch the cells,
+    /// instead of the one set before
+    /// Clearing the memory and growing it with zeroed cells aren't reported, and growing it to the left
+    /// shifts the indices of the cells
+    /// The hook is sent along with the memory, so that the memory can be moved to another thread
+    pub fn set_write_hook(&mut self, hook: impl FnMut(usize, T) + Send + 'static) {
+        self.write_hook = Some(Box::new(hook));
+    }
+
+    /// Removes the hook set with [`Memory::set_write_hook`], if any
+    pub fn remove_write_hook(&mut self) {
+        self.write_hook = None;
+    }
+
+    /// All the memory cells and the index of the current one, for code that accesses them directly
+    #[cfg(feature = "jit")]
+    pub(crate) fn cells_mut(&mut self) -> (&mut [T], &mut usize) {
+        (&mut self.cells, &mut self.current_idx)
+    }
+
+    /// All the memory cells, from the beginning of the tape
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// Index of the current memory cell, the one the data pointer points to
+    pub fn pointer(&self) -> usize {
+        self.current_idx
+    }
+
+    /// The cells within a distance from the current one, to be displayed each on its own line with its index
+    pub(crate) fn window(&self, radius: usize) -> Window<'_, T> {
+        Window {
+            memory: self,
+            radius,
         }
     }
 
     /// Clears all the memory cells, but doesn't change their number
     pub fn clear(&mut self) {
-        for item in &mut self.cells { *item = Wrapping(T::default()); }
+        for item in &mut self.cells {
+            *item = T::default();
+        }
         self.current_idx = 0;
     }
 
     /// Reads the value of the current memory cell
     pub fn read(&self) -> &T {
-        &self.cells[self.current_idx].0
+        &self.cells[self.current_idx]
     }
 
     /// Writes a value to the current memory cell
     pub fn write(&mut self, value: T) {
-        self.cells[self.current_idx] = Wrapping(value);
+        self.cells[self.current_idx] = value;
+        self.written(self.current_idx);
     }
 
     /// Moves the pointer to he next memory cell
@@ -58,21 +97,198 @@ where
         self.current_idx -= 1;
     }
 
+    /// Moves the pointer by the given number of cells, to the right if positive and to the left if negative
+    /// If it goes past either end of the tape, it wraps around to the other end
+    pub fn move_by(&mut self, offset: isize) {
+        self.current_idx = self.offset_idx(offset);
+    }
+
+    /// Moves the pointer by the given step until it reaches a memory cell holding zero
+    /// If it goes past either end of the tape, it wraps around to the other end
+    pub fn scan_zero(&mut self, step: isize) {
+        let zero = T::default();
+        let stride = step.unsigned_abs();
+        loop {
+            let (found, visited) = if step > 0 {
+                let cells = &self.cells[self.current_idx..];
+                let found = cells.iter().step_by(stride).position(|cell| *cell == zero);
+                (found, cells.len().div_ceil(stride))
+            } else {
+                let cells = &self.cells[..=self.current_idx];
+                let found = cells
+                    .iter()
+                    .rev()
+                    .step_by(stride)
+                    .position(|cell| *cell == zero);
+                (found, cells.len().div_ceil(stride))
+            };
+            match found {
+                Some(steps) => return self.move_by(steps as isize * step),
+                None => self.move_by(visited as isize * step),
+            }
+        }
+    }
+
+    /// Checks if the cell at an offset from the current one is on the tape, without wrapping around
+    pub fn contains(&self, offset: isize) -> bool {
+        self.current_idx
+            .checked_add_signed(offset)
+            .is_some_and(|idx| idx < self.cells.len())
+    }
+
+    /// Grows the tape with zeroed cells, so that the cell at an offset from the current one is on it
+    /// When growing to the left, the cells are shifted and the current cell stays the same
+    pub fn grow(&mut self, offset: isize) {
+        match self.current_idx.checked_add_signed(offset) {
+            Some(idx) if idx >= self.cells.len() => {
+                let size = (idx + 1).max(self.cells.len() * 2);
+                self.cells.resize(size, T::default());
+            }
+            Some(_) => {}
+            None => {
+                let missing = offset.unsigned_abs() - self.current_idx;
+                let added = missing.max(self.cells.len());
+                self.cells
+                    .splice(0..0, std::iter::repeat_n(T::default(), added));
+                self.current_idx += added;
+            }
+        }
+    }
+
+    /// Removes cells added by growing the tape in the direction of the offset, to undo the growth
+    pub(crate) fn shrink(&mut self, offset: isize, count: usize) {
+        if offset < 0 {
+            self.cells.drain(..count);
+            self.current_idx = self.current_idx.saturating_sub(count);
+        } else {
+            self.cells.truncate(self.cells.len() - count);
+            self.current_idx = self.current_idx.min(self.cells.len() - 1);
+        }
+    }
+
+    /// Moves the pointer to the cell at the index, to restore it
+    pub(crate) fn set_pointer(&mut self, idx: usize) {
+        self.current_idx = idx;
+    }
+
+    /// Sets the value of the cell at the index, to restore it, without reporting it to the write hook
+    pub(crate) fn set_cell(&mut self, idx: usize, value: T) {
+        self.cells[idx] = value;
+    }
+
+    /// Finds the offset of the nearest zero cell, starting with the current one and moving by a step,
+    /// without wrapping around; `None` if there is no such cell before the end of the tape
+    pub fn find_zero(&self, step: isize) -> Option<isize> {
+        let zero = T::default();
+        let stride = step.unsigned_abs();
+        let found = if step > 0 {
+            self.cells[self.current_idx..]
+                .iter()
+                .step_by(stride)
+                .position(|cell| *cell == zero)
+        } else {
+            self.cells[..=self.current_idx]
+                .iter()
+                .rev()
+                .step_by(stride)
+                .position(|cell| *cell == zero)
+        };
+        found.map(|steps| steps as isize * step)
+    }
+
     /// Increments the value of the current memory cell by 1
     /// If the current memory cell holds the maximum value, it wraps around
     pub fn increment(&mut self) {
-        self.cells[self.current_idx] += Self::ONE;
+        self.add(T::ONE);
     }
 
     /// Decrements the value of the current memory cell by 1
     /// If the current memory cell holds the minimum value, it wraps around
     pub fn decrement(&mut self) {
-        self.cells[self.current_idx] -= Self::ONE;
+        let cell = &mut self.cells[self.current_idx];
+        *cell = cell.wrapping_sub(T::ONE);
+        self.written(self.current_idx);
+    }
+
+    /// Adds a value to the current memory cell
+    /// If the result is beyond the maximum value, it wraps around
+    pub fn add(&mut self, value: T) {
+        let cell = &mut self.cells[self.current_idx];
+        *cell = cell.wrapping_add(value);
+        self.written(self.current_idx);
+    }
+
+    /// Reads the value of the memory cell at the given offset from the current one
+    /// The offset wraps around the ends of the tape like in `move_by`
+    pub fn read_at(&self, offset: isize) -> &T {
+        &self.cells[self.offset_idx(offset)]
+    }
+
+    /// Writes a value to the memory cell at the given offset from the current one
+    /// The offset wraps around the ends of the tape like in `move_by`
+    pub fn write_at(&mut self, offset: isize, value: T) {
+        let idx = self.offset_idx(offset);
+        self.cells[idx] = value;
+        self.written(idx);
+    }
+
+    /// Adds a value to the memory cell at the given offset from the current one
+    /// The offset wraps around the ends of the tape and the value wraps around like in `add`
+    pub fn add_at(&mut self, offset: isize, value: T) {
+        let idx = self.offset_idx(offset);
+        self.cells[idx] = self.cells[idx].wrapping_add(value);
+        self.written(idx);
+    }
+
+    /// Reports the write of the cell at the index to the hook, if any
+    fn written(&mut self, idx: usize) {
+        if let Some(hook) = &mut self.write_hook {
+            hook(idx, self.cells[idx]);
+        }
+    }
+
+    fn offset_idx(&self, offset: isize) -> usize {
+        let len = self.cells.len() as isize;
+        let idx = self.current_idx as isize + offset.rem_euclid(len);
+        idx.rem_euclid(len) as usize
+    }
+}
+
+/// The cells within a distance from the current one, displayed each on its own line with its index
+/// and the current one marked with `->`
+pub(crate) struct Window<'m, T> {
+    memory: &'m Memory<T>,
+    radius: usize,
+}
+
+impl<T> fmt::Display for Window<'_, T>
+where
+    T: Cell,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(last_idx) = self.memory.cells.len().checked_sub(1) else {
+            return Ok(());
+        };
+        let current_idx = self.memory.current_idx;
+        let first = current_idx.saturating_sub(self.radius);
+        let last = current_idx.saturating_add(self.radius).min(last_idx);
+        let width = last.to_string().len();
+        for idx in first..=last {
+            let marker = if idx == current_idx { "->" } else { "  " };
+            let value = self.memory.cells[idx].to_u64();
+            writeln!(f, "{marker} {idx:>width$}: {value}")?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
     use super::*;
 
     #[test]
@@ -232,7 +448,7 @@ mod tests {
     #[test]
     fn memory_cell_is_wrapping_after_incrementing_the_max_value() {
         let mut memory: Memory<u8> = Memory::new(1);
-        memory.write(u8::max_value());
+        memory.write(u8::MAX);
         memory.increment();
         let cell_value = memory.read();
         assert_eq!(0, *cell_value);
@@ -271,7 +487,7 @@ mod tests {
         assert_eq!(0, *cell_value);
         memory.decrement();
         let cell_value = memory.read();
-        assert_eq!(u8::max_value(), *cell_value);
+        assert_eq!(u8::MAX, *cell_value);
     }
 
     #[test]
@@ -291,4 +507,285 @@ mod tests {
         let cell_value = memory.read();
         assert_eq!(0, *cell_value);
     }
+
+    #[test]
+    fn memory_can_move_by_offset_in_both_directions() {
+        let mut memory: Memory<u8> = Memory::new(5);
+        memory.move_by(3);
+        memory.write(3);
+        memory.move_by(-2);
+        memory.write(1);
+        memory.move_by(2);
+        let cell_value = memory.read();
+        assert_eq!(3, *cell_value);
+    }
+
+    #[test]
+    fn memory_wraps_around_when_moving_by_offset_past_the_end() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.write(1);
+        memory.move_by(7);
+        memory.write(2);
+        memory.move_by(-1);
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+    }
+
+    #[test]
+    fn memory_wraps_around_when_moving_by_offset_before_the_beginning() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.write(1);
+        memory.move_by(-4);
+        memory.write(2);
+        memory.move_by(-2);
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+        memory.move_by(-1);
+        let cell_value = memory.read();
+        assert_eq!(2, *cell_value);
+    }
+
+    #[test]
+    fn memory_cell_can_be_added_to() {
+        let mut memory: Memory<u8> = Memory::new(1);
+        memory.add(5);
+        let cell_value = memory.read();
+        assert_eq!(5, *cell_value);
+    }
+
+    #[test]
+    fn memory_cell_is_wrapping_after_adding_beyond_the_max_value() {
+        let mut memory: Memory<u8> = Memory::new(1);
+        memory.write(250);
+        memory.add(10);
+        let cell_value = memory.read();
+        assert_eq!(4, *cell_value);
+    }
+
+    #[test]
+    fn memory_cell_at_offset_can_be_added_to() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.add_at(2, 5);
+        memory.add_at(0, 1);
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+        memory.move_by(2);
+        let cell_value = memory.read();
+        assert_eq!(5, *cell_value);
+    }
+
+    #[test]
+    fn memory_offset_wraps_around_when_adding_at_offset() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.add_at(-1, 255);
+        memory.add_at(2, 2);
+        memory.previous();
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+    }
+
+    #[test]
+    fn memory_scan_stays_on_zero_cell() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.scan_zero(1);
+        memory.write(1);
+        memory.next();
+        let cell_value = memory.read();
+        assert_eq!(0, *cell_value);
+    }
+
+    #[test]
+    fn memory_scan_finds_next_zero_cell_to_the_right() {
+        let mut memory: Memory<u8> = Memory::new(5);
+        memory.write(1);
+        memory.next();
+        memory.write(2);
+        memory.previous();
+        memory.scan_zero(1);
+        memory.write(3);
+        memory.move_by(-2);
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+        memory.move_by(2);
+        let cell_value = memory.read();
+        assert_eq!(3, *cell_value);
+    }
+
+    #[test]
+    fn memory_scan_finds_next_zero_cell_to_the_left_wrapping_around() {
+        let mut memory: Memory<u8> = Memory::new(4);
+        memory.write(1);
+        memory.next();
+        memory.write(2);
+        memory.scan_zero(-1);
+        let cell_value = memory.read();
+        assert_eq!(0, *cell_value);
+        memory.next();
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+    }
+
+    #[test]
+    fn memory_scan_with_step_skips_cells() {
+        let mut memory: Memory<u8> = Memory::new(5);
+        memory.write(1);
+        memory.scan_zero(2);
+        memory.write(2);
+        memory.move_by(-2);
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+    }
+
+    #[test]
+    fn memory_scan_with_step_continues_after_wrapping_around() {
+        let mut memory: Memory<u8> = Memory::new(5);
+        for value in [1, 0, 1, 1, 1] {
+            memory.write(value);
+            memory.next();
+        }
+        memory.move_by(2);
+        memory.scan_zero(2);
+        let cell_value = memory.read();
+        assert_eq!(0, *cell_value);
+        memory.next();
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+    }
+
+    #[test]
+    fn memory_cell_at_offset_can_be_written_and_read() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.write_at(1, 1);
+        memory.write_at(-1, 2);
+        let cell_value = memory.read_at(1);
+        assert_eq!(1, *cell_value);
+        let cell_value = memory.read_at(2);
+        assert_eq!(2, *cell_value);
+        let cell_value = memory.read();
+        assert_eq!(0, *cell_value);
+    }
+
+    #[test]
+    fn memory_offset_wraps_around_like_next_and_previous() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.next();
+        memory.next();
+        memory.write_at(1, 1);
+        memory.write_at(-4, 2);
+        memory.next();
+        let cell_value = memory.read();
+        assert_eq!(1, *cell_value);
+        memory.next();
+        let cell_value = memory.read();
+        assert_eq!(2, *cell_value);
+    }
+
+    #[test]
+    fn memory_cells_can_be_wider_than_a_byte() {
+        let mut memory: Memory<u16> = Memory::new(1);
+        memory.write(255);
+        memory.increment();
+        let cell_value = memory.read();
+        assert_eq!(256, *cell_value);
+    }
+
+    #[test]
+    fn memory_wide_cell_is_wrapping_after_decrementing_zero() {
+        let mut memory: Memory<u64> = Memory::new(1);
+        memory.decrement();
+        let cell_value = memory.read();
+        assert_eq!(u64::MAX, *cell_value);
+    }
+
+    #[test]
+    fn memory_contains_only_cells_on_the_tape() {
+        let mut memory: Memory<u8> = Memory::new(3);
+        memory.next();
+        assert!(memory.contains(0));
+        assert!(memory.contains(-1));
+        assert!(memory.contains(1));
+        assert!(!memory.contains(-2));
+        assert!(!memory.contains(2));
+    }
+
+    #[test]
+    fn memory_grows_to_the_right() {
+        let mut memory: Memory<u8> = Memory::new(2);
+        memory.write(7);
+        memory.grow(5);
+        assert!(memory.contains(5));
+        assert_eq!(7, *memory.read());
+        assert_eq!(0, *memory.read_at(5));
+    }
+
+    #[test]
+    fn memory_grows_to_the_left_keeping_the_current_cell() {
+        let mut memory: Memory<u8> = Memory::new(2);
+        memory.next();
+        memory.write(7);
+        memory.grow(-4);
+        assert!(memory.contains(-4));
+        assert_eq!(7, *memory.read());
+        memory.write_at(-4, 3);
+        assert_eq!(3, *memory.read_at(-4));
+        assert_eq!(0, *memory.read_at(-1));
+    }
+
+    #[test]
+    fn memory_finds_zero_without_wrapping_around() {
+        let mut memory: Memory<u8> = Memory::new(5);
+        for value in [1, 1, 0, 1, 1] {
+            memory.write(value);
+            memory.next();
+        }
+        assert_eq!(Some(2), memory.find_zero(1));
+        assert_eq!(None, memory.find_zero(-1));
+        memory.move_by(3);
+        assert_eq!(None, memory.find_zero(1));
+        assert_eq!(Some(-1), memory.find_zero(-1));
+        assert_eq!(None, memory.find_zero(-2));
+    }
+
+    #[test]
+    fn memory_window_shows_the_cells_around_the_current_one() {
+        let mut memory: Memory<u8> = Memory::new(12);
+        memory.move_by(9);
+        memory.write(72);
+        assert_eq!(
+            "    7: 0\n    8: 0\n->  9: 72\n   10: 0\n   11: 0\n",
+            memory.window(2).to_string()
+        );
+        memory.move_by(3);
+        assert_eq!("-> 0: 0\n   1: 0\n", memory.window(1).to_string());
+    }
+
+    #[test]
+    fn memory_reports_writes_to_the_hook() {
+        let writes = Arc::new(Mutex::new(vec![]));
+        let mut memory: Memory<u8> = Memory::new(4);
+        let hook_writes = Arc::clone(&writes);
+        memory.set_write_hook(move |idx, value| hook_writes.lock().unwrap().push((idx, value)));
+        memory.increment();
+        memory.add_at(-1, 5);
+        memory.write_at(2, 7);
+        memory.next();
+        memory.decrement();
+        memory.write(9);
+        memory.remove_write_hook();
+        memory.increment();
+        assert_eq!(
+            vec![(0, 1), (3, 5), (2, 7), (1, 255), (1, 9)],
+            *writes.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn memory_with_a_hook_can_be_moved_to_another_thread() {
+        let writes = Arc::new(Mutex::new(vec![]));
+        let mut memory: Memory<u8> = Memory::new(2);
+        let hook_writes = Arc::clone(&writes);
+        memory.set_write_hook(move |idx, value| hook_writes.lock().unwrap().push((idx, value)));
+        thread::spawn(move || memory.increment()).join().unwrap();
+        assert_eq!(vec![(0, 1)], *writes.lock().unwrap());
+    }
 }
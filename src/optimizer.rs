@@ -0,0 +1,808 @@
+use std::vec;
+
+use crate::{
+    policy::{BoundaryPolicy, OverflowPolicy},
+    position::Position,
+    program::{Instruction, Node},
+};
+
+/// Operation of the optimized intermediate representation
+/// Consecutive instructions of the same kind are folded into a single operation
+/// and cells are addressed by their offset from the data pointer, so that it is moved
+/// only once at the end of each block of instructions without loops
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum Op {
+    /// Adds a value to the cell at an offset (negative values subtract),
+    /// the position is of the first instruction folded into it
+    Add {
+        offset: isize,
+        delta: i32,
+        position: Position,
+    },
+    /// Moves the data pointer by a number of cells (negative values move left),
+    /// the position is of the first instruction folded into it
+    Move {
+        offset: isize,
+        position: Position,
+    },
+    /// Sets the current cell to zero, recognized from `[-]` and `[+]`
+    SetZero,
+    /// Adds the current cell multiplied by a factor to the cell at an offset, recognized from
+    /// balanced loops like `[->+>++<<]` and always followed by `SetZero`
+    MulAdd {
+        offset: isize,
+        factor: i32,
+    },
+    /// Moves the data pointer by a step until it reaches a zero cell, recognized from `[>]` and `[<]`,
+    /// the position is of the move inside the loop
+    ScanZero {
+        step: isize,
+        position: Position,
+    },
+    /// Outputs the cell at an offset
+    Output {
+        offset: isize,
+    },
+    /// Inputs into the cell at an offset
+    Input {
+        offset: isize,
+    },
+    /// Shows the cells around the current one, nothing is folded across it
+    Debug {
+        position: Position,
+    },
+    Loop(Vec<Op>),
+}
+
+/// Optimizations that keep the optimized program behaving exactly like the parsed one
+/// under the policies of the interpreter running it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Optimizations {
+    /// Additions can be folded together, otherwise each `+` and `-` keeps its own position
+    fold_additions: bool,
+    /// Cells wrap around, so additions of opposite signs can be folded together and loops
+    /// relying on wrapping around (`[+]` and multiply loops) can be recognized
+    wrapping_additions: bool,
+    /// Moves to the right can't fail, so they can be folded together
+    fold_right_moves: bool,
+    /// Moves in neither direction can fail, so all of them can be folded together
+    /// and cells can be addressed by their offset from the data pointer
+    fold_all_moves: bool,
+    /// Number of cells the data pointer wraps around, `usize::MAX` if it doesn't wrap around,
+    /// so that the cells at offsets that differ by a multiple of it are the same
+    tape_size: usize,
+}
+
+impl Optimizations {
+    /// The optimizations for the policies and a tape of the given size, which only matters
+    /// if the data pointer wraps around it
+    pub(crate) fn for_policies(
+        overflow_policy: OverflowPolicy,
+        boundary_policy: BoundaryPolicy,
+        memory_size: usize,
+    ) -> Optimizations {
+        Optimizations {
+            fold_additions: overflow_policy != OverflowPolicy::Trap,
+            wrapping_additions: overflow_policy == OverflowPolicy::Wrap,
+            fold_right_moves: boundary_policy != BoundaryPolicy::Error,
+            fold_all_moves: matches!(
+                boundary_policy,
+                BoundaryPolicy::Wrap | BoundaryPolicy::GrowBoth
+            ),
+            tape_size: match boundary_policy {
+                BoundaryPolicy::Wrap => memory_size,
+                _ => usize::MAX,
+            },
+        }
+    }
+
+    /// The same optimizations for a tape too big for the data pointer to wrap around onto a cell
+    /// at any offset, they optimize a program like these ones if it has no multiply loop
+    /// adding to a cell as far from the data pointer as the size of the tape
+    pub(crate) fn on_any_tape(self) -> Optimizations {
+        Optimizations {
+            tape_size: usize::MAX,
+            ..self
+        }
+    }
+
+    pub(crate) fn tape_size(&self) -> usize {
+        self.tape_size
+    }
+
+    fn can_fold_additions(&self, delta: i32, value: i32) -> bool {
+        self.fold_additions && (self.wrapping_additions || delta.signum() == value.signum())
+    }
+
+    fn can_fold_moves(&self, offset: isize, value: isize) -> bool {
+        self.fold_all_moves || (self.fold_right_moves && offset > 0 && value > 0)
+    }
+}
+
+impl Default for Optimizations {
+    fn default() -> Optimizations {
+        Optimizations::for_policies(
+            OverflowPolicy::default(),
+            BoundaryPolicy::default(),
+            usize::MAX,
+        )
+    }
+}
+
+/// Translates the parsed instructions into the optimized representation
+pub(crate) fn optimize(nodes: &[Node], optimizations: Optimizations) -> Vec<Op> {
+    let ops = fold(nodes, optimizations);
+    if optimizations.fold_all_moves {
+        address_by_offset(ops, optimizations)
+    } else {
+        ops
+    }
+}
+
+/// Folds consecutive instructions and recognizes loop idioms, all cells are addressed
+/// relative to the data pointer as it moves
+fn fold(nodes: &[Node], optimizations: Optimizations) -> Vec<Op> {
+    let mut blocks = vec![(nodes.iter(), Vec::new())];
+
+    while let Some((nodes, ops)) = blocks.last_mut() {
+        let Some(node) = nodes.next() else {
+            let (_, loop_ops) = blocks.pop().expect("the block is on the stack");
+            match blocks.last_mut() {
+                Some((_, ops)) => push_loop(ops, loop_ops, optimizations),
+                None => return loop_ops,
+            }
+            continue;
+        };
+        match &node.instruction {
+            Instruction::NextCell => push_move(ops, 1, node.position, optimizations),
+            Instruction::PreviousCell => push_move(ops, -1, node.position, optimizations),
+            Instruction::IncrementData => push_add(ops, 1, node.position, optimizations),
+            Instruction::DecrementData => push_add(ops, -1, node.position, optimizations),
+            Instruction::OutputData => ops.push(Op::Output { offset: 0 }),
+            Instruction::InputData => ops.push(Op::Input { offset: 0 }),
+            Instruction::Debug => ops.push(Op::Debug {
+                position: node.position,
+            }),
+            Instruction::Loop(loop_nodes) => blocks.push((loop_nodes.iter(), Vec::new())),
+        }
+    }
+
+    unreachable!("the program's block returns when it ends")
+}
+
+fn push_loop(ops: &mut Vec<Op>, loop_ops: Vec<Op>, optimizations: Optimizations) {
+    if let [Op::Move { offset, position }] = loop_ops[..] {
+        ops.push(Op::ScanZero {
+            step: offset,
+            position,
+        });
+    } else if let [Op::Add { delta: -1, .. }] = loop_ops[..] {
+        ops.push(Op::SetZero);
+    } else if let Some(mul_adds) = multiply_loop(&loop_ops, optimizations) {
+        ops.extend(mul_adds);
+        ops.push(Op::SetZero);
+    } else {
+        ops.push(Op::Loop(loop_ops));
+    }
+}
+
+/// Recognizes a loop that only adds and moves, returns to the cell it started from
+/// and changes that cell by exactly one on each pass, so it runs as many times as
+/// the cell's value (or its negation) and each other cell it touches gets a multiple of it
+/// Only recognized if cells wrap around, the moves inside it can't fail and none of them
+/// reaches around the tape back onto the cell it started from
+fn multiply_loop(loop_ops: &[Op], optimizations: Optimizations) -> Option<Vec<Op>> {
+    if !optimizations.wrapping_additions || !optimizations.fold_right_moves {
+        return None;
+    }
+    let mut offset = 0_isize;
+    let mut deltas: Vec<(isize, i32)> = Vec::new();
+
+    for op in loop_ops {
+        match op {
+            Op::Move { offset: step, .. } => {
+                offset = offset.wrapping_add(*step);
+                if offset < 0 && !optimizations.fold_all_moves {
+                    return None;
+                }
+            }
+            Op::Add { delta: value, .. } => {
+                match deltas.iter_mut().find(|(target, _)| *target == offset) {
+                    Some((_, delta)) => *delta = delta.wrapping_add(*value),
+                    None => deltas.push((offset, *value)),
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    if offset != 0
+        || deltas
+            .iter()
+            .any(|(target, _)| *target != 0 && target.unsigned_abs() >= optimizations.tape_size)
+    {
+        return None;
+    }
+    let sign = match deltas.iter().find(|(target, _)| *target == 0) {
+        Some((_, -1)) => 1,
+        Some((_, 1)) => -1,
+        _ => return None,
+    };
+
+    Some(
+        deltas
+            .into_iter()
+            .filter(|&(target, delta)| target != 0 && delta != 0)
+            .map(|(offset, delta)| Op::MulAdd {
+                offset,
+                factor: delta.wrapping_mul(sign),
+            })
+            .collect(),
+    )
+}
+
+fn push_add(ops: &mut Vec<Op>, value: i32, position: Position, optimizations: Optimizations) {
+    match ops.last_mut() {
+        Some(Op::Add { delta, .. }) if optimizations.can_fold_additions(*delta, value) => {
+            *delta = delta.wrapping_add(value);
+            if *delta == 0 {
+                ops.pop();
+            }
+        }
+        _ => ops.push(Op::Add {
+            offset: 0,
+            delta: value,
+            position,
+        }),
+    }
+}
+
+fn push_move(ops: &mut Vec<Op>, value: isize, position: Position, optimizations: Optimizations) {
+    match ops.last_mut() {
+        Some(Op::Move { offset, .. }) if optimizations.can_fold_moves(*offset, value) => {
+            *offset = offset.wrapping_add(value);
+            if *offset == 0 {
+                ops.pop();
+            }
+        }
+        _ => ops.push(Op::Move {
+            offset: value,
+            position,
+        }),
+    }
+}
+
+/// Replaces the pointer moves inside each block of instructions without loops with offsets
+/// of the cells they address, followed by a single move of the pointer by the net offset
+fn address_by_offset(ops: Vec<Op>, optimizations: Optimizations) -> Vec<Op> {
+    let mut blocks = vec![Block::new(ops)];
+
+    while let Some(block) = blocks.last_mut() {
+        let Some(op) = block.ops.next() else {
+            let loop_ops = blocks.pop().expect("the block is on the stack").finish();
+            match blocks.last_mut() {
+                Some(block) => {
+                    block.addressed.push(Op::Loop(loop_ops));
+                    block.block_start = block.addressed.len();
+                }
+                None => return loop_ops,
+            }
+            continue;
+        };
+        let pointer = block.pointer;
+        match op {
+            Op::Move { offset, position } => {
+                if pointer == 0 {
+                    block.pointer_position = position;
+                }
+                block.pointer = pointer.wrapping_add(offset);
+            }
+            Op::Add {
+                offset,
+                delta,
+                position,
+            } => push_add_at(
+                &mut block.addressed,
+                block.block_start,
+                Op::Add {
+                    offset: pointer.wrapping_add(offset),
+                    delta,
+                    position,
+                },
+                optimizations,
+            ),
+            Op::Output { offset } => block.addressed.push(Op::Output {
+                offset: pointer.wrapping_add(offset),
+            }),
+            Op::Input { offset } => block.addressed.push(Op::Input {
+                offset: pointer.wrapping_add(offset),
+            }),
+            op => {
+                block.flush_pointer();
+                match op {
+                    Op::Loop(loop_ops) => blocks.push(Block::new(loop_ops)),
+                    op => {
+                        block.addressed.push(op);
+                        block.block_start = block.addressed.len();
+                    }
+                }
+            }
+        }
+    }
+
+    unreachable!("the program's block returns when it ends")
+}
+
+/// The program or a loop while its cells are being addressed by offset
+struct Block {
+    /// The remaining operations
+    ops: vec::IntoIter<Op>,
+    /// The operations addressed so far
+    addressed: Vec<Op>,
+    /// Start of the current block of instructions without loops in the addressed operations
+    block_start: usize,
+    /// Offset of the data pointer from where it was at the start of the current block
+    pointer: isize,
+    /// Position of the first move of the data pointer in the current block
+    pointer_position: Position,
+}
+
+impl Block {
+    fn new(ops: Vec<Op>) -> Block {
+        Block {
+            addressed: Vec::with_capacity(ops.len()),
+            ops: ops.into_iter(),
+            block_start: 0,
+            pointer: 0,
+            pointer_position: Position::default(),
+        }
+    }
+
+    /// Moves the data pointer by the net offset of the current block
+    fn flush_pointer(&mut self) {
+        if self.pointer != 0 {
+            self.addressed.push(Op::Move {
+                offset: self.pointer,
+                position: self.pointer_position,
+            });
+            self.pointer = 0;
+        }
+    }
+
+    fn finish(mut self) -> Vec<Op> {
+        self.flush_pointer();
+        self.addressed
+    }
+}
+
+/// Merges the addition with an earlier one to the same cell in the block, unless a cell is read
+/// or written in between, which can be the same one at another offset if the data pointer wraps around
+fn push_add_at(ops: &mut Vec<Op>, block_start: usize, add: Op, optimizations: Optimizations) {
+    let Op::Add {
+        offset,
+        delta: value,
+        ..
+    } = add
+    else {
+        return ops.push(add);
+    };
+    for idx in (block_start..ops.len()).rev() {
+        match &mut ops[idx] {
+            Op::Add {
+                offset: target,
+                delta,
+                ..
+            } if *target == offset => {
+                if !optimizations.can_fold_additions(*delta, value) {
+                    break;
+                }
+                *delta = delta.wrapping_add(value);
+                if *delta == 0 {
+                    ops.remove(idx);
+                }
+                return;
+            }
+            Op::Output { .. } | Op::Input { .. } => break,
+            _ => {}
+        }
+    }
+    ops.push(add);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{position::Positioned, program::parse};
+
+    use super::*;
+
+    fn parse_source(source_code: &str) -> Vec<Node> {
+        parse(
+            &mut Positioned::new(source_code.char_indices()),
+            false,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn fold_source(source_code: &str) -> Vec<Op> {
+        without_positions(fold(&parse_source(source_code), Optimizations::default()))
+    }
+
+    fn optimize_source(source_code: &str) -> Vec<Op> {
+        without_positions(optimize(
+            &parse_source(source_code),
+            Optimizations::default(),
+        ))
+    }
+
+    fn optimize_source_with(source_code: &str, overflow_policy: OverflowPolicy) -> Vec<Op> {
+        let optimizations =
+            Optimizations::for_policies(overflow_policy, BoundaryPolicy::Wrap, usize::MAX);
+        optimize(&parse_source(source_code), optimizations)
+    }
+
+    fn optimize_source_within(source_code: &str, boundary_policy: BoundaryPolicy) -> Vec<Op> {
+        let optimizations =
+            Optimizations::for_policies(OverflowPolicy::Wrap, boundary_policy, usize::MAX);
+        optimize(&parse_source(source_code), optimizations)
+    }
+
+    fn without_positions(ops: Vec<Op>) -> Vec<Op> {
+        ops.into_iter()
+            .map(|op| match op {
+                Op::Add { offset, delta, .. } => add(offset, delta),
+                Op::Move { offset, .. } => mov(offset),
+                Op::ScanZero { step, .. } => scan(step),
+                Op::Loop(loop_ops) => Op::Loop(without_positions(loop_ops)),
+                op => op,
+            })
+            .collect()
+    }
+
+    fn add(offset: isize, delta: i32) -> Op {
+        add_at(offset, delta, 0)
+    }
+
+    fn add_at(offset: isize, delta: i32, column: usize) -> Op {
+        Op::Add {
+            offset,
+            delta,
+            position: position(column),
+        }
+    }
+
+    fn mov(offset: isize) -> Op {
+        mov_at(offset, 0)
+    }
+
+    fn mov_at(offset: isize, column: usize) -> Op {
+        Op::Move {
+            offset,
+            position: position(column),
+        }
+    }
+
+    fn scan(step: isize) -> Op {
+        Op::ScanZero {
+            step,
+            position: Position::default(),
+        }
+    }
+
+    fn position(column: usize) -> Position {
+        Position {
+            offset: column.saturating_sub(1),
+            line: 1,
+            column,
+        }
+    }
+
+    fn mul_add(offset: isize, factor: i32) -> Op {
+        Op::MulAdd { offset, factor }
+    }
+
+    const OUTPUT: Op = Op::Output { offset: 0 };
+    const INPUT: Op = Op::Input { offset: 0 };
+
+    #[test]
+    fn optimizer_folds_increments() {
+        assert_eq!(vec![add(0, 10)], fold_source("++++++++++"));
+    }
+
+    #[test]
+    fn optimizer_folds_decrements() {
+        assert_eq!(vec![add(0, -3)], fold_source("---"));
+    }
+
+    #[test]
+    fn optimizer_folds_increments_and_decrements_together() {
+        assert_eq!(vec![add(0, 1)], fold_source("++-+-"));
+    }
+
+    #[test]
+    fn optimizer_folds_moves_in_both_directions() {
+        assert_eq!(vec![mov(2), add(0, 1), mov(-3)], fold_source(">>>< + <<<"));
+    }
+
+    #[test]
+    fn optimizer_drops_operations_that_cancel_out() {
+        assert_eq!(vec![OUTPUT], fold_source("+-><."));
+    }
+
+    #[test]
+    fn optimizer_folds_across_cancelled_operations() {
+        assert_eq!(vec![add(0, 2)], fold_source("+><+"));
+    }
+
+    #[test]
+    fn optimizer_does_not_fold_input_and_output() {
+        assert_eq!(vec![INPUT, INPUT, OUTPUT, OUTPUT], fold_source(",,.."));
+    }
+
+    #[test]
+    fn optimizer_folds_inside_loops() {
+        assert_eq!(
+            vec![
+                add(0, 2),
+                Op::Loop(vec![mov(1), add(0, 3), OUTPUT, mov(-1), add(0, -1)])
+            ],
+            fold_source("++[>+++.<-]")
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_fold_across_loop_boundaries() {
+        assert_eq!(
+            vec![add(0, 1), Op::Loop(vec![add(0, 2), OUTPUT]), add(0, 1)],
+            fold_source("+[++.]+")
+        );
+    }
+
+    #[test]
+    fn optimizer_recognizes_clear_loops() {
+        assert_eq!(vec![Op::SetZero], fold_source("[-]"));
+        assert_eq!(vec![Op::SetZero], fold_source("[+]"));
+    }
+
+    #[test]
+    fn optimizer_recognizes_scan_loops() {
+        assert_eq!(vec![scan(1)], fold_source("[>]"));
+        assert_eq!(vec![scan(-2)], fold_source("[<<]"));
+    }
+
+    #[test]
+    fn optimizer_recognizes_multiply_loops() {
+        assert_eq!(
+            vec![mul_add(1, 1), mul_add(2, 2), Op::SetZero],
+            fold_source("[->+>++<<]")
+        );
+    }
+
+    #[test]
+    fn optimizer_recognizes_multiply_loops_with_decrement_at_the_end() {
+        assert_eq!(vec![mul_add(-1, -3), Op::SetZero], fold_source("[<--->-]"));
+    }
+
+    #[test]
+    fn optimizer_negates_factors_of_multiply_loops_that_increment() {
+        assert_eq!(vec![mul_add(1, -2), Op::SetZero], fold_source("[+>++<]"));
+    }
+
+    #[test]
+    fn optimizer_does_not_recognize_unbalanced_loops() {
+        assert_eq!(
+            vec![Op::Loop(vec![add(0, -1), mov(1), add(0, 1)])],
+            fold_source("[->+]")
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_recognize_loops_changing_their_cell_by_more_than_one() {
+        assert_eq!(
+            vec![Op::Loop(vec![add(0, -2), mov(1), add(0, 1), mov(-1)])],
+            fold_source("[-->+<]")
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_recognize_loops_with_input_or_output() {
+        assert_eq!(
+            vec![Op::Loop(vec![add(0, -1), mov(1), OUTPUT, mov(-1)])],
+            fold_source("[->.<]")
+        );
+    }
+
+    #[test]
+    fn optimizer_addresses_cells_by_offset_with_a_single_move() {
+        assert_eq!(
+            vec![add(1, 1), add(2, 2), add(0, -1)],
+            optimize_source(">+>++<<-")
+        );
+    }
+
+    #[test]
+    fn optimizer_moves_pointer_by_net_offset_at_the_end_of_block() {
+        assert_eq!(
+            vec![add(1, 1), Op::Output { offset: 2 }, mov(3)],
+            optimize_source(">+>.>")
+        );
+    }
+
+    #[test]
+    fn optimizer_merges_additions_to_the_same_offset() {
+        assert_eq!(vec![add(1, 2), add(0, 1)], optimize_source(">+<+>+<"));
+    }
+
+    #[test]
+    fn optimizer_does_not_merge_additions_across_input_or_output_of_the_cell() {
+        assert_eq!(
+            vec![
+                add(1, 1),
+                Op::Output { offset: 1 },
+                add(1, 1),
+                Op::Input { offset: 0 },
+                add(0, 1)
+            ],
+            optimize_source(">+.+<,+")
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_merge_additions_across_input_or_output_of_any_cell() {
+        assert_eq!(
+            vec![
+                add(0, 1),
+                add(-1, 1),
+                Op::Output { offset: -1 },
+                add(0, 1),
+                OUTPUT
+            ],
+            optimize_source("+<+.>+.")
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_recognize_multiply_loops_reaching_around_the_tape() {
+        let optimizations =
+            Optimizations::for_policies(OverflowPolicy::Wrap, BoundaryPolicy::Wrap, 2);
+        assert_eq!(
+            vec![mul_add(1, 1), Op::SetZero],
+            without_positions(optimize(&parse_source("[->+<]"), optimizations))
+        );
+        assert!(matches!(
+            optimize(&parse_source("[->>+<<]"), optimizations)[..],
+            [Op::Loop(_)]
+        ));
+        assert!(matches!(
+            optimize(&parse_source("[-<<+>>]"), optimizations)[..],
+            [Op::Loop(_)]
+        ));
+    }
+
+    #[test]
+    fn optimizer_moves_pointer_before_loops() {
+        assert_eq!(
+            vec![
+                add(1, 1),
+                mov(1),
+                Op::Loop(vec![add(1, 1), Op::Output { offset: 0 }, add(0, -1)]),
+                Op::Output { offset: -1 },
+                mov(-1)
+            ],
+            optimize_source(">+[>+<.-]<.")
+        );
+    }
+
+    #[test]
+    fn optimizer_moves_pointer_before_loop_idioms() {
+        assert_eq!(
+            vec![mov(2), Op::SetZero, mov(-1), scan(-1)],
+            optimize_source(">>[-]<[<]")
+        );
+    }
+
+    #[test]
+    fn optimizer_keeps_position_of_the_first_folded_instruction() {
+        assert_eq!(
+            vec![add_at(0, 1, 1), add_at(1, 3, 3)],
+            optimize_source_with("+>+++<", OverflowPolicy::Wrap)
+        );
+    }
+
+    #[test]
+    fn optimizer_folds_only_additions_of_the_same_sign_if_saturating() {
+        assert_eq!(
+            vec![add_at(0, 2, 1), add_at(0, -1, 3), add_at(0, 1, 4)],
+            optimize_source_with("++-+", OverflowPolicy::Saturate)
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_fold_additions_if_trapping() {
+        assert_eq!(
+            vec![add_at(0, 1, 1), add_at(1, 1, 3), add_at(0, 1, 5)],
+            optimize_source_with("+>+<+", OverflowPolicy::Trap)
+        );
+    }
+
+    #[test]
+    fn optimizer_recognizes_only_decrementing_clear_loops_if_not_wrapping() {
+        assert_eq!(
+            vec![Op::SetZero, Op::Loop(vec![add_at(0, 1, 5)])],
+            optimize_source_with("[-][+]", OverflowPolicy::Saturate)
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_recognize_multiply_loops_if_not_wrapping() {
+        assert_eq!(
+            vec![Op::Loop(vec![add_at(0, -1, 2), add_at(1, 2, 4)])],
+            optimize_source_with("[->++<]", OverflowPolicy::Saturate)
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_fold_moves_if_moving_off_the_tape_is_an_error() {
+        assert_eq!(
+            vec![mov_at(1, 1), mov_at(1, 2), add_at(0, 1, 3), mov_at(-1, 4)],
+            optimize_source_within(">>+<", BoundaryPolicy::Error)
+        );
+    }
+
+    #[test]
+    fn optimizer_folds_only_moves_to_the_right_if_growing_to_the_right() {
+        assert_eq!(
+            vec![mov_at(2, 1), add_at(0, 1, 3), mov_at(-1, 4), mov_at(-1, 5)],
+            optimize_source_within(">>+<<", BoundaryPolicy::Grow)
+        );
+    }
+
+    #[test]
+    fn optimizer_addresses_cells_by_offset_if_growing_in_both_directions() {
+        assert_eq!(
+            vec![add_at(2, 1, 3), mov_at(-1, 1)],
+            optimize_source_within(">>+<<<", BoundaryPolicy::GrowBoth)
+        );
+    }
+
+    #[test]
+    fn optimizer_recognizes_scan_loops_with_position_of_the_move() {
+        assert_eq!(
+            vec![Op::ScanZero {
+                step: -1,
+                position: position(2)
+            }],
+            optimize_source_within("[<]", BoundaryPolicy::Error)
+        );
+    }
+
+    #[test]
+    fn optimizer_does_not_recognize_multiply_loops_if_moving_off_the_tape_is_an_error() {
+        assert_eq!(
+            vec![Op::Loop(vec![
+                add_at(0, -1, 2),
+                mov_at(1, 3),
+                add_at(0, 1, 4),
+                mov_at(-1, 5)
+            ])],
+            optimize_source_within("[->+<]", BoundaryPolicy::Error)
+        );
+    }
+
+    #[test]
+    fn optimizer_recognizes_multiply_loops_going_right_if_growing_to_the_right() {
+        assert_eq!(
+            vec![mul_add(1, 1), Op::SetZero],
+            without_positions(optimize_source_within("[->+<]", BoundaryPolicy::Grow))
+        );
+        assert_eq!(
+            vec![Op::Loop(vec![
+                add_at(0, -1, 2),
+                mov_at(-1, 3),
+                add_at(0, 1, 4),
+                mov_at(1, 5)
+            ])],
+            optimize_source_within("[-<+>]", BoundaryPolicy::Grow)
+        );
+    }
+}
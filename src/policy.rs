@@ -0,0 +1,51 @@
+use clap::ValueEnum;
+
+/// What the `,` instruction does with the current cell when there is no more input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum EofPolicy {
+    /// The value of the current cell is not changed
+    #[default]
+    Unchanged,
+    /// The current cell is set to zero
+    Zero,
+    /// The current cell is set to its maximum value, i.e. -1 for signed interpretations
+    MaxValue,
+    /// Execution stops with an error
+    Error,
+}
+
+/// How the `.` instruction writes the value of the current cell to the output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputEncoding {
+    /// The lowest byte of the cell is written
+    #[default]
+    LowByte,
+    /// The cell is treated as a Unicode code point and written encoded as UTF-8
+    Utf8,
+}
+
+/// What happens when a cell is incremented above its maximum value or decremented below zero
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OverflowPolicy {
+    /// The value wraps around to the other end of the range
+    #[default]
+    Wrap,
+    /// The value stays at the maximum value or at zero
+    Saturate,
+    /// Execution stops with an error reporting the position of the instruction
+    Trap,
+}
+
+/// What happens when the data pointer is moved off either end of the tape
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BoundaryPolicy {
+    /// The pointer wraps around to the other end of the tape
+    #[default]
+    Wrap,
+    /// Execution stops with an error reporting the position of the instruction
+    Error,
+    /// The tape grows to the right as needed, moving off its left end is an error
+    Grow,
+    /// The tape grows in both directions as needed
+    GrowBoth,
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+
+/// Position of an instruction in the source code of a program
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// Offset from the beginning of the source code, in bytes
+    pub offset: usize,
+    /// Line number, starting from 1
+    pub line: usize,
+    /// Column number in the line, starting from 1
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Adds positions to the characters of the source code, given with their byte offsets
+pub(crate) struct Positioned<I> {
+    chars: I,
+    line: usize,
+    column: usize,
+}
+
+impl<I> Positioned<I>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    pub(crate) fn new(chars: I) -> Positioned<I> {
+        Positioned {
+            chars,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl<I> Iterator for Positioned<I>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    type Item = (Position, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (offset, char) = self.chars.next()?;
+        let position = Position {
+            offset,
+            line: self.line,
+            column: self.column,
+        };
+        if char == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some((position, char))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_is_displayed_as_line_and_column() {
+        let position = Position {
+            offset: 12,
+            line: 2,
+            column: 5,
+        };
+        assert_eq!("line 2, column 5", position.to_string());
+    }
+
+    #[test]
+    fn positioned_characters_track_lines_and_columns() {
+        let positions: Vec<Position> = Positioned::new("aé\nb".char_indices())
+            .map(|(position, _)| position)
+            .collect();
+        assert_eq!(
+            vec![
+                Position {
+                    offset: 0,
+                    line: 1,
+                    column: 1
+                },
+                Position {
+                    offset: 1,
+                    line: 1,
+                    column: 2
+                },
+                Position {
+                    offset: 3,
+                    line: 1,
+                    column: 3
+                },
+                Position {
+                    offset: 4,
+                    line: 2,
+                    column: 1
+                },
+            ],
+            positions
+        );
+    }
+}
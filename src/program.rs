@@ -0,0 +1,694 @@
+use std::{borrow::Cow, fmt, mem, slice};
+
+#[cfg(feature = "jit")]
+use std::sync::Arc;
+
+#[cfg(feature = "jit")]
+use crate::jit::{NativeCode, NativeCodeCache};
+
+use crate::{
+    bytecode::{compile, Code},
+    codegen::{self, Target},
+    error::BfError,
+    optimizer::{optimize, Op, Optimizations},
+    policy::{BoundaryPolicy, OverflowPolicy},
+    position::{Position, Positioned},
+};
+
+/// Brainfuck program, parsed and optimized once so that it can be executed many times
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use vtashkov_bf::{Interpreter, Program};
+///
+/// let program = Program::parse(",+.");
+/// for value in [1, 2, 3] {
+///     let mut input = Cursor::new(vec![value]);
+///     let mut output = vec![];
+///     let mut interpreter = Interpreter::new(&mut input, &mut output, 30000);
+///     interpreter.run(&program).unwrap();
+///     assert_eq!(vec![value + 1], output);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Program {
+    nodes: Vec<Node>,
+    code: Vec<Code>,
+    /// The furthest offset from the data pointer a multiply loop in the code adds to,
+    /// the code is reused for any tape bigger than it
+    reach: usize,
+    #[cfg(feature = "jit")]
+    native_code: NativeCodeCache,
+}
+
+impl Program {
+    /// Parses a program from its source code, all characters other than the eight commands are ignored
+    /// Unbalanced `[` are closed at the end and everything after an unbalanced `]` is ignored
+    pub fn parse(source_code: &str) -> Program {
+        Program::from_nodes(parse_lenient(&mut Positioned::new(
+            source_code.char_indices(),
+        )))
+    }
+
+    /// Parses a program from its source code, like [`Program::parse`], but fails on the first unbalanced `]`
+    /// or, if there is none, on the innermost unbalanced `[`
+    pub fn parse_strict(source_code: &str) -> Result<Program, BfError> {
+        let nodes = parse(&mut Positioned::new(source_code.char_indices()), true, None)?;
+        Ok(Program::from_nodes(nodes))
+    }
+
+    /// Parses a program from the raw bytes of its source code, e.g. as read from a file
+    /// All bytes other than the eight commands are ignored, so the source doesn't need to be valid UTF-8
+    pub fn from_bytes(source_code: &[u8]) -> Program {
+        Program::from_nodes(parse_lenient(&mut Positioned::new(chars_of(source_code))))
+    }
+
+    /// Parses a program from the raw bytes of its source code, like [`Program::from_bytes`],
+    /// but fails on an unbalanced bracket like [`Program::parse_strict`]
+    pub fn from_bytes_strict(source_code: &[u8]) -> Result<Program, BfError> {
+        let nodes = parse(&mut Positioned::new(chars_of(source_code)), true, None)?;
+        Ok(Program::from_nodes(nodes))
+    }
+
+    fn from_nodes(nodes: Vec<Node>) -> Program {
+        let code = compile(optimize(&nodes, Optimizations::default()));
+        let reach = code
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Code::MulAdd { offset, .. } => Some(offset.unsigned_abs()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        Program {
+            nodes,
+            code,
+            reach,
+            #[cfg(feature = "jit")]
+            native_code: NativeCodeCache::default(),
+        }
+    }
+
+    /// The parsed instructions, each with its position in the source code
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vtashkov_bf::{Instruction, Program};
+    ///
+    /// let program = Program::parse("+\n[-]");
+    /// let nodes = program.nodes();
+    /// assert_eq!(Instruction::IncrementData, nodes[0].instruction);
+    /// assert_eq!((2, 1), (nodes[1].position.line, nodes[1].position.column));
+    /// if let Instruction::Loop(body) = &nodes[1].instruction {
+    ///     assert_eq!(3, body[0].position.offset);
+    /// }
+    /// ```
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Compiles the program to a standalone program in the target language with a tape of the given size,
+    /// Fails if the tape has no cells or more than the target supports
+    /// Fails if the tape has no cells
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vtashkov_bf::{Program, Target};
+    ///
+    /// let c = Program::parse("+.").compile(Target::C, 30000).unwrap();
+    /// assert!(String::from_utf8(c).unwrap().contains("putchar(tape[p]);"));
+    /// ```
+    pub fn compile(&self, target: Target, memory_size: usize) -> Result<Vec<u8>, BfError> {
+        let code = self.code(default_optimizations(memory_size));
+        codegen::generate(&self.nodes, &code, target, memory_size)
+    }
+
+    /// The compiled bytecode, the one compiled when parsing is reused if the optimizations are the default
+    /// and the tape is too big for the data pointer to wrap around onto the cell a multiply loop starts from
+    pub(crate) fn code(&self, optimizations: Optimizations) -> Cow<'_, [Code]> {
+        if optimizations.on_any_tape() == Optimizations::default()
+            && self.reach < optimizations.tape_size()
+        {
+            Cow::Borrowed(&self.code)
+        } else {
+            Cow::Owned(compile(self.ops(optimizations)))
+        }
+    }
+
+    /// The bytecode compiled with the default optimizations to native code for a tape of the given size,
+    /// which is reused by the runs on a tape of the same size as the last one
+    #[cfg(feature = "jit")]
+    pub(crate) fn native_code(&self, memory_size: usize) -> Option<Arc<NativeCode>> {
+        let code = self.code(default_optimizations(memory_size));
+        self.native_code.get_or_compile(&code, memory_size)
+    }
+
+    /// The optimized operations, before they are compiled to bytecode
+    pub(crate) fn ops(&self, optimizations: Optimizations) -> Vec<Op> {
+        optimize(&self.nodes, optimizations)
+    }
+}
+
+/// Parser of programs with options other than the ones of [`Program::parse`],
+/// e.g. recognizing a debug instruction
+///
+/// # Examples
+///
+/// ```
+/// use vtashkov_bf::{Instruction, Parser};
+///
+/// let program = Parser::new().with_debug_char('#').parse("+#").unwrap();
+/// assert_eq!(Instruction::Debug, program.nodes()[1].instruction);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Parser {
+    strict: bool,
+    debug_char: Option<char>,
+}
+
+impl Parser {
+    /// Creates new parser, which parses programs like [`Program::parse`]
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /// Sets whether programs with unbalanced brackets are rejected, like by [`Program::parse_strict`],
+    /// by default unbalanced `[` are closed at the end and everything after an unbalanced `]` is ignored
+    pub fn with_strict(mut self, strict: bool) -> Parser {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets the character parsed as [`Instruction::Debug`], e.g. `#`, by default there is none and all
+    /// characters other than the eight commands are ignored
+    /// The characters of the eight commands are always parsed as the commands
+    pub fn with_debug_char(mut self, debug_char: char) -> Parser {
+        self.debug_char = Some(debug_char);
+        self
+    }
+
+    /// Parses a program from its source code
+    /// Fails on an unbalanced bracket, like [`Program::parse_strict`], if the parser is strict
+    pub fn parse(&self, source_code: &str) -> Result<Program, BfError> {
+        let mut chars = Positioned::new(source_code.char_indices());
+        let nodes = parse(&mut chars, self.strict, self.debug_char)?;
+        Ok(Program::from_nodes(nodes))
+    }
+
+    /// Parses a program from the raw bytes of its source code, like [`Program::from_bytes`]
+    /// Fails on an unbalanced bracket, like [`Program::parse_strict`], if the parser is strict
+    pub fn parse_bytes(&self, source_code: &[u8]) -> Result<Program, BfError> {
+        let mut chars = Positioned::new(chars_of(source_code));
+        let nodes = parse(&mut chars, self.strict, self.debug_char)?;
+        Ok(Program::from_nodes(nodes))
+    }
+}
+
+/// Parsed instruction together with its position in the source code
+///
+/// Loops can be nested to any depth, so the nodes are never walked recursively: parsing, optimizing,
+/// compiling, cloning, comparing, formatting and dropping them keep the loops being walked on a stack of
+/// their own, so that deeply nested programs don't overflow the call stack
+#[non_exhaustive]
+pub struct Node {
+    /// The instruction
+    pub instruction: Instruction,
+    /// Position of the instruction's character, for loops the one of their `[`
+    pub position: Position,
+}
+
+impl Drop for Node {
+    /// Drops the nodes of the loops one by one instead of recursively, so that deeply nested loops
+    /// don't overflow the stack
+    fn drop(&mut self) {
+        let Instruction::Loop(body) = &mut self.instruction else {
+            return;
+        };
+        let mut nodes = mem::take(body);
+        while let Some(mut node) = nodes.pop() {
+            if let Instruction::Loop(body) = &mut node.instruction {
+                nodes.append(body);
+            }
+        }
+    }
+}
+
+/// Instruction of the parsed program, loops hold the nodes of their body
+#[non_exhaustive]
+pub enum Instruction {
+    /// `>` - moves the data pointer to the next cell
+    NextCell,
+    /// `<` - moves the data pointer to the previous cell
+    PreviousCell,
+    /// `+` - increments the current cell
+    IncrementData,
+    /// `-` - decrements the current cell
+    DecrementData,
+    /// `.` - writes the current cell to the output
+    OutputData,
+    /// `,` - reads a byte from the input into the current cell
+    InputData,
+    /// `[` and `]` - executes the body while the current cell isn't zero
+    Loop(Vec<Node>),
+    /// The debug character, only if it is set with [`Parser::with_debug_char`] - shows the cells
+    /// around the current one and the position of the instruction, or pauses the debugger
+    Debug,
+}
+
+impl Clone for Node {
+    fn clone(&self) -> Node {
+        Node {
+            instruction: self.instruction.clone(),
+            position: self.position,
+        }
+    }
+}
+
+impl Clone for Instruction {
+    fn clone(&self) -> Instruction {
+        match self {
+            Instruction::Loop(body) => Instruction::Loop(clone_nodes(body)),
+            instruction => instruction.clone_unit(),
+        }
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        nodes_eq(slice::from_ref(self), slice::from_ref(other))
+    }
+}
+
+impl Eq for Node {}
+
+impl PartialEq for Instruction {
+    fn eq(&self, other: &Instruction) -> bool {
+        match (self, other) {
+            (Instruction::Loop(body), Instruction::Loop(other_body)) => nodes_eq(body, other_body),
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Instruction {}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Node {{ instruction: {:?}, position: {:?} }}",
+            self.instruction, self.position
+        )
+    }
+}
+
+impl fmt::Debug for Instruction {
+    /// Formats the instruction like a derived implementation without `{:#?}`, which isn't supported
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Instruction::NextCell => "NextCell",
+            Instruction::PreviousCell => "PreviousCell",
+            Instruction::IncrementData => "IncrementData",
+            Instruction::DecrementData => "DecrementData",
+            Instruction::OutputData => "OutputData",
+            Instruction::InputData => "InputData",
+            Instruction::Debug => "Debug",
+            Instruction::Loop(body) => {
+                f.write_str("Loop(")?;
+                fmt_nodes(body, f)?;
+                return f.write_str(")");
+            }
+        };
+        f.write_str(name)
+    }
+}
+
+impl Instruction {
+    /// Copies an instruction other than a loop
+    fn clone_unit(&self) -> Instruction {
+        match self {
+            Instruction::NextCell => Instruction::NextCell,
+            Instruction::PreviousCell => Instruction::PreviousCell,
+            Instruction::IncrementData => Instruction::IncrementData,
+            Instruction::DecrementData => Instruction::DecrementData,
+            Instruction::OutputData => Instruction::OutputData,
+            Instruction::InputData => Instruction::InputData,
+            Instruction::Debug => Instruction::Debug,
+            Instruction::Loop(_) => unreachable!("loops are cloned with their body"),
+        }
+    }
+}
+
+fn clone_nodes(nodes: &[Node]) -> Vec<Node> {
+    let mut blocks = vec![(nodes.iter(), Vec::with_capacity(nodes.len()), None)];
+
+    loop {
+        let (nodes, clones, _) = blocks.last_mut().expect("the program is on the stack");
+        match nodes.next() {
+            Some(Node {
+                instruction: Instruction::Loop(body),
+                position,
+            }) => blocks.push((body.iter(), Vec::with_capacity(body.len()), Some(*position))),
+            Some(node) => clones.push(Node {
+                instruction: node.instruction.clone_unit(),
+                position: node.position,
+            }),
+            None => {
+                let (_, clones, position) = blocks.pop().expect("the block is on the stack");
+                let Some(position) = position else {
+                    return clones;
+                };
+                let (_, outer_clones, _) = blocks.last_mut().expect("loops are inside the program");
+                outer_clones.push(Node {
+                    instruction: Instruction::Loop(clones),
+                    position,
+                });
+            }
+        }
+    }
+}
+
+fn nodes_eq(nodes: &[Node], other_nodes: &[Node]) -> bool {
+    // The bodies of the loops left to compare
+    let mut blocks = vec![(nodes, other_nodes)];
+
+    while let Some((nodes, other_nodes)) = blocks.pop() {
+        if nodes.len() != other_nodes.len() {
+            return false;
+        }
+        for (node, other) in nodes.iter().zip(other_nodes) {
+            if node.position != other.position {
+                return false;
+            }
+            match (&node.instruction, &other.instruction) {
+                (Instruction::Loop(body), Instruction::Loop(other_body)) => {
+                    blocks.push((body, other_body))
+                }
+                (instruction, other_instruction) => {
+                    if instruction != other_instruction {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+
+fn fmt_nodes(nodes: &[Node], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("[")?;
+    let mut blocks = vec![(nodes.iter(), None, true)];
+
+    while let Some((nodes, _, first)) = blocks.last_mut() {
+        let Some(node) = nodes.next() else {
+            let (_, position, _) = blocks.pop().expect("the block is on the stack");
+            f.write_str("]")?;
+            if let Some(position) = position {
+                write!(f, "), position: {position:?} }}")?;
+            }
+            continue;
+        };
+        if !mem::take(first) {
+            f.write_str(", ")?;
+        }
+        match &node.instruction {
+            Instruction::Loop(body) => {
+                f.write_str("Node { instruction: Loop([")?;
+                blocks.push((body.iter(), Some(node.position), true));
+            }
+            _ => write!(f, "{node:?}")?,
+        }
+    }
+    Ok(())
+}
+
+/// The optimizations for the default policies, which the compiled programs behave like, on a tape of the size
+fn default_optimizations(memory_size: usize) -> Optimizations {
+    Optimizations::for_policies(
+        OverflowPolicy::default(),
+        BoundaryPolicy::default(),
+        memory_size,
+    )
+}
+
+fn chars_of(source_code: &[u8]) -> impl Iterator<Item = (usize, char)> + '_ {
+    source_code.iter().map(|&byte| char::from(byte)).enumerate()
+}
+
+fn parse_lenient(chars: &mut impl Iterator<Item = (Position, char)>) -> Vec<Node> {
+    parse(chars, false, None).expect("lenient parsing accepts unbalanced brackets")
+}
+
+/// Parses the instructions, if `strict` is set it fails on the first unbalanced `]` or the innermost unbalanced `[`,
+/// otherwise unbalanced `[` are closed at the end and an unbalanced `]` ends the program
+/// The `debug_char`, if any, is parsed as a debug instruction
+pub(crate) fn parse(
+    chars: &mut impl Iterator<Item = (Position, char)>,
+    strict: bool,
+    debug_char: Option<char>,
+) -> Result<Vec<Node>, BfError> {
+    let mut nodes: Vec<Node> = Vec::new();
+    // The nodes before each of the open loops, together with the position of its `[`
+    let mut open_loops: Vec<(Vec<Node>, Position)> = Vec::new();
+
+    for (position, char) in chars {
+        let instruction = match char {
+            '>' => Instruction::NextCell,
+            '<' => Instruction::PreviousCell,
+            '+' => Instruction::IncrementData,
+            '-' => Instruction::DecrementData,
+            '.' => Instruction::OutputData,
+            ',' => Instruction::InputData,
+            '[' => {
+                open_loops.push((mem::take(&mut nodes), position));
+                continue;
+            }
+            ']' => match open_loops.pop() {
+                Some((outer_nodes, loop_start)) => {
+                    close_loop(&mut nodes, outer_nodes, loop_start);
+                    continue;
+                }
+                None if strict => return Err(BfError::UnmatchedLoopEnd(position)),
+                None => break,
+            },
+            char if Some(char) == debug_char => Instruction::Debug,
+            _ => continue,
+        };
+        nodes.push(Node {
+            instruction,
+            position,
+        });
+    }
+
+    while let Some((outer_nodes, loop_start)) = open_loops.pop() {
+        if strict {
+            return Err(BfError::UnmatchedLoopStart(loop_start));
+        }
+        close_loop(&mut nodes, outer_nodes, loop_start);
+    }
+
+    Ok(nodes)
+}
+
+/// Replaces the nodes of a loop's body with the ones before it, followed by the loop
+fn close_loop(nodes: &mut Vec<Node>, outer_nodes: Vec<Node>, loop_start: Position) {
+    let body = mem::replace(nodes, outer_nodes);
+    nodes.push(Node {
+        instruction: Instruction::Loop(body),
+        position: loop_start,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_source(source_code: &str) -> Vec<Node> {
+        parse_lenient(&mut Positioned::new(source_code.char_indices()))
+    }
+
+    fn position(offset: usize, line: usize, column: usize) -> Position {
+        Position {
+            offset,
+            line,
+            column,
+        }
+    }
+
+    fn node(instruction: Instruction, offset: usize) -> Node {
+        Node {
+            instruction,
+            position: Position {
+                offset,
+                line: 1,
+                column: offset + 1,
+            },
+        }
+    }
+
+    #[test]
+    fn parser_ignores_non_instructions() {
+        assert_eq!(Vec::<Node>::new(), parse_source("abc 123\n"));
+    }
+
+    #[test]
+    fn parser_recognizes_all_instructions() {
+        assert_eq!(
+            vec![
+                node(Instruction::NextCell, 0),
+                node(Instruction::PreviousCell, 1),
+                node(Instruction::IncrementData, 2),
+                node(Instruction::DecrementData, 3),
+                node(Instruction::OutputData, 4),
+                node(Instruction::InputData, 5),
+                node(
+                    Instruction::Loop(vec![node(Instruction::IncrementData, 7)]),
+                    6
+                ),
+            ],
+            parse_source("><+-.,[+]")
+        );
+    }
+
+    #[test]
+    fn parser_closes_unbalanced_loops_at_the_end() {
+        assert_eq!(
+            vec![node(
+                Instruction::Loop(vec![node(
+                    Instruction::Loop(vec![node(Instruction::OutputData, 2)]),
+                    1
+                )]),
+                0
+            )],
+            parse_source("[[.")
+        );
+    }
+
+    #[test]
+    fn parser_ignores_everything_after_unbalanced_loop_end() {
+        assert_eq!(vec![node(Instruction::OutputData, 0)], parse_source(".]."));
+    }
+
+    #[test]
+    fn parser_records_positions_of_instructions() {
+        let nodes = parse_source("+ a\n é.");
+        assert_eq!(
+            Position {
+                offset: 0,
+                line: 1,
+                column: 1
+            },
+            nodes[0].position
+        );
+        assert_eq!(
+            Position {
+                offset: 7,
+                line: 2,
+                column: 3
+            },
+            nodes[1].position
+        );
+    }
+
+    #[test]
+    fn parser_records_positions_of_instructions_inside_loops() {
+        let program = Program::parse("é[\n  [>]]");
+        let Instruction::Loop(outer) = &program.nodes()[0].instruction else {
+            panic!("expected a loop");
+        };
+        let Instruction::Loop(inner) = &outer[0].instruction else {
+            panic!("expected a loop");
+        };
+        assert_eq!(position(2, 1, 2), program.nodes()[0].position);
+        assert_eq!(position(6, 2, 3), outer[0].position);
+        assert_eq!(position(7, 2, 4), inner[0].position);
+    }
+
+    #[test]
+    fn program_can_be_parsed_from_bytes() {
+        let program = Program::from_bytes(b"+\xff[-]");
+        assert_eq!(Program::parse("+ [-]").code, program.code);
+    }
+
+    #[test]
+    fn strict_parser_accepts_balanced_loops() {
+        let program = Program::parse_strict("+[>[-]<-]").unwrap();
+        assert_eq!(Program::parse("+[>[-]<-]").nodes, program.nodes);
+    }
+
+    #[test]
+    fn strict_parser_reports_position_of_unbalanced_loop_start() {
+        let result = Program::parse_strict("+[\n [-]");
+        assert!(matches!(
+            result,
+            Err(BfError::UnmatchedLoopStart(start)) if start == position(1, 1, 2)
+        ));
+    }
+
+    #[test]
+    fn strict_parser_reports_position_of_unbalanced_loop_end() {
+        let result = Program::parse_strict("[-]\n.].");
+        assert!(matches!(
+            result,
+            Err(BfError::UnmatchedLoopEnd(end)) if end == position(5, 2, 2)
+        ));
+    }
+
+    #[test]
+    fn strict_parser_reports_innermost_unbalanced_loop_start() {
+        let result = Program::from_bytes_strict(b"[[]\xff[");
+        assert!(matches!(
+            result,
+            Err(BfError::UnmatchedLoopStart(start)) if start == position(4, 1, 5)
+        ));
+    }
+
+    #[test]
+    fn parser_ignores_the_debug_char_unless_it_is_set() {
+        assert_eq!(vec![node(Instruction::OutputData, 1)], parse_source("#.#"));
+        let program = Parser::new().with_debug_char('#').parse("#.!").unwrap();
+        assert_eq!(
+            vec![
+                node(Instruction::Debug, 0),
+                node(Instruction::OutputData, 1)
+            ],
+            program.nodes
+        );
+        let program = Parser::new().with_debug_char('!').parse("#.!").unwrap();
+        assert_eq!(node(Instruction::Debug, 2), program.nodes[1]);
+    }
+
+    #[test]
+    fn parser_keeps_the_commands_when_one_is_the_debug_char() {
+        let program = Parser::new().with_debug_char('+').parse("+").unwrap();
+        assert_eq!(vec![node(Instruction::IncrementData, 0)], program.nodes);
+    }
+
+    #[test]
+    fn parser_can_be_strict() {
+        let result = Parser::new().with_strict(true).parse_bytes(b"#[");
+        assert!(matches!(
+            result,
+            Err(BfError::UnmatchedLoopStart(start)) if start == position(1, 1, 2)
+        ));
+        assert!(Parser::new().parse_bytes(b"#[").is_ok());
+    }
+
+    #[test]
+    fn nodes_are_formatted_like_derived_debug() {
+        let program = Program::parse("[-]+");
+        assert_eq!(
+            "[Node { instruction: Loop([Node { instruction: DecrementData, position: \
+             Position { offset: 1, line: 1, column: 2 } }]), position: \
+             Position { offset: 0, line: 1, column: 1 } }, \
+             Node { instruction: IncrementData, position: \
+             Position { offset: 3, line: 1, column: 4 } }]",
+            format!("{:?}", program.nodes)
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    env, fs,
+    io::{Cursor, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use vtashkov_bf::{Interpreter, Program, Target};
+use wasmi::{Caller, Engine, Linker, Module, Store};
+
+const PROGRAMS: [(&str, &str, &[u8]); 4] = [
+    (
+        "hello_world",
+        include_str!("../examples/hello_world.bf"),
+        b"",
+    ),
+    (
+        "rot13",
+        include_str!("../examples/rot13.bf"),
+        b"Hello, World!",
+    ),
+    ("wrap", "<<<+++[>>>>+<<<<-]>>>>.<<<<<<<<[-]+.", b""),
+    ("eof", ",+.,.>,.", b"a"),
+];
+
+/// Output of the program when interpreted with the default policies and a tape of 16 cells
+fn interpret(source_code: &str, input: &[u8]) -> Vec<u8> {
+    let mut input = Cursor::new(input.to_vec());
+    let mut output = vec![];
+    let mut interpreter = Interpreter::new(&mut input, &mut output, 16);
+    interpreter.execute(source_code).unwrap();
+    drop(interpreter);
+    output
+}
+
+/// Runs a command with the input and returns its output, `None` if the command isn't available
+fn run(command: &mut Command, input: &[u8]) -> Option<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{command:?} failed");
+    Some(output.stdout)
+}
+
+/// Input and output of a WebAssembly module run by the host
+struct Host {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+/// Runs the `run` function of a WebAssembly module with the input and returns its output
+fn run_wasm(wasm: &[u8], input: &[u8]) -> Vec<u8> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, wasm).unwrap();
+    let host = Host {
+        input: Cursor::new(input.to_vec()),
+        output: vec![],
+    };
+    let mut store = Store::new(&engine, host);
+    let mut linker = Linker::<Host>::new(&engine);
+    linker
+        .func_wrap(
+            "env",
+            "output",
+            |mut caller: Caller<'_, Host>, byte: i32| {
+                caller.data_mut().output.push(byte as u8);
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap("env", "input", |mut caller: Caller<'_, Host>| -> i32 {
+            let mut byte = [0];
+            match caller.data_mut().input.read_exact(&mut byte) {
+                Ok(()) => i32::from(byte[0]),
+                Err(_) => -1,
+            }
+        })
+        .unwrap();
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    let run = instance.get_typed_func::<(), ()>(&store, "run").unwrap();
+    run.call(&mut store, ()).unwrap();
+    store.into_data().output
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("vtashkov-bf-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn compiled_c_behaves_like_the_interpreter() {
+    for (name, source_code, input) in PROGRAMS {
+        let source_file = temp_path(&format!("{name}.c"));
+        let executable = temp_path(name);
+        fs::write(
+            &source_file,
+            Program::parse(source_code).compile(Target::C, 16).unwrap(),
+        )
+        .unwrap();
+        let compiled = run(
+            Command::new("cc")
+                .arg("-O2")
+                .arg("-o")
+                .arg(&executable)
+                .arg(&source_file),
+            b"",
+        );
+        if compiled.is_none() {
+            eprintln!("skipping the C backend, no C compiler available");
+            return;
+        }
+        let output = run(&mut Command::new(&executable), input).unwrap();
+        fs::remove_file(&source_file).unwrap();
+        fs::remove_file(&executable).unwrap();
+        assert_eq!(interpret(source_code, input), output, "{name}");
+    }
+}
+
+#[test]
+fn compiled_rust_behaves_like_the_interpreter() {
+    for (name, source_code, input) in PROGRAMS {
+        let source_file = temp_path(&format!("{name}.rs"));
+        let executable = temp_path(&format!("{name}-rust"));
+        fs::write(
+            &source_file,
+            Program::parse(source_code)
+                .compile(Target::Rust, 16)
+                .unwrap(),
+        )
+        .unwrap();
+        let compiled = run(
+            Command::new("rustc")
+                .args(["--edition", "2021", "-O", "-D", "warnings", "-o"])
+                .arg(&executable)
+                .arg(&source_file),
+            b"",
+        );
+        if compiled.is_none() {
+            eprintln!("skipping the Rust backend, no Rust compiler available");
+            return;
+        }
+        let output = run(&mut Command::new(&executable), input).unwrap();
+        fs::remove_file(&source_file).unwrap();
+        fs::remove_file(&executable).unwrap();
+        assert_eq!(interpret(source_code, input), output, "{name}");
+    }
+}
+
+#[test]
+fn wasm_module_behaves_like_the_interpreter() {
+    for (name, source_code, input) in PROGRAMS {
+        let wasm = Program::parse(source_code)
+            .compile(Target::Wasm, 16)
+            .unwrap();
+        assert_eq!(
+            interpret(source_code, input),
+            run_wasm(&wasm, input),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn wat_module_behaves_like_the_interpreter() {
+    for (name, source_code, input) in PROGRAMS {
+        let wat = Program::parse(source_code)
+            .compile(Target::Wat, 16)
+            .unwrap();
+        let wasm = wat::parse_bytes(&wat).unwrap();
+        assert_eq!(
+            interpret(source_code, input),
+            run_wasm(&wasm, input),
+            "{name}"
+        );
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[test]
+fn x86_64_executable_behaves_like_the_interpreter() {
+    use std::os::unix::fs::PermissionsExt;
+
+    for (name, source_code, input) in PROGRAMS {
+        let executable = temp_path(&format!("{name}-elf"));
+        fs::write(
+            &executable,
+            Program::parse(source_code)
+                .compile(Target::X86_64Linux, 16)
+                .unwrap(),
+        )
+        .unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        let output = run(&mut Command::new(&executable), input).unwrap();
+        fs::remove_file(&executable).unwrap();
+        assert_eq!(interpret(source_code, input), output, "{name}");
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+#[test]
+fn x86_64_assembly_behaves_like_the_interpreter() {
+    for (name, source_code, input) in PROGRAMS {
+        let source_file = temp_path(&format!("{name}.s"));
+        let object_file = temp_path(&format!("{name}.o"));
+        let executable = temp_path(&format!("{name}-asm"));
+        fs::write(
+            &source_file,
+            Program::parse(source_code)
+                .compile(Target::X86_64LinuxAsm, 16)
+                .unwrap(),
+        )
+        .unwrap();
+        let assembled = run(
+            Command::new("as")
+                .arg("-o")
+                .arg(&object_file)
+                .arg(&source_file),
+            b"",
+        )
+        .and_then(|_| {
+            run(
+                Command::new("ld")
+                    .arg("-o")
+                    .arg(&executable)
+                    .arg(&object_file),
+                b"",
+            )
+        });
+        if assembled.is_none() {
+            eprintln!("skipping the x86-64 assembly, no assembler or linker available");
+            return;
+        }
+        let output = run(&mut Command::new(&executable), input).unwrap();
+        for file in [&source_file, &object_file, &executable] {
+            fs::remove_file(file).unwrap();
+        }
+        assert_eq!(interpret(source_code, input), output, "{name}");
+    }
+}
+
+/// Arguments LLVM tools need to read IR with opaque pointers, which are only the default since LLVM 15
+fn llvm_arguments(tool: &str) -> Option<Vec<&'static str>> {
+    let version = run(Command::new(tool).arg("--version"), b"")?;
+    let version = String::from_utf8(version).unwrap();
+    let major: u32 = version
+        .split("version ")
+        .nth(1)?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()?;
+    Some(if major < 15 {
+        vec!["-opaque-pointers"]
+    } else {
+        vec![]
+    })
+}
+
+#[test]
+fn llvm_ir_behaves_like_the_interpreter() {
+    let Some(arguments) = llvm_arguments("lli") else {
+        eprintln!("skipping the LLVM backend, no LLVM interpreter available");
+        return;
+    };
+    for (name, source_code, input) in PROGRAMS {
+        let source_file = temp_path(&format!("{name}.ll"));
+        fs::write(
+            &source_file,
+            Program::parse(source_code)
+                .compile(Target::Llvm, 16)
+                .unwrap(),
+        )
+        .unwrap();
+        let output = run(
+            Command::new("lli").args(&arguments).arg(&source_file),
+            input,
+        )
+        .unwrap();
+        fs::remove_file(&source_file).unwrap();
+        assert_eq!(interpret(source_code, input), output, "{name}");
+    }
+}
+
+#[test]
+fn llvm_ir_can_be_optimized_and_compiled() {
+    let Some(arguments) = llvm_arguments("llc") else {
+        eprintln!("skipping the LLVM backend, no LLVM compiler available");
+        return;
+    };
+    let source_file = temp_path("rot13.ll");
+    let object_file = temp_path("rot13-llvm.o");
+    fs::write(
+        &source_file,
+        Program::parse(include_str!("../examples/rot13.bf"))
+            .compile(Target::Llvm, 30000)
+            .unwrap(),
+    )
+    .unwrap();
+    run(
+        Command::new("llc")
+            .args(&arguments)
+            .args(["-O3", "-filetype=obj", "-o"])
+            .arg(&object_file)
+            .arg(&source_file),
+        b"",
+    )
+    .unwrap();
+    assert!(fs::metadata(&object_file).unwrap().len() > 0);
+    fs::remove_file(&source_file).unwrap();
+    fs::remove_file(&object_file).unwrap();
+}
+
+#[test]
+fn javascript_behaves_like_the_interpreter() {
+    for (name, source_code, input) in PROGRAMS {
+        let source_file = temp_path(&format!("{name}.js"));
+        fs::write(
+            &source_file,
+            Program::parse(source_code)
+                .compile(Target::JavaScript, 16)
+                .unwrap(),
+        )
+        .unwrap();
+        let Some(output) = run(Command::new("node").arg(&source_file), input) else {
+            eprintln!("skipping the JavaScript backend, no Node.js available");
+            fs::remove_file(&source_file).unwrap();
+            return;
+        };
+        fs::remove_file(&source_file).unwrap();
+        assert_eq!(interpret(source_code, input), output, "{name}");
+    }
+}
+
+#[test]
+fn python_behaves_like_the_interpreter() {
+    for (name, source_code, input) in PROGRAMS {
+        let source_file = temp_path(&format!("{name}.py"));
+        fs::write(
+            &source_file,
+            Program::parse(source_code)
+                .compile(Target::Python, 16)
+                .unwrap(),
+        )
+        .unwrap();
+        let Some(output) = run(Command::new("python3").arg(&source_file), input) else {
+            eprintln!("skipping the Python backend, no Python available");
+            fs::remove_file(&source_file).unwrap();
+            return;
+        };
+        fs::remove_file(&source_file).unwrap();
+        assert_eq!(interpret(source_code, input), output, "{name}");
+    }
+}
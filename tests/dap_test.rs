@@ -0,0 +1,299 @@
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    process::{self, Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+/// Program written to a file of its own in the temporary directory, deleted when dropped
+struct ProgramFile(PathBuf);
+
+impl ProgramFile {
+    fn new(name: &str, source_code: &str) -> ProgramFile {
+        let path = env::temp_dir().join(format!("vtashkov-bf-dap-{}-{name}.bf", process::id()));
+        fs::write(&path, source_code).unwrap();
+        ProgramFile(path)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for ProgramFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Requests resuming the program, after which a client waits for it to stop before sending anything but
+/// a pause
+const RESUMING: [&str; 5] = ["configurationDone", "continue", "next", "stepIn", "stepOut"];
+
+/// Reads a message sent by the `dap` subcommand, `None` when it has exited
+fn read_message(stdout: &mut impl BufRead) -> Option<Value> {
+    let mut line = String::new();
+    if stdout.read_line(&mut line).unwrap() == 0 {
+        return None;
+    }
+    let length: usize = line
+        .trim_end()
+        .strip_prefix("Content-Length: ")
+        .unwrap()
+        .parse()
+        .unwrap();
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!("\r\n", line);
+    let mut content = vec![0; length];
+    stdout.read_exact(&mut content).unwrap();
+    Some(serde_json::from_slice(&content).unwrap())
+}
+
+/// Sends the requests to the `dap` subcommand, numbering them from 1, and returns the messages it sent back
+/// After one resuming the program the next is sent once it stops, unless it is a pause
+fn debug(requests: &[Value]) -> Vec<Value> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_vtashkov-bf"))
+        .arg("dap")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut messages = vec![];
+    for (seq, request) in requests.iter().enumerate() {
+        let mut request = request.clone();
+        request["seq"] = json!(seq + 1);
+        request["type"] = json!("request");
+        let content = request.to_string();
+        write!(stdin, "Content-Length: {}\r\n\r\n{content}", content.len()).unwrap();
+        stdin.flush().unwrap();
+        let pausing = requests
+            .get(seq + 1)
+            .is_some_and(|next| next["command"] == "pause");
+        if RESUMING.contains(&request["command"].as_str().unwrap()) && !pausing {
+            let mut responded = false;
+            loop {
+                let message = read_message(&mut stdout).unwrap();
+                responded |= message["request_seq"] == request["seq"];
+                let stopped = message["event"] == "stopped" || message["event"] == "terminated";
+                messages.push(message);
+                if responded && stopped {
+                    break;
+                }
+            }
+        }
+    }
+    drop(stdin);
+
+    while let Some(message) = read_message(&mut stdout) {
+        messages.push(message);
+    }
+    assert!(child.wait().unwrap().success());
+    messages
+}
+
+/// The response to the request with the sequence number
+fn response(messages: &[Value], request_seq: u64) -> &Value {
+    messages
+        .iter()
+        .find(|message| message["type"] == "response" && message["request_seq"] == request_seq)
+        .unwrap()
+}
+
+/// The events of the given kind, in the order they were sent
+fn events<'m>(messages: &'m [Value], event: &str) -> Vec<&'m Value> {
+    messages
+        .iter()
+        .filter(|message| message["type"] == "event" && message["event"] == event)
+        .collect()
+}
+
+#[test]
+fn it_debugs_a_program_over_the_debug_adapter_protocol() {
+    let program_file = ProgramFile::new("loop", "++[>+<-]\n>+.");
+    let program = program_file.path();
+    let messages = debug(&[
+        json!({ "command": "initialize", "arguments": { "adapterID": "bf" } }),
+        json!({ "command": "launch", "arguments": { "program": program } }),
+        json!({
+            "command": "setBreakpoints",
+            "arguments": {
+                "source": { "path": program },
+                "breakpoints": [{ "line": 1, "column": 4 }, { "line": 3 }],
+            },
+        }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "threads" }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "scopes", "arguments": { "frameId": 0 } }),
+        json!({ "command": "variables", "arguments": { "variablesReference": 2 } }),
+        json!({ "command": "stepIn", "arguments": { "threadId": 1 } }),
+        json!({
+            "command": "variables",
+            "arguments": { "variablesReference": 1, "start": 0, "count": 3 },
+        }),
+        json!({
+            "command": "setBreakpoints",
+            "arguments": { "source": { "path": program }, "breakpoints": [] },
+        }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+        json!({ "command": "disconnect" }),
+    ]);
+
+    assert!(messages.iter().all(|message| message["success"] != false));
+    assert_eq!(
+        true,
+        response(&messages, 1)["body"]["supportsConfigurationDoneRequest"]
+    );
+    let breakpoints = &response(&messages, 3)["body"]["breakpoints"];
+    assert_eq!(
+        json!([true, 1, 4]),
+        json!([
+            breakpoints[0]["verified"],
+            breakpoints[0]["line"],
+            breakpoints[0]["column"]
+        ])
+    );
+    assert_eq!(false, breakpoints[1]["verified"]);
+    assert_eq!("main", response(&messages, 5)["body"]["threads"][0]["name"]);
+
+    let frames = &response(&messages, 6)["body"]["stackFrames"];
+    assert_eq!(
+        vec![("loop at line 1, column 3", 1, 4), ("program", 1, 3)],
+        frames
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| (
+                frame["name"].as_str().unwrap(),
+                frame["line"].as_u64().unwrap(),
+                frame["column"].as_u64().unwrap()
+            ))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(program, frames[0]["source"]["path"]);
+    assert_eq!(
+        2,
+        response(&messages, 7)["body"]["scopes"]
+            .as_array()
+            .unwrap()
+            .len()
+    );
+    let current_cell = &response(&messages, 8)["body"]["variables"];
+    assert_eq!(
+        ("0", "2"),
+        (
+            current_cell[0]["value"].as_str().unwrap(),
+            current_cell[1]["value"].as_str().unwrap()
+        )
+    );
+    let tape: Vec<&str> = response(&messages, 10)["body"]["variables"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|variable| variable["value"].as_str().unwrap())
+        .collect();
+    assert_eq!(vec!["2", "0", "0"], tape);
+
+    let stopped: Vec<&str> = events(&messages, "stopped")
+        .iter()
+        .map(|event| event["body"]["reason"].as_str().unwrap())
+        .collect();
+    assert_eq!(vec!["breakpoint", "step"], stopped);
+    assert_eq!("\u{3}", events(&messages, "output")[0]["body"]["output"]);
+    assert_eq!(1, events(&messages, "exited").len());
+    assert_eq!(1, events(&messages, "terminated").len());
+}
+
+#[test]
+fn it_stops_at_a_breakpoint_at_the_first_instruction_and_when_paused() {
+    let program_file = ProgramFile::new("first", "+.");
+    let program = program_file.path();
+    let messages = debug(&[
+        json!({ "command": "initialize", "arguments": { "adapterID": "bf" } }),
+        json!({ "command": "launch", "arguments": { "program": program } }),
+        json!({
+            "command": "setBreakpoints",
+            "arguments": { "source": { "path": program }, "breakpoints": [{ "line": 1 }] },
+        }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "pause", "arguments": { "threadId": 1 } }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+        json!({ "command": "threads" }),
+        json!({ "command": "pause", "arguments": { "threadId": 1 } }),
+    ]);
+    let stopped: Vec<&Value> = events(&messages, "stopped")
+        .iter()
+        .map(|event| &event["body"]["reason"])
+        .collect();
+    assert_eq!(vec!["breakpoint", "pause"], stopped);
+    assert_eq!(
+        1,
+        response(&messages, 5)["body"]["stackFrames"][0]["column"]
+    );
+    assert_eq!(true, response(&messages, 6)["success"]);
+    assert_eq!(1, events(&messages, "terminated").len());
+    assert_eq!(
+        "the program has finished",
+        response(&messages, 9)["message"]
+    );
+}
+
+#[test]
+fn it_sends_initialized_after_initialize_and_pauses_a_running_program() {
+    let program_file = ProgramFile::new("forever", "+[]");
+    let program = program_file.path();
+    let messages = debug(&[
+        json!({ "command": "initialize", "arguments": { "adapterID": "bf" } }),
+        json!({
+            "command": "setBreakpoints",
+            "arguments": { "source": { "path": program }, "breakpoints": [{ "line": 1 }] },
+        }),
+        json!({ "command": "launch", "arguments": { "program": program } }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+        json!({ "command": "pause", "arguments": { "threadId": 1 } }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "disconnect" }),
+    ]);
+
+    assert!(messages.iter().all(|message| message["success"] != false));
+    assert_eq!(1, messages[0]["request_seq"]);
+    assert_eq!("initialized", messages[1]["event"]);
+    let breakpoint = &response(&messages, 2)["body"]["breakpoints"][0];
+    assert_eq!(
+        json!([true, 1]),
+        json!([breakpoint["verified"], breakpoint["column"]])
+    );
+    let stopped: Vec<&Value> = events(&messages, "stopped")
+        .iter()
+        .map(|event| &event["body"]["reason"])
+        .collect();
+    assert_eq!(vec!["breakpoint", "pause"], stopped);
+    assert_eq!(
+        2,
+        response(&messages, 7)["body"]["stackFrames"][0]["column"]
+    );
+    assert!(events(&messages, "terminated").is_empty());
+}
+
+#[test]
+fn it_fails_to_launch_a_missing_program() {
+    let messages = debug(&[
+        json!({ "command": "initialize", "arguments": { "adapterID": "bf" } }),
+        json!({ "command": "launch", "arguments": { "program": "missing.bf" } }),
+        json!({ "command": "threads" }),
+    ]);
+    let launch = response(&messages, 2);
+    assert_eq!(false, launch["success"]);
+    assert_eq!("no such file: 'missing.bf'", launch["message"]);
+    assert_eq!(
+        "no program has been launched",
+        response(&messages, 3)["message"]
+    );
+}
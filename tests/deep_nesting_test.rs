@@ -0,0 +1,62 @@
+use std::io::Cursor;
+
+use vtashkov_bf::{BfError, BoundaryPolicy, Interpreter, Program};
+
+const DEPTH: usize = 100_000;
+
+fn nested_loops(body: &str) -> String {
+    format!("+{}{}{}.", "[".repeat(DEPTH), body, "]".repeat(DEPTH))
+}
+
+#[test]
+fn it_can_execute_deeply_nested_loops() {
+    let source_code = nested_loops(">+<-");
+    let mut input = Cursor::new(vec![]);
+    let mut output = vec![];
+    let mut interpreter = Interpreter::new(&mut input, &mut output, 30000);
+    interpreter.execute(&source_code).unwrap();
+    assert_eq!(vec![0], output);
+}
+
+#[test]
+fn it_can_execute_deeply_nested_loops_without_offset_addressing() {
+    let source_code = nested_loops(">+<-");
+    let mut input = Cursor::new(vec![]);
+    let mut output = vec![];
+    let mut interpreter = Interpreter::new(&mut input, &mut output, 30000)
+        .with_boundary_policy(BoundaryPolicy::Error);
+    interpreter.execute(&source_code).unwrap();
+    assert_eq!(vec![0], output);
+}
+
+#[test]
+fn it_can_parse_deeply_nested_loops_strictly() {
+    let program = Program::parse_strict(&nested_loops("-")).unwrap();
+    assert_eq!(3, program.nodes().len());
+}
+
+#[test]
+fn it_reports_deeply_nested_unbalanced_loops() {
+    let source_code = "[".repeat(DEPTH);
+    let result = Program::parse_strict(&source_code);
+    assert!(
+        matches!(result, Err(BfError::UnmatchedLoopStart(position)) if position.offset == DEPTH - 1)
+    );
+}
+
+#[test]
+fn it_can_clone_and_compare_deeply_nested_loops() {
+    let program = Program::parse(&nested_loops("-"));
+    let clone = program.clone();
+    assert!(program.nodes() == clone.nodes());
+    let other = Program::parse(&nested_loops("+"));
+    assert!(program.nodes() != other.nodes());
+}
+
+#[test]
+fn it_can_format_deeply_nested_loops() {
+    let program = Program::parse(&nested_loops("-"));
+    let formatted = format!("{:?}", program.nodes());
+    assert_eq!(DEPTH, formatted.matches("Loop(").count());
+    assert!(formatted.ends_with("}]"));
+}
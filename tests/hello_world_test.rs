@@ -9,6 +9,6 @@ fn it_can_execute_hello_world() {
     let mut input = Cursor::new(vec![]);
     let mut output = vec![];
     let mut interpreter = Interpreter::new(&mut input, &mut output, 30000);
-    interpreter.execute(&source_code);
+    interpreter.execute(source_code).unwrap();
     assert_eq!("Hello World!\n", str::from_utf8(output.as_slice()).unwrap());
 }